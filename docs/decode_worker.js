@@ -0,0 +1,33 @@
+// The worker downloading and decoding the images for the page. It loads the
+// same module as the page, the messages received in the meantime are kept
+// until the decoder answers them.
+importScripts("./carbaseus.js");
+
+var queued = [];
+self.onmessage = function (event) {
+  queued.push(event);
+};
+
+wasm_bindgen("./carbaseus_bg.wasm")
+  .then(function () {
+    // Replaces `self.onmessage` with the decoder
+    wasm_bindgen.start_decode_worker();
+    queued.forEach(function (event) {
+      self.onmessage(event);
+    });
+    queued = [];
+  })
+  .catch(function (error) {
+    console.error("Failed to start the decoder: " + error);
+
+    // The fetches fail instead of waiting forever
+    function fail(event) {
+      self.postMessage({
+        id: event.data.id,
+        error: "Failed to start the decoder: " + error,
+      });
+    }
+    queued.forEach(fail);
+    queued = [];
+    self.onmessage = fail;
+  });
@@ -4,6 +4,7 @@ var filesToCache = [
   "./index.html",
   "./carbaseus.js",
   "./carbaseus_bg.wasm",
+  "./decode_worker.js",
 ];
 
 /* Start the service worker and cache all of the app's content */
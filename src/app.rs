@@ -1,8 +1,10 @@
 mod components;
+mod history;
 mod layout;
 mod math;
 mod state;
 
+use crate::app::components::graph::batch;
 use crate::app::components::graph::node;
 use crate::app::components::graph::node::*;
 
@@ -66,10 +68,30 @@ impl eframe::App for App {
             .max_width(640.0)
             .show(ctx, |ui| layout::output_pannel::show(state, ui));
 
+        if state.d_inspector {
+            egui::SidePanel::right("inspector_pannel")
+                .min_width(220.0)
+                .max_width(400.0)
+                .show(ctx, |ui| layout::inspector_pannel::show(state, ui));
+        }
+
+        if state.d_viewer {
+            layout::output_pannel::show_window(state, ctx);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| layout::central_pannel::show(state, ui, ctx));
 
+        // The files of a sequence are processed one per frame, so that the
+        // progress can be followed and the batch cancelled
+        if batch::step(state) {
+            ctx.request_repaint();
+        }
+
         if state.first_loop {
             init_nodes(state);
+
+            // The initial nodes are not an edit that can be undone
+            state.history.reset(&state.graph);
         }
 
         state.first_loop = false;
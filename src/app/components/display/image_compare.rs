@@ -0,0 +1,180 @@
+use egui::epaint::ColorImage;
+use egui::{Color32, CursorIcon, Rect, Sense, Stroke};
+use egui_extras::image::RetainedImage;
+
+use super::image_viewer::{self, ImageView};
+
+const SIDE_A_ID: &str = "compare side a";
+const SIDE_B_ID: &str = "compare side b";
+const SPLIT_ID: &str = "compare split";
+const DIFFERENCE_ID: &str = "compare difference";
+
+const HANDLE_WIDTH: f32 = 8.0;
+
+/// How the two compared outputs are displayed
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareMode {
+    SideBySide,
+    /// A on the left of a draggable line, B on its right
+    Split,
+    Difference,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct CompareOptions {
+    pub mode: CompareMode,
+    /// Position of the split line, from 0 on the left to 1 on the right
+    pub split: f32,
+    /// Factor applied to the difference so that small changes are visible
+    pub gain: f32,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::Split,
+            split: 0.5,
+            gain: 1.0,
+        }
+    }
+}
+
+/// An image to display, with its pixels for the close zooms and the readout
+#[derive(Clone, Copy)]
+pub struct Side<'a> {
+    pub image: &'a RetainedImage,
+    pub pixels: &'a ColorImage,
+}
+
+/// Display the two images with the same zoom and position, returns the pixel
+/// under the cursor
+pub fn show(
+    ui: &mut egui::Ui,
+    view: &mut ImageView,
+    options: &mut CompareOptions,
+    a: Side<'_>,
+    b: Side<'_>,
+    difference: Side<'_>,
+    height: f32,
+) -> Option<[usize; 2]> {
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut options.mode, CompareMode::SideBySide, "◫ Side by side");
+        ui.selectable_value(&mut options.mode, CompareMode::Split, "◧ Split");
+        ui.selectable_value(&mut options.mode, CompareMode::Difference, "∆ Difference");
+    });
+
+    if options.mode == CompareMode::Difference {
+        ui.add(
+            egui::Slider::new(&mut options.gain, 1.0..=32.0)
+                .text("Gain")
+                .logarithmic(true),
+        );
+    }
+
+    let columns = if options.mode == CompareMode::SideBySide {
+        2.0
+    } else {
+        1.0
+    };
+    let viewport = egui::vec2(ui.available_width() / columns, height);
+    image_viewer::show_toolbar(ui, view, a.image.size_vec2(), viewport);
+
+    match options.mode {
+        CompareMode::SideBySide => {
+            let mut hovered = None;
+
+            ui.columns(2, |columns| {
+                let hovered_a = show_side(&mut columns[0], SIDE_A_ID, view, a, height);
+                let hovered_b = show_side(&mut columns[1], SIDE_B_ID, view, b, height);
+                hovered = hovered_a.or(hovered_b);
+            });
+
+            hovered
+        }
+        CompareMode::Split => show_split(ui, view, &mut options.split, a, b, height),
+        CompareMode::Difference => show_side(ui, DIFFERENCE_ID, view, difference, height),
+    }
+}
+
+/// Returns true if the pointer was over one of the views during the last frame
+pub fn hovered(ctx: &egui::Context) -> bool {
+    [SIDE_A_ID, SIDE_B_ID, SPLIT_ID, DIFFERENCE_ID]
+        .iter()
+        .any(|id| image_viewer::hovered(ctx, id))
+}
+
+fn show_side(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    view: &mut ImageView,
+    side: Side<'_>,
+    height: f32,
+) -> Option<[usize; 2]> {
+    image_viewer::show(ui, id_source, view, side.image, side.pixels, height)
+}
+
+// Both images in the same view, B is only painted on the right of the line
+fn show_split(
+    ui: &mut egui::Ui,
+    view: &mut ImageView,
+    split: &mut f32,
+    a: Side<'_>,
+    b: Side<'_>,
+    height: f32,
+) -> Option<[usize; 2]> {
+    // The handle is registered before the view, so it takes the drags
+    // starting on it instead of panning the images
+    let area = Rect::from_min_size(ui.cursor().min, egui::vec2(ui.available_width(), height));
+    let line = egui::lerp(area.x_range(), *split);
+    let handle_rect = Rect::from_center_size(
+        egui::pos2(line, area.center().y),
+        egui::vec2(HANDLE_WIDTH, area.height()),
+    );
+    let handle = ui.interact(handle_rect, ui.id().with(SPLIT_ID), Sense::drag());
+
+    if let Some(pointer) = handle.interact_pointer_pos() {
+        *split = ((pointer.x - area.min.x) / area.width()).clamp(0.0, 1.0);
+    }
+    if handle.hovered() || handle.dragged() {
+        ui.output().cursor_icon = CursorIcon::ResizeHorizontal;
+    }
+
+    let (viewport, response) =
+        image_viewer::interact(ui, SPLIT_ID, view, a.image.size_vec2(), height);
+
+    let line = egui::lerp(viewport.x_range(), *split);
+    let mut left = viewport;
+    left.max.x = line;
+    let mut right = viewport;
+    right.min.x = line;
+
+    let painter = ui.painter_at(viewport);
+    painter.rect_filled(viewport, 0.0, ui.visuals().extreme_bg_color);
+
+    let left_painter = painter.with_clip_rect(left);
+    let right_painter = painter.with_clip_rect(right);
+    image_viewer::paint(ui, &left_painter, view, a.image, a.pixels, viewport);
+    image_viewer::paint(ui, &right_painter, view, b.image, b.pixels, viewport);
+
+    painter.vline(line, viewport.y_range(), Stroke::new(2.0, Color32::WHITE));
+    painter.text(
+        left.left_top() + egui::vec2(4.0, 4.0),
+        egui::Align2::LEFT_TOP,
+        "A",
+        egui::TextStyle::Heading.resolve(ui.style()),
+        Color32::WHITE,
+    );
+    painter.text(
+        right.right_top() + egui::vec2(-4.0, 4.0),
+        egui::Align2::RIGHT_TOP,
+        "B",
+        egui::TextStyle::Heading.resolve(ui.style()),
+        Color32::WHITE,
+    );
+
+    let hovered = image_viewer::hovered_pixel(response.hover_pos(), view, a.pixels.size, viewport);
+    image_viewer::outline_pixel(&painter, view, a.pixels.size, viewport, hovered);
+
+    hovered
+}
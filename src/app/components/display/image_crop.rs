@@ -0,0 +1,154 @@
+use egui::{Color32, Pos2, Rect, Sense, Stroke};
+use egui_extras::image::RetainedImage;
+
+const HANDLE_SIZE: f32 = 8.0;
+
+#[derive(Clone, Copy)]
+enum DragMode {
+    Move,
+    Resize,
+    Draw,
+}
+
+#[derive(Clone, Copy)]
+struct DragState {
+    mode: DragMode,
+    start: Pos2,
+    region: [usize; 4],
+}
+
+/// Display the image with an editable crop rectangle on top of it. The region
+/// is `[x, y, width, height]` in pixels, where a null width or height extends
+/// up to the image edge. Returns true once the user releases the rectangle.
+pub fn show(ui: &mut egui::Ui, image: &RetainedImage, region: &mut [usize; 4]) -> bool {
+    let [width, height] = image.size();
+
+    let mut size = image.size_vec2();
+    size *= (ui.available_width() / size.x).min(1.0);
+    let scale = size.x / width as f32;
+
+    let response = ui.add(egui::Image::new(image.texture_id(ui.ctx()), size).sense(Sense::drag()));
+    let bounds = response.rect;
+
+    let to_screen = |[x, y, w, h]: [usize; 4]| {
+        let x = x.min(width.saturating_sub(1));
+        let y = y.min(height.saturating_sub(1));
+        let w = if w == 0 { width - x } else { w.min(width - x) };
+        let h = if h == 0 {
+            height - y
+        } else {
+            h.min(height - y)
+        };
+
+        Rect::from_min_size(
+            bounds.min + egui::vec2(x as f32, y as f32) * scale,
+            egui::vec2(w as f32, h as f32) * scale,
+        )
+    };
+
+    let to_region = |rect: Rect| {
+        let rect = rect.intersect(bounds);
+        let min = (rect.min - bounds.min) / scale;
+        let max = (rect.max - bounds.min) / scale;
+
+        let x = (min.x.round() as usize).min(width.saturating_sub(1));
+        let y = (min.y.round() as usize).min(height.saturating_sub(1));
+
+        [
+            x,
+            y,
+            (max.x.round() as usize).saturating_sub(x).max(1),
+            (max.y.round() as usize).saturating_sub(y).max(1),
+        ]
+    };
+
+    let rect = to_screen(*region);
+    let handle = Rect::from_center_size(rect.max, egui::Vec2::splat(HANDLE_SIZE));
+
+    if response.drag_started() {
+        if let Some(pointer) = response.interact_pointer_pos() {
+            let mode = if handle.expand(HANDLE_SIZE * 0.5).contains(pointer) {
+                DragMode::Resize
+            } else if rect.contains(pointer) {
+                DragMode::Move
+            } else {
+                DragMode::Draw
+            };
+
+            let drag = DragState {
+                mode,
+                start: pointer,
+                region: *region,
+            };
+
+            ui.memory().data.insert_temp(response.id, drag);
+        }
+    }
+
+    if response.dragged() {
+        let drag = ui.memory().data.get_temp::<DragState>(response.id);
+
+        if let (Some(drag), Some(pointer)) = (drag, response.interact_pointer_pos()) {
+            let start_rect = to_screen(drag.region);
+            let delta = pointer - drag.start;
+
+            let new_rect = match drag.mode {
+                DragMode::Move => {
+                    // Keep the rectangle size while sliding it inside the image
+                    let min = start_rect.min + delta;
+                    let min = egui::pos2(
+                        min.x.clamp(bounds.min.x, bounds.max.x - start_rect.width()),
+                        min.y
+                            .clamp(bounds.min.y, bounds.max.y - start_rect.height()),
+                    );
+                    Rect::from_min_size(min, start_rect.size())
+                }
+                DragMode::Resize => Rect::from_two_pos(start_rect.min, start_rect.max + delta),
+                DragMode::Draw => Rect::from_two_pos(drag.start, pointer),
+            };
+
+            *region = to_region(new_rect);
+        }
+    }
+
+    // Darken what is cropped out and outline the kept region
+    let rect = to_screen(*region);
+    let shade = Color32::from_black_alpha(160);
+    let painter = ui.painter_at(bounds);
+
+    painter.rect_filled(
+        Rect::from_min_max(bounds.min, egui::pos2(bounds.max.x, rect.min.y)),
+        0.0,
+        shade,
+    );
+    painter.rect_filled(
+        Rect::from_min_max(egui::pos2(bounds.min.x, rect.max.y), bounds.max),
+        0.0,
+        shade,
+    );
+    painter.rect_filled(
+        Rect::from_min_max(
+            egui::pos2(bounds.min.x, rect.min.y),
+            egui::pos2(rect.min.x, rect.max.y),
+        ),
+        0.0,
+        shade,
+    );
+    painter.rect_filled(
+        Rect::from_min_max(
+            egui::pos2(rect.max.x, rect.min.y),
+            egui::pos2(bounds.max.x, rect.max.y),
+        ),
+        0.0,
+        shade,
+    );
+
+    painter.rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::WHITE));
+    painter.rect_filled(
+        Rect::from_center_size(rect.max, egui::Vec2::splat(HANDLE_SIZE)),
+        0.0,
+        Color32::WHITE,
+    );
+
+    response.drag_released()
+}
@@ -0,0 +1,170 @@
+use egui::plot::{Legend, Line, Plot, VLine, Value, Values};
+use egui::Color32;
+
+use crate::app::math::statistics::{Channel, Statistics};
+
+/// Display options of the histogram, kept between sessions
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HistogramOptions {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub luminance: bool,
+    /// Display the logarithm of the counts, to see the small bins
+    pub log_scale: bool,
+    /// Overlay the cumulative distribution of each channel
+    pub cumulative: bool,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        Self {
+            red: true,
+            green: true,
+            blue: true,
+            luminance: false,
+            log_scale: false,
+            cumulative: false,
+        }
+    }
+}
+
+impl HistogramOptions {
+    fn shown(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Red => self.red,
+            Channel::Green => self.green,
+            Channel::Blue => self.blue,
+            Channel::Luminance => self.luminance,
+            Channel::Alpha => false,
+        }
+    }
+}
+
+fn channel_color(channel: Channel) -> Color32 {
+    match channel {
+        Channel::Red => Color32::RED,
+        Channel::Green => Color32::GREEN,
+        Channel::Blue => Color32::BLUE,
+        _ => Color32::GRAY,
+    }
+}
+
+pub fn show(
+    ui: &mut egui::Ui,
+    statistics: &[(Channel, Statistics)],
+    options: &mut HistogramOptions,
+) {
+    ui.horizontal_wrapped(|ui| {
+        ui.checkbox(&mut options.red, "Red");
+        ui.checkbox(&mut options.green, "Green");
+        ui.checkbox(&mut options.blue, "Blue");
+        ui.checkbox(&mut options.luminance, "Luminance");
+    });
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut options.log_scale, "Log scale");
+        ui.checkbox(&mut options.cumulative, "Cumulative");
+    });
+
+    let shown: Vec<&(Channel, Statistics)> = statistics
+        .iter()
+        .filter(|(channel, _)| options.shown(*channel))
+        .collect();
+
+    let height = |count: usize| {
+        if options.log_scale {
+            (count as f64 + 1.0).log10()
+        } else {
+            count as f64
+        }
+    };
+
+    // The cumulative distributions are scaled to the highest bin
+    let top = shown
+        .iter()
+        .flat_map(|(_, stats)| stats.histogram.iter())
+        .map(|&count| height(count))
+        .fold(1.0, f64::max);
+
+    let hovered = Plot::new("Image histogram")
+        .legend(Legend::default())
+        .height(240.0)
+        .include_x(0.0)
+        .include_x(255.0)
+        .include_y(0.0)
+        .show(ui, |plot_ui| {
+            for (channel, stats) in &shown {
+                let color = channel_color(*channel);
+
+                let bins = stats
+                    .histogram
+                    .iter()
+                    .enumerate()
+                    .map(|(value, &count)| Value::new(value as f64, height(count)));
+
+                plot_ui.line(
+                    Line::new(Values::from_values_iter(bins))
+                        .color(color)
+                        .fill(0.0)
+                        .name(channel.name()),
+                );
+
+                if options.cumulative {
+                    let total = stats.count.max(1) as f64;
+                    let cumulated = stats.histogram.iter().scan(0, |sum, &count| {
+                        *sum += count;
+                        Some(*sum)
+                    });
+
+                    let distribution = cumulated
+                        .enumerate()
+                        .map(|(value, sum)| Value::new(value as f64, sum as f64 / total * top));
+
+                    plot_ui.line(
+                        Line::new(Values::from_values_iter(distribution))
+                            .color(color)
+                            .width(1.0)
+                            .name(format!("{} cumulative", channel.name())),
+                    );
+                }
+            }
+
+            // Intensity under the cursor
+            let hovered = plot_ui
+                .pointer_coordinate()
+                .filter(|_| plot_ui.plot_hovered())
+                .map(|pointer| pointer.x.round().clamp(0.0, 255.0) as usize);
+
+            if let Some(value) = hovered {
+                plot_ui.vline(VLine::new(value as f64).color(Color32::GRAY));
+            }
+
+            hovered
+        })
+        .inner;
+
+    match hovered {
+        Some(value) => {
+            ui.label(format!("Intensity {}", value));
+
+            for (channel, stats) in &shown {
+                let below: usize = stats.histogram[..=value].iter().sum();
+                let percent = 100.0 * below as f32 / stats.count.max(1) as f32;
+
+                ui.colored_label(
+                    channel_color(*channel),
+                    format!(
+                        "{}: {} px, {:.1} % at or below",
+                        channel.name(),
+                        stats.histogram[value],
+                        percent
+                    ),
+                );
+            }
+        }
+        None => {
+            ui.weak("Hover the histogram to read a bin");
+        }
+    }
+}
@@ -2,7 +2,13 @@ use egui::epaint::ColorImage;
 use egui_extras::{image::RetainedImage, Size, TableBuilder};
 use std::mem::size_of;
 
-pub fn show(ui: &mut egui::Ui, image: &ColorImage, retained: &RetainedImage) {
+pub fn show(
+    ui: &mut egui::Ui,
+    image: &ColorImage,
+    retained: &RetainedImage,
+    format: Option<&'static str>,
+    float: bool,
+) {
     let full_size = image.size[0] * image.size[1];
     let pixel_weight = 3 * size_of::<usize>();
     let full_weight = full_size * pixel_weight;
@@ -21,6 +27,17 @@ pub fn show(ui: &mut egui::Ui, image: &ColorImage, retained: &RetainedImage) {
                 });
             });
 
+            if let Some(format) = format {
+                body.row(row_height, |mut row| {
+                    row.col(|ui| {
+                        ui.label("Format".to_string());
+                    });
+                    row.col(|ui| {
+                        ui.label(format.to_string());
+                    });
+                });
+            }
+
             body.row(row_height, |mut row| {
                 row.col(|ui| {
                     ui.label("Size".to_string());
@@ -47,5 +64,19 @@ pub fn show(ui: &mut egui::Ui, image: &ColorImage, retained: &RetainedImage) {
                     ));
                 });
             });
+
+            body.row(row_height, |mut row| {
+                row.col(|ui| {
+                    ui.label("Depth".to_string());
+                });
+                row.col(|ui| {
+                    // The float images are tone mapped to 8 bits to be displayed
+                    ui.label(if float {
+                        "32 bits float / channel"
+                    } else {
+                        "8 bits / channel"
+                    });
+                });
+            });
         });
 }
@@ -0,0 +1,48 @@
+use egui_extras::{Size, TableBuilder};
+
+use crate::app::math::statistics::{Channel, Statistics};
+
+// Name of a statistic and how it is displayed
+type Row = (&'static str, fn(&Statistics) -> String);
+
+pub fn show(ui: &mut egui::Ui, statistics: &[(Channel, Statistics)]) {
+    let row_height = 14.0;
+
+    let rows: [Row; 8] = [
+        ("Mean", |s| format!("{:.1}", s.mean)),
+        ("Std dev", |s| format!("{:.1}", s.std_dev)),
+        ("Min", |s| s.min.to_string()),
+        ("Max", |s| s.max.to_string()),
+        ("5 %", |s| s.percentile(5.0).to_string()),
+        ("Median", |s| s.percentile(50.0).to_string()),
+        ("95 %", |s| s.percentile(95.0).to_string()),
+        ("Entropy", |s| format!("{:.2} bits", s.entropy)),
+    ];
+
+    TableBuilder::new(ui)
+        .striped(true)
+        .column(Size::initial(60.0).at_least(40.0))
+        .columns(Size::remainder().at_least(40.0), statistics.len())
+        .header(row_height, |mut header| {
+            header.col(|_| {});
+            for (channel, _) in statistics {
+                header.col(|ui| {
+                    ui.strong(channel.name());
+                });
+            }
+        })
+        .body(|mut body| {
+            for (name, value) in rows {
+                body.row(row_height, |mut row| {
+                    row.col(|ui| {
+                        ui.label(name);
+                    });
+                    for (_, channel) in statistics {
+                        row.col(|ui| {
+                            ui.label(value(channel));
+                        });
+                    }
+                });
+            }
+        });
+}
@@ -0,0 +1,305 @@
+use egui::epaint::{ColorImage, Mesh};
+use egui::{Color32, Pos2, Rect, Sense, Stroke, Vec2};
+use egui_extras::image::RetainedImage;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 64.0;
+const ZOOM_STEP: f32 = 1.25;
+
+/// Above this zoom the pixels are drawn as squares instead of being
+/// interpolated, so that they can be told apart
+const NEAREST_ZOOM: f32 = 3.0;
+
+/// Zoom and position of an image in a viewer. Viewers sharing the same view
+/// display their images at the same place.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageView {
+    /// Screen points per pixel of the image, `None` fits the image in the view
+    pub zoom: Option<f32>,
+    /// Position in the image, in pixels, displayed at the center of the view
+    pub center: Pos2,
+}
+
+impl Default for ImageView {
+    fn default() -> Self {
+        Self {
+            zoom: None,
+            center: Pos2::ZERO,
+        }
+    }
+}
+
+impl ImageView {
+    /// Screen points per pixel of an image displayed in the viewport
+    pub fn scale(&self, image_size: Vec2, viewport: Vec2) -> f32 {
+        self.zoom
+            .unwrap_or_else(|| (viewport.x / image_size.x).min(viewport.y / image_size.y))
+    }
+
+    /// Screen rectangle covered by the whole image
+    pub fn image_rect(&self, image_size: Vec2, viewport: Rect) -> Rect {
+        let scale = self.scale(image_size, viewport.size());
+        let center = match self.zoom {
+            Some(_) => self.center,
+            None => (image_size / 2.0).to_pos2(),
+        };
+
+        Rect::from_min_size(
+            viewport.center() - center.to_vec2() * scale,
+            image_size * scale,
+        )
+    }
+
+    /// Change the zoom while keeping the pixel under the anchor in place
+    pub fn zoom_at(&mut self, image_size: Vec2, viewport: Rect, factor: f32, anchor: Pos2) {
+        let rect = self.image_rect(image_size, viewport);
+        let scale = self.scale(image_size, viewport.size());
+        let zoom = (scale * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let anchored = ((anchor - rect.min) / scale).to_pos2();
+        self.center = anchored - (anchor - viewport.center()) / zoom;
+        self.zoom = Some(zoom);
+    }
+
+    /// Move the image by a distance in screen points
+    pub fn pan(&mut self, image_size: Vec2, viewport: Rect, delta: Vec2) {
+        let rect = self.image_rect(image_size, viewport);
+        let scale = self.scale(image_size, viewport.size());
+
+        self.center = ((viewport.center() - rect.min - delta) / scale).to_pos2();
+        self.zoom = Some(scale);
+    }
+}
+
+/// Display the image in a view of the given height. Returns the pixel under
+/// the cursor.
+pub fn show(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    view: &mut ImageView,
+    image: &RetainedImage,
+    pixels: &ColorImage,
+    height: f32,
+) -> Option<[usize; 2]> {
+    let (viewport, response) = interact(ui, id_source, view, image.size_vec2(), height);
+
+    let painter = ui.painter_at(viewport);
+    painter.rect_filled(viewport, 0.0, ui.visuals().extreme_bg_color);
+    paint(ui, &painter, view, image, pixels, viewport);
+
+    let hovered = hovered_pixel(response.hover_pos(), view, pixels.size, viewport);
+    outline_pixel(&painter, view, pixels.size, viewport, hovered);
+
+    hovered
+}
+
+/// Allocate a view of the given height for an image. The mouse wheel zooms
+/// around the cursor, dragging pans and a double click fits the image back in
+/// the view.
+pub fn interact(
+    ui: &mut egui::Ui,
+    id_source: impl std::hash::Hash,
+    view: &mut ImageView,
+    image_size: Vec2,
+    height: f32,
+) -> (Rect, egui::Response) {
+    let viewport_size = egui::vec2(ui.available_width(), height.max(32.0));
+    let (viewport, response) = ui.allocate_exact_size(viewport_size, Sense::click_and_drag());
+
+    // Remember if the viewer is hovered, so that the scroll area around it
+    // lets the mouse wheel zoom the image
+    ui.data()
+        .insert_temp(egui::Id::new(&id_source), response.hovered());
+
+    if response.double_clicked() {
+        view.zoom = None;
+    } else if response.dragged() {
+        view.pan(image_size, viewport, response.drag_delta());
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let (zoom, scroll) = {
+            let input = ui.input();
+            (input.zoom_delta(), input.scroll_delta.y)
+        };
+        let factor = zoom * (scroll / 200.0).exp();
+
+        if factor != 1.0 {
+            view.zoom_at(image_size, viewport, factor, pointer);
+        }
+    }
+
+    // Keep part of the image in the view
+    if view.zoom.is_some() {
+        view.center = view.center.clamp(Pos2::ZERO, image_size.to_pos2());
+    }
+
+    (viewport, response)
+}
+
+/// Paint the image where the view places it in the viewport, only the part
+/// inside the clip rectangle of the painter is drawn
+pub fn paint(
+    ui: &egui::Ui,
+    painter: &egui::Painter,
+    view: &ImageView,
+    image: &RetainedImage,
+    pixels: &ColorImage,
+    viewport: Rect,
+) {
+    let image_size = image.size_vec2();
+    let rect = view.image_rect(image_size, viewport);
+    let scale = view.scale(image_size, viewport.size());
+
+    if scale >= NEAREST_ZOOM {
+        paint_pixels(painter, pixels, rect, scale);
+    } else {
+        let uv = Rect::from_min_max(Pos2::ZERO, egui::pos2(1.0, 1.0));
+        let mut mesh = Mesh::with_texture(image.texture_id(ui.ctx()));
+        mesh.add_rect_with_uv(rect, uv, Color32::WHITE);
+        painter.add(mesh);
+    }
+}
+
+/// Returns the pixel of an image of the given size under the pointer
+pub fn hovered_pixel(
+    pointer: Option<Pos2>,
+    view: &ImageView,
+    size: [usize; 2],
+    viewport: Rect,
+) -> Option<[usize; 2]> {
+    let image_size = egui::vec2(size[0] as f32, size[1] as f32);
+    let rect = view.image_rect(image_size, viewport);
+    let scale = view.scale(image_size, viewport.size());
+
+    pointer
+        .map(|pointer| (pointer - rect.min) / scale)
+        .filter(|position| position.x >= 0.0 && position.y >= 0.0)
+        .map(|position| [position.x as usize, position.y as usize])
+        .filter(|&[x, y]| x < size[0] && y < size[1])
+}
+
+/// Outline the hovered pixel when it is large enough to be seen
+pub fn outline_pixel(
+    painter: &egui::Painter,
+    view: &ImageView,
+    size: [usize; 2],
+    viewport: Rect,
+    pixel: Option<[usize; 2]>,
+) {
+    let image_size = egui::vec2(size[0] as f32, size[1] as f32);
+    let rect = view.image_rect(image_size, viewport);
+    let scale = view.scale(image_size, viewport.size());
+
+    if let Some([x, y]) = pixel {
+        if scale >= NEAREST_ZOOM {
+            let min = rect.min + egui::vec2(x as f32, y as f32) * scale;
+            let outline = Rect::from_min_size(min, Vec2::splat(scale));
+            painter.rect_stroke(outline, 0.0, Stroke::new(1.0, Color32::WHITE));
+        }
+    }
+}
+
+/// Returns true if the pointer was over the viewer during the last frame
+pub fn hovered(ctx: &egui::Context, id_source: impl std::hash::Hash) -> bool {
+    ctx.data()
+        .get_temp(egui::Id::new(id_source))
+        .unwrap_or(false)
+}
+
+/// Display the coordinates and the value of the hovered pixel. Slices are
+/// displayed as images, their value is the brightest channel.
+pub fn show_readout(
+    ui: &mut egui::Ui,
+    pixels: &ColorImage,
+    pixel: Option<[usize; 2]>,
+    slice: bool,
+) {
+    ui.horizontal(|ui| match pixel {
+        Some([x, y]) => {
+            let color = pixels.pixels[y * pixels.size[0] + x];
+
+            egui::widgets::color_picker::show_color(ui, color, egui::vec2(12.0, 12.0));
+            ui.monospace(format!("x {:>4}  y {:>4}", x, y));
+
+            if slice {
+                ui.monospace(format!(
+                    "value {:>3}",
+                    color.r().max(color.g()).max(color.b())
+                ));
+            } else {
+                ui.monospace(format!(
+                    "R {:>3}  G {:>3}  B {:>3}  A {:>3}",
+                    color.r(),
+                    color.g(),
+                    color.b(),
+                    color.a()
+                ));
+            }
+        }
+        None => {
+            ui.weak("Hover the image to inspect its pixels");
+        }
+    });
+}
+
+/// Display the zoom controls of a view, for an image displayed in a viewport
+/// of the given size
+pub fn show_toolbar(ui: &mut egui::Ui, view: &mut ImageView, image_size: Vec2, viewport: Vec2) {
+    ui.horizontal(|ui| {
+        if ui
+            .selectable_label(view.zoom.is_none(), "⛶ Fit")
+            .on_hover_text("Fit the image in the view (double click)")
+            .clicked()
+        {
+            view.zoom = None;
+        }
+
+        if ui
+            .selectable_label(view.zoom == Some(1.0), "1:1")
+            .on_hover_text("Display one pixel of the image per point")
+            .clicked()
+        {
+            set_zoom(view, image_size, 1.0);
+        }
+
+        let scale = view.scale(image_size, viewport);
+
+        if ui.small_button("➖").clicked() {
+            set_zoom(view, image_size, (scale / ZOOM_STEP).max(MIN_ZOOM));
+        }
+        if ui.small_button("➕").clicked() {
+            set_zoom(view, image_size, (scale * ZOOM_STEP).min(MAX_ZOOM));
+        }
+
+        ui.label(format!("{:.0} %", scale * 100.0));
+    });
+}
+
+// Zoom on the center of the view
+fn set_zoom(view: &mut ImageView, image_size: Vec2, zoom: f32) {
+    if view.zoom.is_none() {
+        view.center = (image_size / 2.0).to_pos2();
+    }
+    view.zoom = Some(zoom);
+}
+
+// Draw the visible pixels as squares, egui textures are always interpolated
+fn paint_pixels(painter: &egui::Painter, pixels: &ColorImage, rect: Rect, scale: f32) {
+    let [width, height] = pixels.size;
+    let clip = painter.clip_rect();
+    let first = ((clip.min - rect.min) / scale).max(Vec2::ZERO);
+    let last = ((clip.max - rect.min) / scale).ceil();
+
+    let mut mesh = Mesh::default();
+
+    for y in first.y as usize..(last.y.max(0.0) as usize).min(height) {
+        for x in first.x as usize..(last.x.max(0.0) as usize).min(width) {
+            let min = rect.min + egui::vec2(x as f32, y as f32) * scale;
+            let square = Rect::from_min_size(min, Vec2::splat(scale));
+            mesh.add_colored_rect(square, pixels.pixels[y * width + x]);
+        }
+    }
+
+    painter.add(mesh);
+}
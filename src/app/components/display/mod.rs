@@ -1,3 +1,8 @@
-pub mod image_frame;
+pub mod image_compare;
+pub mod image_crop;
+pub mod image_histogram;
 pub mod image_infos;
-pub mod image_spectrum;
+pub mod image_statistics;
+pub mod image_viewer;
+pub mod node_profiler;
+pub mod thumbnail;
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use egui::Color32;
+
+use crate::app::components::graph::node::ProcessGraph;
+use crate::app::components::graph::profiler::{NodeProfile, Profiler};
+
+const KILOBYTE: f64 = 1024.0;
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+
+/// Column the profiler table is sorted by
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfilerColumn {
+    Node,
+    Time,
+    Memory,
+    Evaluations,
+    CacheHits,
+}
+
+impl ProfilerColumn {
+    fn label(&self) -> &'static str {
+        match self {
+            ProfilerColumn::Node => "Node",
+            ProfilerColumn::Time => "Time",
+            ProfilerColumn::Memory => "Memory",
+            ProfilerColumn::Evaluations => "Computed",
+            ProfilerColumn::CacheHits => "Cache hits",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProfilerOptions {
+    pub column: ProfilerColumn,
+    pub descending: bool,
+    /// Display the time of the nodes above their title bar
+    pub badges: bool,
+}
+
+impl Default for ProfilerOptions {
+    fn default() -> Self {
+        Self {
+            column: ProfilerColumn::Time,
+            descending: true,
+            badges: true,
+        }
+    }
+}
+
+/// Display the cost of each node of the last evaluation, sorted by the column
+/// selected in the header
+pub fn show(
+    ui: &mut egui::Ui,
+    graph: &ProcessGraph,
+    profiler: &Profiler,
+    options: &mut ProfilerOptions,
+) {
+    ui.checkbox(&mut options.badges, "Show the timings on the nodes");
+
+    let mut rows: Vec<(&str, &NodeProfile)> = profiler
+        .nodes
+        .iter()
+        .filter_map(|(id, profile)| Some((graph.nodes.get(*id)?.label.as_str(), profile)))
+        .collect();
+
+    if rows.is_empty() {
+        ui.label("Evaluate the graph to profile its nodes");
+        return;
+    }
+
+    rows.sort_by(|(a_label, a), (b_label, b)| {
+        let ordering = match options.column {
+            ProfilerColumn::Node => a_label.cmp(b_label),
+            ProfilerColumn::Time => a.time.cmp(&b.time),
+            ProfilerColumn::Memory => a.bytes.cmp(&b.bytes),
+            ProfilerColumn::Evaluations => a.evaluations.cmp(&b.evaluations),
+            ProfilerColumn::CacheHits => a.cache_hits.cmp(&b.cache_hits),
+        };
+
+        if options.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    let total = profiler.total();
+    ui.label(format!("Total {}", format_duration(total)));
+
+    egui::Grid::new("profiler table")
+        .striped(true)
+        .num_columns(5)
+        .show(ui, |ui| {
+            for column in [
+                ProfilerColumn::Node,
+                ProfilerColumn::Time,
+                ProfilerColumn::Memory,
+                ProfilerColumn::Evaluations,
+                ProfilerColumn::CacheHits,
+            ] {
+                let label = match (options.column == column, options.descending) {
+                    (true, true) => format!("{} ⏷", column.label()),
+                    (true, false) => format!("{} ⏶", column.label()),
+                    (false, _) => column.label().to_string(),
+                };
+
+                if ui
+                    .selectable_label(options.column == column, label)
+                    .clicked()
+                {
+                    if options.column == column {
+                        options.descending = !options.descending;
+                    } else {
+                        options.column = column;
+                        options.descending = column != ProfilerColumn::Node;
+                    }
+                }
+            }
+            ui.end_row();
+
+            for (label, profile) in rows {
+                ui.label(label);
+                ui.colored_label(
+                    time_color(profile.time, total),
+                    format_duration(profile.time),
+                );
+                ui.label(format_bytes(profile.bytes));
+                ui.label(profile.evaluations.to_string());
+                ui.label(profile.cache_hits.to_string());
+                ui.end_row();
+            }
+        });
+}
+
+/// Color of a node timing, from green to red as it takes more of the total time
+pub fn time_color(time: Duration, total: Duration) -> Color32 {
+    let share = if total.is_zero() {
+        0.0
+    } else {
+        (time.as_secs_f32() / total.as_secs_f32()).clamp(0.0, 1.0)
+    };
+
+    let red = (share * 2.0).min(1.0);
+    let green = ((1.0 - share) * 2.0).min(1.0);
+
+    Color32::from_rgb((80.0 + 175.0 * red) as u8, (80.0 + 140.0 * green) as u8, 80)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+
+    if seconds >= 1.0 {
+        format!("{:.2} s", seconds)
+    } else if seconds >= 1e-3 {
+        format!("{:.1} ms", seconds * 1e3)
+    } else {
+        format!("{:.0} µs", seconds * 1e6)
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    let bytes = bytes as f64;
+
+    if bytes >= MEGABYTE {
+        format!("{:.1} MB", bytes / MEGABYTE)
+    } else if bytes >= KILOBYTE {
+        format!("{:.1} KB", bytes / KILOBYTE)
+    } else if bytes > 0.0 {
+        format!("{} B", bytes)
+    } else {
+        "-".to_string()
+    }
+}
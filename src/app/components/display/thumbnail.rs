@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+
+use egui::{ColorImage, TextureHandle, TextureId};
+
+use crate::app::math::transform::thumbnail_image;
+
+/// Largest side of the thumbnails, in pixels
+pub const THUMBNAIL_SIZE: usize = 192;
+
+/// A downscaled copy of an image, to preview it inside the nodes. The texture
+/// is only uploaded while the thumbnail is displayed, and can be released
+/// when it is hidden.
+pub struct Thumbnail {
+    name: String,
+    image: ColorImage,
+    /// Size of the image the thumbnail was made from
+    source_size: [usize; 2],
+    texture: Mutex<Option<TextureHandle>>,
+}
+
+impl Thumbnail {
+    pub fn new(name: impl Into<String>, image: &ColorImage) -> Self {
+        Self {
+            name: name.into(),
+            image: thumbnail_image(image, THUMBNAIL_SIZE),
+            source_size: image.size,
+            texture: Mutex::new(None),
+        }
+    }
+
+    pub fn size_vec2(&self) -> egui::Vec2 {
+        egui::vec2(self.image.size[0] as f32, self.image.size[1] as f32)
+    }
+
+    /// Returns the texture of the thumbnail, uploading it if needed
+    pub fn texture_id(&self, ctx: &egui::Context) -> TextureId {
+        self.texture
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| ctx.load_texture(&self.name, self.image.clone()))
+            .id()
+    }
+
+    /// Free the texture, it is uploaded again when the thumbnail is displayed
+    pub fn release(&self) {
+        self.texture.lock().unwrap().take();
+    }
+
+    /// Display the thumbnail, scaled down to the available width
+    pub fn show(&self, ui: &mut egui::Ui) -> egui::Response {
+        let mut size = self.size_vec2();
+        size *= (ui.available_width() / size.x).min(1.0);
+
+        let [width, height] = self.source_size;
+        ui.image(self.texture_id(ui.ctx()), size)
+            .on_hover_text(format!("{} x {}", width, height))
+    }
+}
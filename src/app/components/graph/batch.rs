@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::app::components::graph::node::{self, NodeId, ValueType};
+use crate::app::math::animation::{self, GifWriter};
+use crate::app::math::image;
+use crate::app::state::{self, SelectedNode};
+
+/// Progress of the evaluation of the graph for every frame of a sequence or
+/// an animation, one frame is processed on each frame of the interface
+pub struct Batch {
+    pub node_id: NodeId,
+    /// Next frame to process
+    pub frame: usize,
+    pub count: usize,
+    /// Number of images written
+    pub written: usize,
+    pub errors: Vec<String>,
+    pub done: bool,
+
+    // Frame displayed before the batch started, restored once it is done
+    restore: usize,
+
+    // Animated GIF written by each save node, with its path
+    gifs: HashMap<NodeId, (PathBuf, GifWriter)>,
+}
+
+impl Batch {
+    pub fn progress(&self) -> f32 {
+        if self.count == 0 {
+            1.0
+        } else {
+            self.frame as f32 / self.count as f32
+        }
+    }
+}
+
+/// Start processing every frame of a sequence or an animation node
+pub fn start(state: &mut state::AppState, node_id: NodeId) {
+    let targets = node::save_targets(&state.graph.graph);
+
+    let (count, restore, animation) = match node::frames_mut(&mut state.graph.graph, node_id) {
+        Some(ValueType::ImageSequence { value }) => (value.files.len(), value.frame, false),
+        Some(ValueType::Animation { value }) => {
+            // The frames are chosen by the batch until it is done
+            value.playing = false;
+            (value.frames.len(), value.frame, true)
+        }
+        _ => return,
+    };
+
+    // Each frame would overwrite the file written for the previous one
+    let overwritten: Vec<String> = targets
+        .iter()
+        .filter(|(_, _, template)| !changes_with_frame(template, animation))
+        .map(|(save_node, _, template)| {
+            format!(
+                "{}: add {{frame}} to {} to write a file per frame",
+                state.graph.graph[*save_node].label, template
+            )
+        })
+        .collect();
+
+    let mut batch = Batch {
+        node_id,
+        frame: 0,
+        count,
+        written: 0,
+        errors: vec![],
+        done: false,
+        restore,
+        gifs: HashMap::new(),
+    };
+
+    if count == 0 {
+        batch
+            .errors
+            .push("There is no frame to process".to_string());
+        batch.done = true;
+    } else if targets.is_empty() {
+        batch
+            .errors
+            .push("Add a save image node to write the results".to_string());
+        batch.done = true;
+    } else if !overwritten.is_empty() {
+        batch.errors.extend(overwritten);
+        batch.done = true;
+    }
+
+    state.batch = Some(batch);
+}
+
+/// Stop the batch after the frame being processed
+pub fn cancel(state: &mut state::AppState) {
+    if let Some(batch) = &mut state.batch {
+        batch.count = batch.frame;
+    }
+}
+
+/// Process the next frame of the running batch, returns true while there are
+/// frames left
+pub fn step(state: &mut state::AppState) -> bool {
+    let batch = match &mut state.batch {
+        Some(batch) if !batch.done => batch,
+        _ => return false,
+    };
+
+    if batch.frame >= batch.count {
+        finish(state);
+        return false;
+    }
+
+    let frame = batch.frame;
+    batch.frame += 1;
+
+    let loaded = match node::frames_mut(&mut state.graph.graph, batch.node_id) {
+        Some(value) => set_frame(value, frame),
+        None => {
+            batch
+                .errors
+                .push("The processed node was removed".to_string());
+            batch.count = frame;
+            return true;
+        }
+    };
+
+    let (file, delay) = match loaded {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            batch.errors.push(error);
+            return true;
+        }
+    };
+
+    node::evaluate_graph(&mut state.graph);
+
+    for (save_node, output_id, template) in node::save_targets(&state.graph.graph) {
+        let label = state.graph.graph[save_node].label.clone();
+
+        if let Some(error) = state.graph.user_state.node_errors.get(&save_node) {
+            let error = format!("{} of {}: {}", label, file.display(), error);
+            batch.errors.push(error);
+            continue;
+        }
+
+        let result = node::output_value(&mut state.graph, output_id)
+            .ok_or_else(|| "The image was not computed".to_string())
+            .and_then(|value| {
+                let path = output_path(&template, &file, frame)?;
+
+                if is_gif(&path) {
+                    let image = value.try_to_image().map_err(|err| err.to_string())?;
+                    write_gif_frame(&mut batch.gifs, save_node, path, &image, delay)
+                } else {
+                    save(&value, &path)
+                }
+            });
+
+        match result {
+            Ok(()) => batch.written += 1,
+            Err(error) => {
+                let error = format!("{} of {}: {}", label, file.display(), error);
+                batch.errors.push(error);
+            }
+        }
+    }
+
+    true
+}
+
+// Close the animated GIFs and display the frame shown before the batch
+fn finish(state: &mut state::AppState) {
+    let (node_id, restore) = match &mut state.batch {
+        Some(batch) => {
+            batch.done = true;
+            batch.gifs.clear();
+            (batch.node_id, batch.restore)
+        }
+        None => return,
+    };
+
+    if let Some(value) = node::frames_mut(&mut state.graph.graph, node_id) {
+        set_frame(value, restore).ok();
+    }
+
+    node::evaluate_graph(&mut state.graph);
+
+    let selected = state.selected_node.node_id;
+    state.selected_node = SelectedNode::default(); // reset node
+    state.selected_node.node_id = selected; // trigger update
+}
+
+// Display a frame, returns the file it comes from and how long it lasts
+fn set_frame(value: &mut ValueType, frame: usize) -> Result<(PathBuf, f32), String> {
+    match value {
+        ValueType::ImageSequence { value } => {
+            value.set_frame(frame)?;
+            Ok((value.files[frame].clone(), animation::DEFAULT_DELAY))
+        }
+        ValueType::Animation { value } => {
+            let delay = match value.frames.get(frame) {
+                Some(frame) => frame.delay,
+                None => return Err(format!("There is no frame {}", frame + 1)),
+            };
+
+            value.frame = frame;
+            Ok((PathBuf::from(&value.path), delay))
+        }
+        _ => Err("The node has no frames".to_string()),
+    }
+}
+
+// The frames of an animation all come from the same file, only `{frame}`
+// gives them different paths. The frames written to a GIF are gathered.
+fn changes_with_frame(template: &str, animation: bool) -> bool {
+    let per_file = !animation && (template.contains("{name}") || template.contains("{stem}"));
+
+    per_file || template.contains("{frame}") || is_gif(Path::new(template))
+}
+
+fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("gif"))
+}
+
+// The frames written to the same path are gathered in one animated GIF
+fn write_gif_frame(
+    gifs: &mut HashMap<NodeId, (PathBuf, GifWriter)>,
+    save_node: NodeId,
+    path: PathBuf,
+    image: &egui::ColorImage,
+    delay: f32,
+) -> Result<(), String> {
+    let writing = gifs
+        .get(&save_node)
+        .map_or(false, |(current, _)| *current == path);
+
+    if !writing {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .map_err(|err| format!("Failed to create {}: {}", directory.display(), err))?;
+        }
+
+        let writer = GifWriter::create(&path)?;
+        gifs.insert(save_node, (path, writer));
+    }
+
+    match gifs.get_mut(&save_node) {
+        Some((_, writer)) => writer.add(image, delay),
+        None => Ok(()),
+    }
+}
+
+/// Path of the file written for a frame, the template is relative to the
+/// directory of the processed file and may contain:
+/// - `{name}` the name of the processed file
+/// - `{stem}` its name without the extension
+/// - `{frame}` the index of the frame, on 4 digits
+pub fn output_path(template: &str, file: &Path, frame: usize) -> Result<PathBuf, String> {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+
+    let relative = template
+        .replace("{name}", &name)
+        .replace("{stem}", &stem)
+        .replace("{frame}", &format!("{:04}", frame));
+
+    let path = match file.parent() {
+        Some(directory) => directory.join(relative),
+        None => PathBuf::from(relative),
+    };
+
+    if canonical(&path) == canonical(file) {
+        return Err("The processed file would be overwritten".to_string());
+    }
+
+    Ok(path)
+}
+
+// Absolute path without `.`, `..` or links, to compare the different ways of
+// writing the same path. The output file or its directory may not exist yet,
+// then the parent directory is resolved and the name appended to it.
+fn canonical(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    if let Ok(path) = normalized.canonicalize() {
+        return path;
+    }
+
+    match (normalized.parent(), normalized.file_name()) {
+        (Some(directory), Some(name)) => {
+            let directory = if directory.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                directory
+            };
+            directory
+                .canonicalize()
+                .map_or(normalized.clone(), |directory| directory.join(name))
+        }
+        _ => normalized,
+    }
+}
+
+// The float images keep their precision in the formats that support it
+fn save(value: &ValueType, path: &Path) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .map_err(|err| format!("Failed to create {}: {}", directory.display(), err))?;
+    }
+
+    match value {
+        ValueType::FloatImage { value, .. } => image::save_float_image(value, path),
+        _ => {
+            let image = value.try_to_image().map_err(|err| err.to_string())?;
+            image::save_image(&image, path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation_templates_need_the_frame() {
+        assert!(!changes_with_frame("processed/{stem}.png", true));
+        assert!(changes_with_frame("processed/{stem}_{frame}.png", true));
+        assert!(changes_with_frame("processed/{stem}.gif", true));
+    }
+
+    #[test]
+    fn sequence_templates_need_the_file_or_the_frame() {
+        assert!(changes_with_frame("processed/{stem}.png", false));
+        assert!(changes_with_frame("processed/{name}", false));
+        assert!(changes_with_frame("processed/{frame}.png", false));
+        assert!(changes_with_frame("processed/all.GIF", false));
+        assert!(!changes_with_frame("processed/result.png", false));
+    }
+
+    #[test]
+    fn output_path_replaces_the_placeholders() {
+        let file = Path::new("photos/beach.jpg");
+
+        assert_eq!(
+            output_path("processed/{stem}_{frame}.png", file, 12),
+            Ok(PathBuf::from("photos/processed/beach_0012.png"))
+        );
+        assert_eq!(
+            output_path("{name}.png", file, 0),
+            Ok(PathBuf::from("photos/beach.jpg.png"))
+        );
+        assert_eq!(
+            output_path("{frame}.png", Path::new("clip.gif"), 3),
+            Ok(PathBuf::from("0003.png"))
+        );
+    }
+
+    #[test]
+    fn output_path_does_not_overwrite_the_processed_file() {
+        let file = Path::new("photos/beach.jpg");
+
+        assert!(output_path("{name}", file, 0).is_err());
+        assert!(output_path("{stem}.jpg", file, 0).is_err());
+        assert!(output_path("{stem}.png", file, 0).is_ok());
+    }
+
+    #[test]
+    fn output_path_resolves_the_other_spellings_of_the_processed_file() {
+        let directory = std::env::temp_dir()
+            .join("carbaseus-tests")
+            .join(format!("batch-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("beach.jpg");
+        std::fs::write(&file, []).unwrap();
+
+        assert!(output_path("./{name}", &file, 0).is_err());
+        assert!(output_path("processed/../{name}", &file, 0).is_err());
+        assert!(output_path("processed/{name}", &file, 0).is_ok());
+
+        let parent = directory.file_name().unwrap().to_string_lossy();
+        assert!(output_path(&format!("../{}/{{name}}", parent), &file, 0).is_err());
+
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
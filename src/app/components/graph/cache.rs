@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use egui_node_graph::OutputId;
+
+use crate::app::components::graph::node::ValueType;
+
+/// Memory the images of the cache can take before the least recently used
+/// ones are evicted, in bytes
+pub const DEFAULT_BUDGET: usize = 512 * 1024 * 1024;
+
+struct Entry {
+    value: ValueType,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Values computed for the outputs of the nodes. The images and slices are
+/// shared with the nodes reading them instead of being copied. When they take
+/// more memory than the budget, the least recently used ones are evicted and
+/// computed again when they are needed.
+pub struct OutputsCache {
+    entries: HashMap<OutputId, Entry>,
+    clock: u64,
+    memory: usize,
+    pub budget: usize,
+    /// Number of values evicted since the cache was created
+    pub evictions: usize,
+}
+
+impl Default for OutputsCache {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            clock: 0,
+            memory: 0,
+            budget: DEFAULT_BUDGET,
+            evictions: 0,
+        }
+    }
+}
+
+impl OutputsCache {
+    /// Returns a value without marking it as used
+    pub fn get(&self, output_id: &OutputId) -> Option<&ValueType> {
+        self.entries.get(output_id).map(|entry| &entry.value)
+    }
+
+    /// Returns a value read by a node, which makes it the most recently used
+    pub fn fetch(&mut self, output_id: &OutputId) -> Option<&ValueType> {
+        self.clock += 1;
+
+        let entry = self.entries.get_mut(output_id)?;
+        entry.last_used = self.clock;
+
+        Some(&entry.value)
+    }
+
+    pub fn insert(&mut self, output_id: OutputId, value: ValueType) {
+        self.remove(&output_id);
+        self.clock += 1;
+
+        let bytes = memory(&value);
+        self.memory += bytes;
+        self.entries.insert(
+            output_id,
+            Entry {
+                value,
+                bytes,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, output_id: &OutputId) -> Option<ValueType> {
+        let entry = self.entries.remove(output_id)?;
+        self.memory -= entry.bytes;
+
+        Some(entry.value)
+    }
+
+    pub fn contains_key(&self, output_id: &OutputId) -> bool {
+        self.entries.contains_key(output_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.memory = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OutputId, &ValueType)> {
+        self.entries.iter().map(|(id, entry)| (id, &entry.value))
+    }
+
+    /// Memory taken by the image or slice of an output, in bytes
+    pub fn bytes(&self, output_id: &OutputId) -> usize {
+        self.entries.get(output_id).map_or(0, |entry| entry.bytes)
+    }
+
+    /// Memory taken by the images and slices, in bytes
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Evict the least recently used images and slices until the cache fits
+    /// in its budget, except the outputs that are still needed. It is not done
+    /// while a node is evaluated, so that the outputs it just computed are
+    /// still there for the nodes reading them.
+    pub fn trim(&mut self, needed: impl Fn(&OutputId) -> bool) {
+        while self.memory > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(id, entry)| entry.bytes > 0 && !needed(id))
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| *id);
+
+            match oldest {
+                Some(output_id) => {
+                    self.remove(&output_id);
+                    self.evictions += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+// Size of the pixels of a value, the other values are negligible
+fn memory(value: &ValueType) -> usize {
+    match value {
+        ValueType::Image { value } => value.pixels.len() * 4,
+        ValueType::FloatImage { value, image } => value.bytes() + image.pixels.len() * 4,
+        ValueType::Slice { value } => value.pixels.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use egui::{Color32, ColorImage};
+    use egui_node_graph::NodeTemplateTrait;
+
+    use super::*;
+    use crate::app::components::graph::node::{DataType, NodeTemplate, ProcessGraph};
+
+    fn output_ids(count: usize) -> Vec<OutputId> {
+        let mut graph = ProcessGraph::new();
+        let data = NodeTemplate::SaveImage.user_data();
+        let node_id = graph.add_node("Outputs".to_string(), data, |_, _| {});
+
+        (0..count)
+            .map(|i| graph.add_output_param(node_id, i.to_string(), DataType::Image))
+            .collect()
+    }
+
+    // Image taking 40 bytes
+    fn image() -> ValueType {
+        ValueType::Image {
+            value: Arc::new(ColorImage::new([10, 1], Color32::RED)),
+        }
+    }
+
+    #[test]
+    fn trim_evicts_the_least_recently_used() {
+        let ids = output_ids(3);
+        let mut cache = OutputsCache {
+            budget: 100,
+            ..Default::default()
+        };
+
+        for id in &ids {
+            cache.insert(*id, image());
+        }
+        cache.fetch(&ids[0]);
+        assert_eq!(cache.memory(), 120);
+
+        cache.trim(|_| false);
+
+        assert!(cache.contains_key(&ids[0]));
+        assert!(!cache.contains_key(&ids[1]));
+        assert!(cache.contains_key(&ids[2]));
+        assert_eq!(cache.memory(), 80);
+        assert_eq!(cache.evictions, 1);
+    }
+
+    #[test]
+    fn trim_keeps_the_needed_outputs() {
+        let ids = output_ids(3);
+        let mut cache = OutputsCache {
+            budget: 50,
+            ..Default::default()
+        };
+
+        for id in &ids {
+            cache.insert(*id, image());
+        }
+
+        cache.trim(|id| *id == ids[0]);
+
+        assert!(cache.contains_key(&ids[0]));
+        assert!(!cache.contains_key(&ids[1]));
+        assert!(!cache.contains_key(&ids[2]));
+        assert_eq!(cache.evictions, 2);
+    }
+
+    #[test]
+    fn trim_stops_when_nothing_can_be_evicted() {
+        let ids = output_ids(2);
+        let mut cache = OutputsCache {
+            budget: 10,
+            ..Default::default()
+        };
+
+        cache.insert(ids[0], image());
+        cache.insert(
+            ids[1],
+            ValueType::Color {
+                value: Color32::BLUE,
+            },
+        );
+
+        cache.trim(|id| *id == ids[0]);
+
+        assert_eq!(cache.memory(), 40);
+        assert!(cache.contains_key(&ids[1]));
+        assert_eq!(cache.evictions, 0);
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use egui::{Color32, ColorImage};
+use egui_node_graph::NodeTemplateTrait;
+
+use crate::app::components::graph::node::*;
+use crate::app::components::input::image_fetcher::Fetcher;
+use crate::app::math::float_image::FloatImage;
+use crate::app::state;
+
+/// Version of the text format written to the system clipboard
+const CLIPBOARD_VERSION: u32 = 1;
+
+/// Nodes copied out of the graph, along with the connections between them and
+/// the constant values of their inputs. It is written to the system clipboard
+/// as text, so that it can be pasted in another window.
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct SubGraph {
+    #[serde(rename = "carbaseus")]
+    version: u32,
+    nodes: Vec<CopiedNode>,
+    connections: Vec<CopiedConnection>,
+
+    /// Fetched images by the cache key of their request, only kept when
+    /// pasting in the same window
+    #[serde(skip)]
+    images: HashMap<u64, CopiedImage>,
+}
+
+// The image of a fetcher, with the precision and the format it was decoded with
+#[derive(Clone)]
+struct CopiedImage {
+    image: Arc<ColorImage>,
+    float: Option<Arc<FloatImage>>,
+    format: Option<&'static str>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+struct CopiedNode {
+    template: NodeTemplate,
+    position: [f32; 2],
+    inputs: Vec<CopiedInput>,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+struct CopiedInput {
+    name: String,
+    typ: DataType,
+    value: CopiedValue,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+enum CopiedValue {
+    Connection,
+    /// Written by the older versions, without the headers and the timeout
+    Url(String),
+    Request {
+        url: String,
+        headers: Vec<(String, String)>,
+        timeout: u64,
+    },
+    Files(String),
+    File(String),
+    Color([u8; 4]),
+    Scalar(f32),
+    Integer(i32),
+    Boolean(bool),
+    Choice(String),
+    Expression(String),
+    Text(String),
+}
+
+/// A connection between two copied nodes, referred by their index
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+struct CopiedConnection {
+    output_node: usize,
+    output: String,
+    input_node: usize,
+    input: String,
+}
+
+impl SubGraph {
+    /// Copy the given nodes, only the connections between them are kept
+    pub fn copy(editor: &EditorState, node_ids: &[NodeId]) -> Self {
+        let graph = &editor.graph;
+
+        let mut subgraph = SubGraph {
+            version: CLIPBOARD_VERSION,
+            ..Default::default()
+        };
+
+        let node_ids: Vec<NodeId> = node_ids
+            .iter()
+            .copied()
+            .filter(|id| graph.nodes.contains_key(*id))
+            .collect();
+
+        for node_id in &node_ids {
+            let node = &graph[*node_id];
+            let position = editor
+                .node_positions
+                .get(*node_id)
+                .copied()
+                .unwrap_or(egui::Pos2::ZERO);
+
+            let inputs = node
+                .inputs
+                .iter()
+                .map(|(name, input_id)| {
+                    let input = &graph[*input_id];
+
+                    let value = match &input.value {
+                        ValueType::ImageFetcher { value } => {
+                            let image = CopiedImage {
+                                image: value.image.clone(),
+                                float: value.float.clone(),
+                                format: value.format,
+                            };
+                            subgraph.images.insert(value.request().cache_key(), image);
+                            CopiedValue::Request {
+                                url: value.url.clone(),
+                                headers: value.headers.clone(),
+                                timeout: value.timeout,
+                            }
+                        }
+                        ValueType::Color { value } => CopiedValue::Color(value.to_array()),
+                        ValueType::Scalar { value, .. } => CopiedValue::Scalar(*value),
+                        ValueType::Integer { value, .. } => CopiedValue::Integer(*value),
+                        ValueType::Boolean { value } => CopiedValue::Boolean(*value),
+                        ValueType::Choice { value } => CopiedValue::Choice(value.label().into()),
+                        ValueType::Expression { value } => {
+                            CopiedValue::Expression(value.source.clone())
+                        }
+                        ValueType::ImageSequence { value } => {
+                            CopiedValue::Files(value.pattern.clone())
+                        }
+                        ValueType::Animation { value } => CopiedValue::File(value.path.clone()),
+                        ValueType::Text { value } => CopiedValue::Text(value.clone()),
+                        _ => CopiedValue::Connection,
+                    };
+
+                    CopiedInput {
+                        name: name.clone(),
+                        typ: input.typ,
+                        value,
+                    }
+                })
+                .collect();
+
+            subgraph.nodes.push(CopiedNode {
+                template: node.user_data.template.clone(),
+                position: [position.x, position.y],
+                inputs,
+            });
+        }
+
+        let index = |node_id: NodeId| node_ids.iter().position(|id| *id == node_id);
+
+        for (input_id, output_id) in graph.connections.iter() {
+            let input_node = graph[input_id].node;
+            let output_node = graph[*output_id].node;
+
+            if let (Some(input_index), Some(output_index)) = (index(input_node), index(output_node))
+            {
+                let input = graph[input_node]
+                    .inputs
+                    .iter()
+                    .find(|(_, id)| *id == input_id);
+                let output = graph[output_node]
+                    .outputs
+                    .iter()
+                    .find(|(_, id)| id == output_id);
+
+                if let (Some((input, _)), Some((output, _))) = (input, output) {
+                    subgraph.connections.push(CopiedConnection {
+                        output_node: output_index,
+                        output: output.clone(),
+                        input_node: input_index,
+                        input: input.clone(),
+                    });
+                }
+            }
+        }
+
+        subgraph
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Top left corner of the copied nodes
+    pub fn position(&self) -> egui::Pos2 {
+        self.nodes
+            .iter()
+            .map(|node| egui::pos2(node.position[0], node.position[1]))
+            .reduce(|a, b| a.min(b))
+            .unwrap_or(egui::Pos2::ZERO)
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Read a subgraph from text, fails if it was not written by `to_text`
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let subgraph = serde_json::from_str::<SubGraph>(text)
+            .map_err(|err| format!("The text is not a copied graph: {}", err))?;
+
+        if subgraph.version != CLIPBOARD_VERSION {
+            return Err(format!(
+                "The nodes were copied from another version ({})",
+                subgraph.version
+            ));
+        }
+
+        subgraph.check()?;
+
+        Ok(subgraph)
+    }
+
+    // The text may have been edited or corrupted, the connections must refer
+    // to copied nodes
+    fn check(&self) -> Result<(), String> {
+        let count = self.nodes.len();
+
+        match self
+            .connections
+            .iter()
+            .find(|connection| connection.output_node >= count || connection.input_node >= count)
+        {
+            Some(connection) => Err(format!(
+                "The connection from {} to {} refers to a missing node",
+                connection.output, connection.input
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the copied nodes to the graph, with their top left corner at the
+    /// given position. Returns the created nodes.
+    pub fn paste(&self, editor: &mut EditorState, position: egui::Pos2) -> Vec<NodeId> {
+        let offset = position - self.position();
+        let node_ids = self.add_to_graph(&mut editor.graph, &editor.user_state);
+
+        for (copied, node_id) in self.nodes.iter().zip(&node_ids) {
+            let pos = egui::pos2(copied.position[0], copied.position[1]) + offset;
+            editor.node_positions.insert(*node_id, pos);
+            editor.node_order.push(*node_id);
+        }
+
+        node_ids
+    }
+
+    /// Add the copied nodes and their connections to the graph, without
+    /// placing them in the editor. Returns the created nodes, in order. The
+    /// connections to missing nodes or between different types are skipped.
+    pub fn add_to_graph(&self, graph: &mut ProcessGraph, user_state: &GraphState) -> Vec<NodeId> {
+        let mut node_ids = vec![];
+
+        for copied in &self.nodes {
+            let template = &copied.template;
+
+            let node_id = graph.add_node(
+                template.node_graph_label(),
+                template.user_data(),
+                |graph, node_id| template.build_node(graph, user_state, node_id),
+            );
+
+            // Expression nodes may have more or less variables than when built
+            if let NodeTemplate::Expression = template {
+                let variables: Vec<(String, DataType)> = copied
+                    .inputs
+                    .iter()
+                    .filter(|input| input.typ != DataType::Expression)
+                    .map(|input| (input.name.clone(), input.typ))
+                    .collect();
+
+                set_expression_variables(graph, node_id, &variables);
+            }
+
+            for input in &copied.inputs {
+                if let Ok(input_id) = graph[node_id].get_input(&input.name) {
+                    self.paste_value(&mut graph[input_id].value, &input.value);
+                }
+            }
+
+            node_ids.push(node_id);
+        }
+
+        for connection in &self.connections {
+            let (output_node, input_node) = match (
+                node_ids.get(connection.output_node),
+                node_ids.get(connection.input_node),
+            ) {
+                (Some(output_node), Some(input_node)) => (*output_node, *input_node),
+                _ => continue,
+            };
+
+            let output = graph[output_node].get_output(&connection.output);
+            let input = graph[input_node].get_input(&connection.input);
+
+            if let (Ok(output), Ok(input)) = (output, input) {
+                if graph[output].typ == graph[input].typ {
+                    graph.add_connection(output, input);
+                }
+            }
+        }
+
+        node_ids
+    }
+
+    // The image copied along the request, or fetched again once the node is
+    // displayed
+    fn paste_image(&self, fetcher: &mut Fetcher) {
+        match self.images.get(&fetcher.request().cache_key()) {
+            Some(copied) => {
+                fetcher.image = copied.image.clone();
+                fetcher.float = copied.float.clone();
+                fetcher.format = copied.format;
+            }
+            None => fetcher.refetch = true,
+        }
+    }
+
+    fn paste_value(&self, value: &mut ValueType, copied: &CopiedValue) {
+        match (value, copied) {
+            (ValueType::ImageFetcher { value }, CopiedValue::Url(url)) => {
+                value.url = url.clone();
+                self.paste_image(value);
+            }
+            (
+                ValueType::ImageFetcher { value },
+                CopiedValue::Request {
+                    url,
+                    headers,
+                    timeout,
+                },
+            ) => {
+                value.url = url.clone();
+                value.headers = headers.clone();
+                value.timeout = *timeout;
+                self.paste_image(value);
+            }
+            (ValueType::Color { value }, CopiedValue::Color([r, g, b, a])) => {
+                *value = Color32::from_rgba_premultiplied(*r, *g, *b, *a);
+            }
+            (ValueType::Scalar { value, .. }, CopiedValue::Scalar(copied)) => *value = *copied,
+            (ValueType::Integer { value, .. }, CopiedValue::Integer(copied)) => *value = *copied,
+            (ValueType::Boolean { value }, CopiedValue::Boolean(copied)) => *value = *copied,
+            (ValueType::Choice { value }, CopiedValue::Choice(label)) => {
+                if let Some(choice) = value.options().into_iter().find(|c| c.label() == label) {
+                    *value = choice;
+                }
+            }
+            (ValueType::Expression { value }, CopiedValue::Expression(source)) => {
+                value.source = source.clone();
+                value.check();
+            }
+            (ValueType::ImageSequence { value }, CopiedValue::Files(pattern)) => {
+                value.pattern = pattern.clone();
+                value.list();
+            }
+            (ValueType::Animation { value }, CopiedValue::File(path)) => {
+                value.path = path.clone();
+                value.load();
+            }
+            (ValueType::Text { value }, CopiedValue::Text(text)) => *value = text.clone(),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the nodes to copy: the multiple selection if any, or the selected node
+pub fn selected_nodes(state: &state::AppState) -> Vec<NodeId> {
+    let selection = &state.graph.user_state.selection;
+
+    if selection.is_empty() {
+        state.graph.selected_node.into_iter().collect()
+    } else {
+        // Keep the order of the nodes on screen
+        state
+            .graph
+            .node_order
+            .iter()
+            .copied()
+            .filter(|id| selection.contains(id))
+            .collect()
+    }
+}
+
+/// Copy the selected nodes, returns the text to put on the system clipboard
+pub fn copy(state: &mut state::AppState) -> Option<String> {
+    let subgraph = SubGraph::copy(&state.graph, &selected_nodes(state));
+
+    if subgraph.is_empty() {
+        return None;
+    }
+
+    let text = subgraph.to_text();
+    state.clipboard = subgraph;
+
+    Some(text)
+}
+
+/// Paste the nodes at the given position, they become the new selection
+pub fn paste(state: &mut state::AppState, subgraph: &SubGraph, position: egui::Pos2) {
+    if subgraph.is_empty() {
+        return;
+    }
+
+    let node_ids = subgraph.paste(&mut state.graph, position);
+
+    state.graph.user_state.selection = node_ids.iter().copied().collect();
+    state.graph.selected_node = node_ids.last().copied();
+
+    state.history.commit(&state.graph);
+
+    if state.auto_compute {
+        evaluate_graph(&mut state.graph);
+    }
+}
+
+/// Paste the text of the system clipboard, the nodes copied from this window
+/// are preferred as they keep their fetched images
+pub fn paste_text(
+    state: &mut state::AppState,
+    text: &str,
+    position: egui::Pos2,
+) -> Result<(), String> {
+    let subgraph = if state.clipboard.to_text() == text {
+        state.clipboard.clone()
+    } else {
+        SubGraph::from_text(text)?
+    };
+
+    paste(state, &subgraph, position);
+
+    Ok(())
+}
+
+/// Copy and paste the selected nodes next to themselves, without touching the clipboard
+pub fn duplicate(state: &mut state::AppState) {
+    let subgraph = SubGraph::copy(&state.graph, &selected_nodes(state));
+    let position = subgraph.position() + egui::vec2(30.0, 30.0);
+
+    paste(state, &subgraph, position);
+}
+
+/// Select every node of the graph
+pub fn select_all(state: &mut state::AppState) {
+    state.graph.user_state.selection = state.graph.graph.iter_nodes().collect();
+
+    // The selection is cleared when no node is selected
+    if state.graph.selected_node.is_none() {
+        state.graph.selected_node = state.graph.node_order.last().copied();
+        state.selected_node = state::SelectedNode::default();
+        state.selected_node.node_id = state.graph.selected_node;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_text_rejects_connections_to_missing_nodes() {
+        let text = r#"{
+            "carbaseus": 1,
+            "nodes": [],
+            "connections": [
+                { "output_node": 0, "output": "out", "input_node": 3, "input": "image" }
+            ]
+        }"#;
+
+        assert!(SubGraph::from_text(text).is_err());
+    }
+
+    #[test]
+    fn from_text_rejects_other_versions() {
+        let text = r#"{ "carbaseus": 99, "nodes": [], "connections": [] }"#;
+
+        assert!(SubGraph::from_text(text).is_err());
+        assert!(SubGraph::from_text("not a graph").is_err());
+    }
+
+    #[test]
+    fn from_text_reads_copied_text() {
+        let subgraph = SubGraph {
+            version: CLIPBOARD_VERSION,
+            ..Default::default()
+        };
+
+        assert!(SubGraph::from_text(&subgraph.to_text()).is_ok());
+    }
+
+    #[test]
+    fn paste_value_keeps_the_headers_of_the_fetcher() {
+        let copied = CopiedValue::Request {
+            url: "https://example.com/image.png".to_string(),
+            headers: vec![("Authorization".to_string(), "Bearer token".to_string())],
+            timeout: 5,
+        };
+        let mut value = ValueType::ImageFetcher {
+            value: Fetcher::default(),
+        };
+
+        SubGraph::default().paste_value(&mut value, &copied);
+
+        match value {
+            ValueType::ImageFetcher { value } => {
+                assert_eq!(value.url, "https://example.com/image.png");
+                assert_eq!(value.headers[0].1, "Bearer token");
+                assert_eq!(value.timeout, 5);
+                assert!(value.refetch);
+            }
+            _ => panic!("The value is not a fetcher"),
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use egui_node_graph::{InputParamKind, NodeTemplateTrait};
+
+use crate::app::components::graph::cache::OutputsCache;
+use crate::app::components::graph::clipboard::{self, SubGraph};
+use crate::app::components::graph::node::*;
+use crate::app::components::graph::profiler::Profiler;
+use crate::app::state;
+
+/// A parameter of the group node, forwarded to a parameter of an inner node
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupPort {
+    pub name: String,
+    /// Index of the inner node
+    pub node: usize,
+    pub param: String,
+    pub typ: DataType,
+}
+
+/// Nodes collapsed into a single group node. The inputs and outputs of the
+/// inner nodes that were not connected between them become the ports of the
+/// group, and the promoted parameters are displayed in the group node.
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupDefinition {
+    pub name: String,
+    nodes: SubGraph,
+    pub inputs: Vec<GroupPort>,
+    pub outputs: Vec<GroupPort>,
+    pub parameters: Vec<GroupPort>,
+}
+
+/// The inner nodes of a group node, built on the first use. The copies of a
+/// node share them, as the snapshots of the history, but the nodes added
+/// from the same definition do not.
+#[derive(Clone, Default)]
+pub struct GroupInstance(Arc<Mutex<Option<InnerGraph>>>);
+
+// The inner nodes are kept between the evaluations, so that the files of the
+// sequences and animations are not read again
+struct InnerGraph {
+    graph: ProcessGraph,
+    node_ids: Vec<NodeId>,
+    /// Outputs giving the values of the connected inputs of the group
+    ports: HashMap<String, OutputId>,
+}
+
+impl GroupDefinition {
+    pub fn new(
+        name: String,
+        nodes: SubGraph,
+        inputs: Vec<GroupPort>,
+        outputs: Vec<GroupPort>,
+        parameters: Vec<GroupPort>,
+    ) -> Self {
+        Self {
+            name,
+            nodes,
+            inputs,
+            outputs,
+            parameters,
+        }
+    }
+
+    fn build_inner(&self) -> InnerGraph {
+        let mut graph = ProcessGraph::new();
+        let node_ids = self.nodes.add_to_graph(&mut graph, &GraphState::default());
+
+        // The ports are the outputs of a node that is never evaluated, their
+        // values are put in the cache before evaluating the inner nodes
+        let ports_node = graph.add_node(
+            NodeTemplate::GroupInputs.node_graph_label(),
+            NodeTemplate::GroupInputs.user_data(),
+            |_, _| {},
+        );
+
+        let ports = self
+            .inputs
+            .iter()
+            .chain(&self.parameters)
+            .map(|port| {
+                let output_id = graph.add_output_param(ports_node, port.name.clone(), port.typ);
+                (port.name.clone(), output_id)
+            })
+            .collect();
+
+        // The outputs of the group are connected to the same node, as the
+        // inner nodes only compute their connected outputs
+        for port in &self.outputs {
+            let output_id = node_ids
+                .get(port.node)
+                .and_then(|id| graph[*id].get_output(&port.param).ok());
+
+            if let Some(output_id) = output_id {
+                let input_id = graph.add_input_param(
+                    ports_node,
+                    port.name.clone(),
+                    port.typ,
+                    ValueType::Text {
+                        value: String::new(),
+                    },
+                    InputParamKind::ConnectionOnly,
+                    false,
+                );
+                graph.add_connection(output_id, input_id);
+            }
+        }
+
+        InnerGraph {
+            graph,
+            node_ids,
+            ports,
+        }
+    }
+
+    /// Add the ports and promoted parameters to the group node
+    pub fn build_node(&self, graph: &mut ProcessGraph, node_id: NodeId) {
+        // The inner nodes give the default value of each parameter
+        let instance = graph[node_id].user_data.group.clone();
+        let mut inner = instance.0.lock().unwrap();
+        let InnerGraph {
+            graph: inner,
+            node_ids: inner_ids,
+            ..
+        } = inner.get_or_insert_with(|| self.build_inner());
+
+        for port in self.inputs.iter().chain(&self.parameters) {
+            let input_id = inner_ids
+                .get(port.node)
+                .and_then(|id| inner[*id].get_input(&port.param).ok());
+
+            if let Some(input_id) = input_id {
+                let input = &inner[input_id];
+
+                graph.add_input_param(
+                    node_id,
+                    port.name.clone(),
+                    port.typ,
+                    input.value.clone(),
+                    input.kind(),
+                    true,
+                );
+            }
+        }
+
+        for port in &self.outputs {
+            graph.add_output_param(node_id, port.name.clone(), port.typ);
+        }
+    }
+
+    /// Evaluate the inner nodes and returns the value of each output. The
+    /// connected inputs of the group are given in `connections`, the values
+    /// of the other inputs and of the promoted parameters in `constants`.
+    pub fn evaluate(
+        &self,
+        instance: &GroupInstance,
+        connections: &HashMap<String, ValueType>,
+        constants: &HashMap<String, ValueType>,
+    ) -> anyhow::Result<Vec<(String, ValueType)>> {
+        let mut inner = instance.0.lock().unwrap();
+        let InnerGraph {
+            graph,
+            node_ids,
+            ports,
+        } = inner.get_or_insert_with(|| self.build_inner());
+
+        let mut outputs_cache = OutputsCache::default();
+
+        // The time spent in the nodes of the group is counted for the group node
+        let mut profiler = Profiler::default();
+
+        // The constants replace the values of the inner inputs, so that the
+        // frames chosen in the group node are the ones evaluated
+        for port in self.inputs.iter().chain(&self.parameters) {
+            let input_id = node_ids
+                .get(port.node)
+                .and_then(|id| graph[*id].get_input(&port.param).ok());
+
+            if let Some(input_id) = input_id {
+                match (connections.get(&port.name), ports.get(&port.name)) {
+                    (Some(value), Some(output_id)) => {
+                        graph.add_connection(*output_id, input_id);
+                        outputs_cache.insert(*output_id, value.clone());
+                    }
+                    _ => {
+                        graph.remove_connection(input_id);
+
+                        if let Some(value) = constants.get(&port.name) {
+                            graph[input_id].value = value.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        self.outputs
+            .iter()
+            .map(|port| {
+                let node_id = match node_ids.get(port.node) {
+                    Some(node_id) => *node_id,
+                    None => anyhow::bail!("The output {} of the group is missing", port.name),
+                };
+                let output_id = graph[node_id].get_output(&port.param)?;
+
+                if !outputs_cache.contains_key(&output_id) {
+                    evaluate_node(graph, node_id, &mut outputs_cache, &mut profiler)?;
+                }
+
+                match outputs_cache.get(&output_id) {
+                    Some(value) => Ok((port.name.clone(), value.clone())),
+                    None => anyhow::bail!("The output {} of the group was not computed", port.name),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A parameter of the selected nodes that can be promoted to the group node
+pub struct PromotedParameter {
+    pub node: usize,
+    pub param: String,
+    pub label: String,
+    pub promoted: bool,
+    /// The images are fetched by the widget of the node, the fetchers are
+    /// always displayed in the group node
+    pub required: bool,
+}
+
+/// Options chosen before collapsing the selected nodes into a group
+pub struct GroupDialog {
+    pub name: String,
+    pub node_ids: Vec<NodeId>,
+    pub parameters: Vec<PromotedParameter>,
+    pub save: bool,
+}
+
+/// Open the dialog to group the selected nodes, if any
+pub fn open_dialog(state: &mut state::AppState) {
+    let node_ids = clipboard::selected_nodes(state);
+
+    if node_ids.is_empty() {
+        return;
+    }
+
+    let graph = &state.graph.graph;
+    let mut parameters = vec![];
+
+    for (index, node_id) in node_ids.iter().enumerate() {
+        let node = &graph[*node_id];
+
+        for (param, input_id) in &node.inputs {
+            if is_parameter(graph, *input_id) {
+                let required = matches!(graph[*input_id].value, ValueType::ImageFetcher { .. });
+
+                parameters.push(PromotedParameter {
+                    node: index,
+                    param: param.clone(),
+                    label: format!("{}: {}", node.label, param),
+                    promoted: required,
+                    required,
+                });
+            }
+        }
+    }
+
+    state.group_dialog = Some(GroupDialog {
+        name: "Group".to_string(),
+        node_ids,
+        parameters,
+        save: false,
+    });
+}
+
+// Inputs edited in the node rather than connected, they may be promoted to
+// the group node
+fn is_parameter(graph: &ProcessGraph, input_id: InputId) -> bool {
+    match graph[input_id].kind() {
+        InputParamKind::ConnectionOnly => false,
+        InputParamKind::ConstantOnly => true,
+        _ => graph.connection(input_id).is_none(),
+    }
+}
+
+/// Replace the nodes of the dialog by a group node, keeping their connections
+/// to the rest of the graph
+pub fn group_nodes(state: &mut state::AppState, dialog: &GroupDialog) {
+    let graph = &state.graph.graph;
+
+    let node_ids: Vec<NodeId> = dialog
+        .node_ids
+        .iter()
+        .copied()
+        .filter(|id| graph.nodes.contains_key(*id))
+        .collect();
+
+    if node_ids.is_empty() {
+        return;
+    }
+
+    let inside = |node_id: NodeId| node_ids.contains(&node_id);
+
+    // Names of the ports must be unique in the group node
+    let unique = |names: &mut HashSet<String>, name: &str| {
+        let mut unique = name.to_string();
+        let mut count = 1;
+        while !names.insert(unique.clone()) {
+            count += 1;
+            unique = format!("{} {}", name, count);
+        }
+        unique
+    };
+
+    let mut input_names = HashSet::new();
+    let mut output_names = HashSet::new();
+
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    let mut parameters = vec![];
+
+    let mut outer_inputs = vec![];
+    let mut outer_outputs = vec![];
+
+    for (index, node_id) in node_ids.iter().enumerate() {
+        let node = &graph[*node_id];
+
+        // Inputs that are not fed by another grouped node
+        for (param, input_id) in &node.inputs {
+            let input = &graph[*input_id];
+            let source = graph.connection(*input_id);
+
+            if is_parameter(graph, *input_id)
+                || source.map_or(false, |output_id| inside(graph[output_id].node))
+            {
+                continue;
+            }
+
+            let name = unique(&mut input_names, param);
+
+            if let Some(source) = source {
+                outer_inputs.push((name.clone(), source));
+            }
+
+            inputs.push(GroupPort {
+                name,
+                node: index,
+                param: param.clone(),
+                typ: input.typ,
+            });
+        }
+
+        // Outputs that are not only used by other grouped nodes
+        for (param, output_id) in &node.outputs {
+            let targets: Vec<InputId> = graph
+                .connections
+                .iter()
+                .filter(|(_, output)| *output == output_id)
+                .map(|(input_id, _)| input_id)
+                .collect();
+
+            let outer: Vec<InputId> = targets
+                .iter()
+                .copied()
+                .filter(|input_id| !inside(graph[*input_id].node))
+                .collect();
+
+            if !targets.is_empty() && outer.is_empty() {
+                continue;
+            }
+
+            let name = unique(&mut output_names, param);
+
+            outer_outputs.push((name.clone(), outer));
+
+            outputs.push(GroupPort {
+                name,
+                node: index,
+                param: param.clone(),
+                typ: graph[*output_id].typ,
+            });
+        }
+    }
+
+    for parameter in dialog
+        .parameters
+        .iter()
+        .filter(|p| p.promoted || p.required)
+    {
+        let input_id = node_ids
+            .get(parameter.node)
+            .and_then(|id| graph[*id].get_input(&parameter.param).ok());
+
+        if let Some(input_id) = input_id {
+            parameters.push(GroupPort {
+                name: unique(&mut input_names, &parameter.param),
+                node: parameter.node,
+                param: parameter.param.clone(),
+                typ: graph[input_id].typ,
+            });
+        }
+    }
+
+    let nodes = SubGraph::copy(&state.graph, &node_ids);
+    let position = nodes.position();
+
+    let group = Arc::new(GroupDefinition::new(
+        dialog.name.clone(),
+        nodes,
+        inputs,
+        outputs,
+        parameters,
+    ));
+
+    // Replace the grouped nodes by the group node
+    for node_id in &node_ids {
+        state.graph.graph.remove_node(*node_id);
+        state.graph.node_positions.remove(*node_id);
+    }
+    state.graph.node_order.retain(|id| !node_ids.contains(id));
+    state.graph.user_state.selection.clear();
+
+    let template = NodeTemplate::Group(group.clone());
+    let group_id = state.graph.graph.add_node(
+        template.node_graph_label(),
+        template.user_data(),
+        |graph, node_id| template.build_node(graph, &state.graph.user_state, node_id),
+    );
+
+    state.graph.node_positions.insert(group_id, position);
+    state.graph.node_order.push(group_id);
+    state.graph.selected_node = Some(group_id);
+
+    let graph = &mut state.graph.graph;
+
+    for (name, source) in outer_inputs {
+        if let Ok(input_id) = graph[group_id].get_input(&name) {
+            graph.add_connection(source, input_id);
+        }
+    }
+
+    for (name, targets) in outer_outputs {
+        if let Ok(output_id) = graph[group_id].get_output(&name) {
+            for input_id in targets {
+                graph.add_connection(output_id, input_id);
+            }
+        }
+    }
+
+    if dialog.save {
+        state.library.push(group);
+    }
+
+    state.selected_node = state::SelectedNode::default();
+    state.selected_node.node_id = Some(group_id);
+
+    state.history.commit(&state.graph);
+
+    if state.auto_compute {
+        evaluate_graph(&mut state.graph);
+    }
+}
@@ -1,2 +1,9 @@
+pub mod batch;
+pub mod cache;
+pub mod clipboard;
+pub mod group;
 pub mod node;
+pub mod param;
+pub mod profiler;
+pub mod registry;
 pub mod utils;
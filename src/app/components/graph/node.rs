@@ -1,29 +1,53 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use eframe::egui::{self, DragValue};
+use eframe::egui;
 use egui::epaint::{Color32, ColorImage};
-use egui_extras::image::RetainedImage;
 use egui_node_graph::*;
 
-use crate::app::components::display;
+use crate::app::components::display::thumbnail::Thumbnail;
+use crate::app::components::graph::cache::OutputsCache;
+use crate::app::components::graph::group::{GroupDefinition, GroupInstance};
+use crate::app::components::graph::param::{ParamEdit, ParamInfo};
+use crate::app::components::graph::profiler::Profiler;
+use crate::app::components::graph::registry;
+use crate::app::components::input::animation_player::Animation;
+use crate::app::components::input::expression_editor::ExpressionEditor;
 use crate::app::components::input::image_fetcher::Fetcher;
+use crate::app::components::input::image_sequence::Sequence;
+use crate::app::math::blend::{blend_images, Alignment, BlendMode};
+use crate::app::math::denoise::{denoise_image, denoise_slice, Denoise};
+use crate::app::math::expression::{evaluate_image, evaluate_slice, operands_size, Operand};
 use crate::app::math::fft;
+use crate::app::math::float_image::FloatImage;
 use crate::app::math::image::{
     brighten_image, contrast_image, flip_image, hue_rotate_image, image_blur, image_to_gray,
-    invert_colors_image, rotate_image, ImageSlice, SliceColor,
+    invert_colors_image, is_writable, rotate_image, slice_to_image, ImageSlice, SliceColor,
+};
+use crate::app::math::scalar::{
+    apply_operation, compare, luminance, mean_color, Comparison, ScalarOperation,
+};
+use crate::app::math::statistics::{Channel, Statistics};
+use crate::app::math::transform::{
+    affine_warp, crop_image, pad_image, perspective_warp, resize_image, rotate_image_angle,
+    BorderMode, Interpolation,
 };
 use crate::app::state;
 
 const LABEL_IMAGE_IN: &str = "image_in";
 const LABEL_IMAGE_OUT: &str = "image_out";
+const LABEL_IMAGE_LAYER_IN: &str = "image_layer_in";
 
 const LABEL_INPUT_IMAGE_OUT: &str = "input_image";
+const LABEL_SEQUENCE_IN: &str = "sequence";
+const LABEL_ANIMATION_IN: &str = "animation";
 
 const LABEL_SLICE_R_IN: &str = "slice_r_in";
 const LABEL_SLICE_G_IN: &str = "slice_g_in";
 const LABEL_SLICE_B_IN: &str = "slice_b_in";
 const LABEL_SLICE_S_IN: &str = "slice_s_in";
+const LABEL_SLICE_MASK_IN: &str = "slice_mask_in";
 
 const LABEL_SLICE_R_OUT: &str = "slice_r_out";
 const LABEL_SLICE_G_OUT: &str = "slice_g_out";
@@ -32,31 +56,109 @@ const LABEL_SLICE_S_OUT: &str = "slice_s_out";
 
 const LABEL_BOOLEAN_H_IN: &str = "input_h_in";
 const LABEL_BOOLEAN_V_IN: &str = "input_v_in";
+const LABEL_BOOLEAN_EXPAND_IN: &str = "boolean_expand";
+const LABEL_BOOLEAN_VALUE_IN: &str = "boolean_value";
+const LABEL_BOOLEAN_OUT: &str = "boolean_out";
 
 const LABEL_SCALAR_SIGMA_IN: &str = "scalar_sigma";
-const _LABEL_COLOR_OUT: &str = "color_out";
-const _LABEL_SCALAR_OUT: &str = "scala_out";
+const LABEL_SCALAR_ANGLE_IN: &str = "scalar_angle";
+const LABEL_SCALAR_OPACITY_IN: &str = "scalar_opacity";
+const LABEL_SCALAR_SIGMA_SPATIAL_IN: &str = "scalar_sigma_spatial";
+const LABEL_SCALAR_SIGMA_RANGE_IN: &str = "scalar_sigma_range";
+const LABEL_SCALAR_STRENGTH_IN: &str = "scalar_strength";
+const LABEL_SCALAR_VALUE_IN: &str = "scalar_value";
+const LABEL_SCALAR_A_IN: &str = "scalar_a";
+const LABEL_SCALAR_B_IN: &str = "scalar_b";
+const LABEL_SCALAR_OUT: &str = "scalar_out";
+const LABEL_SCALAR_PERCENTILE_IN: &str = "scalar_percentile";
+const LABEL_SCALAR_MEAN_OUT: &str = "scalar_mean_out";
+const LABEL_SCALAR_STD_DEV_OUT: &str = "scalar_std_dev_out";
+const LABEL_SCALAR_MIN_OUT: &str = "scalar_min_out";
+const LABEL_SCALAR_MAX_OUT: &str = "scalar_max_out";
+const LABEL_SCALAR_PERCENTILE_OUT: &str = "scalar_percentile_out";
+const LABEL_SCALAR_ENTROPY_OUT: &str = "scalar_entropy_out";
+
+const LABEL_COLOR_FILL_IN: &str = "color_fill";
+const LABEL_COLOR_VALUE_IN: &str = "color_value";
+const LABEL_COLOR_OUT: &str = "color_out";
+
+const LABEL_SCALAR_MATRIX_IN: [&str; 6] = [
+    "scalar_a", "scalar_b", "scalar_c", "scalar_d", "scalar_e", "scalar_f",
+];
+const LABEL_SCALAR_CORNERS_IN: [[&str; 2]; 4] = [
+    ["scalar_top_left_x", "scalar_top_left_y"],
+    ["scalar_top_right_x", "scalar_top_right_y"],
+    ["scalar_bottom_right_x", "scalar_bottom_right_y"],
+    ["scalar_bottom_left_x", "scalar_bottom_left_y"],
+];
 
 const LABEL_INTEGER_SIGMA_IN: &str = "integer_sigma";
+const LABEL_INTEGER_X_IN: &str = "integer_x";
+const LABEL_INTEGER_Y_IN: &str = "integer_y";
+const LABEL_INTEGER_WIDTH_IN: &str = "integer_width";
+const LABEL_INTEGER_HEIGHT_IN: &str = "integer_height";
+const LABEL_INTEGER_RADIUS_IN: &str = "integer_radius";
+const LABEL_INTEGER_PATCH_IN: &str = "integer_patch_radius";
+const LABEL_INTEGER_SEARCH_IN: &str = "integer_search_radius";
+const LABEL_INTEGER_VALUE_IN: &str = "integer_value";
+const LABEL_INTEGER_OUT: &str = "integer_out";
+const LABEL_INTEGER_FRAME_OUT: &str = "integer_frame_out";
+const LABEL_INTEGER_REGION_IN: [&str; 4] = [
+    LABEL_INTEGER_X_IN,
+    LABEL_INTEGER_Y_IN,
+    LABEL_INTEGER_WIDTH_IN,
+    LABEL_INTEGER_HEIGHT_IN,
+];
+const LABEL_INTEGER_PADDING_IN: [&str; 4] = [
+    "integer_top",
+    "integer_right",
+    "integer_bottom",
+    "integer_left",
+];
+
+const LABEL_CHOICE_INTERPOLATION_IN: &str = "choice_interpolation";
+const LABEL_CHOICE_BORDER_IN: &str = "choice_border";
+const LABEL_CHOICE_BLEND_IN: &str = "choice_blend";
+const LABEL_CHOICE_ALIGNMENT_IN: &str = "choice_alignment";
+const LABEL_CHOICE_OPERATION_IN: &str = "choice_operation";
+const LABEL_CHOICE_COMPARISON_IN: &str = "choice_comparison";
+const LABEL_CHOICE_CHANNEL_IN: &str = "choice_channel";
+
+const LABEL_EXPRESSION_IN: &str = "expression";
+
+const LABEL_TEXT_FILE_NAME_IN: &str = "file_name";
+
+/// Where the images of a batch are written, relative to the processed file
+const DEFAULT_FILE_NAME: &str = "processed/{stem}.png";
+
+/// Names given to the variable inputs of expression nodes, in order. The
+/// pixel variables x, y, w and h are left out, they would be shadowed.
+const EXPRESSION_VARIABLES: [&str; 21] = [
+    "a", "b", "c", "d", "e", "f", "g", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t",
+    "u", "v",
+];
 
 pub type _Node = egui_node_graph::Node<NodeData>;
 pub type NodeId = egui_node_graph::id_type::NodeId;
 pub type OutputId = egui_node_graph::id_type::OutputId;
-pub type _InputId = egui_node_graph::id_type::InputId;
+pub type InputId = egui_node_graph::id_type::InputId;
 
 // ========= First, define your user data types =============
 
 /// The NodeData holds a custom data struct inside each node. It's useful to
 /// store additional information that doesn't live in parameters. For this
 /// example, the node data stores the template (i.e. the "type") of the node.
+#[derive(Clone)]
 pub struct NodeData {
     pub template: NodeTemplate,
+    /// The inner nodes of a group node, each group node has its own
+    pub group: GroupInstance,
 }
 
 /// `DataType`s are what defines the possible range of connections when
 /// attaching two ports together. The graph UI will make sure to not allow
 /// attaching incompatible datatypes.
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 pub enum DataType {
     Image,
     Slice,
@@ -64,6 +166,9 @@ pub enum DataType {
     Scalar,
     Integer,
     Boolean,
+    Choice,
+    Expression,
+    Text,
 }
 
 /// In the graph, input parameters can optionally have a constant value. This
@@ -75,38 +180,218 @@ pub enum DataType {
 /// with a DataType of Scalar and a ValueType of Vec2.
 #[derive(Clone)]
 pub enum ValueType {
-    ImageFetcher { value: Fetcher },
-    Image { value: ColorImage },
-    Slice { value: ImageSlice },
-    _Color { value: Color32 },
-    Scalar { value: f32 },
-    Integer { value: i32 },
-    Boolean { value: bool },
+    ImageFetcher {
+        value: Fetcher,
+    },
+    ImageSequence {
+        value: Sequence,
+    },
+    Animation {
+        value: Animation,
+    },
+    Image {
+        value: Arc<ColorImage>,
+    },
+    /// The image keeps its 8 bits version, displayed and read by the nodes
+    /// that do not handle floating point
+    FloatImage {
+        value: Arc<FloatImage>,
+        image: Arc<ColorImage>,
+    },
+    Slice {
+        value: Arc<ImageSlice>,
+    },
+    Color {
+        value: Color32,
+    },
+    Scalar {
+        value: f32,
+        info: ParamInfo,
+    },
+    Integer {
+        value: i32,
+        info: ParamInfo,
+    },
+    Boolean {
+        value: bool,
+    },
+    Choice {
+        value: Choice,
+    },
+    Expression {
+        value: ExpressionEditor,
+    },
+    Text {
+        value: String,
+    },
+}
+
+/// A `Choice` is a constant parameter picked from a fixed list of options,
+/// each variant holds one of the enumerations that can be selected in a node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Choice {
+    Interpolation(Interpolation),
+    Border(BorderMode),
+    Blend(BlendMode),
+    Alignment(Alignment),
+    Operation(ScalarOperation),
+    Comparison(Comparison),
+    Channel(Channel),
+}
+
+impl Choice {
+    /// All the options that can replace the current value
+    pub fn options(&self) -> Vec<Choice> {
+        match self {
+            Choice::Interpolation(_) => vec![
+                Choice::Interpolation(Interpolation::Nearest),
+                Choice::Interpolation(Interpolation::Bilinear),
+                Choice::Interpolation(Interpolation::Bicubic),
+                Choice::Interpolation(Interpolation::Lanczos),
+            ],
+            Choice::Border(_) => vec![
+                Choice::Border(BorderMode::Constant),
+                Choice::Border(BorderMode::Replicate),
+                Choice::Border(BorderMode::Reflect),
+                Choice::Border(BorderMode::Wrap),
+            ],
+            Choice::Blend(_) => vec![
+                Choice::Blend(BlendMode::Normal),
+                Choice::Blend(BlendMode::Multiply),
+                Choice::Blend(BlendMode::Screen),
+                Choice::Blend(BlendMode::Overlay),
+                Choice::Blend(BlendMode::Add),
+                Choice::Blend(BlendMode::Subtract),
+                Choice::Blend(BlendMode::Difference),
+                Choice::Blend(BlendMode::Darken),
+                Choice::Blend(BlendMode::Lighten),
+            ],
+            Choice::Alignment(_) => vec![
+                Choice::Alignment(Alignment::Stretch),
+                Choice::Alignment(Alignment::TopLeft),
+                Choice::Alignment(Alignment::Center),
+                Choice::Alignment(Alignment::Tile),
+            ],
+            Choice::Operation(_) => vec![
+                Choice::Operation(ScalarOperation::Add),
+                Choice::Operation(ScalarOperation::Subtract),
+                Choice::Operation(ScalarOperation::Multiply),
+                Choice::Operation(ScalarOperation::Divide),
+                Choice::Operation(ScalarOperation::Power),
+                Choice::Operation(ScalarOperation::Modulo),
+                Choice::Operation(ScalarOperation::Minimum),
+                Choice::Operation(ScalarOperation::Maximum),
+            ],
+            Choice::Comparison(_) => vec![
+                Choice::Comparison(Comparison::Less),
+                Choice::Comparison(Comparison::LessOrEqual),
+                Choice::Comparison(Comparison::Equal),
+                Choice::Comparison(Comparison::NotEqual),
+                Choice::Comparison(Comparison::GreaterOrEqual),
+                Choice::Comparison(Comparison::Greater),
+            ],
+            Choice::Channel(_) => vec![
+                Choice::Channel(Channel::Red),
+                Choice::Channel(Channel::Green),
+                Choice::Channel(Channel::Blue),
+                Choice::Channel(Channel::Alpha),
+                Choice::Channel(Channel::Luminance),
+            ],
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Choice::Interpolation(Interpolation::Nearest) => "Nearest",
+            Choice::Interpolation(Interpolation::Bilinear) => "Bilinear",
+            Choice::Interpolation(Interpolation::Bicubic) => "Bicubic",
+            Choice::Interpolation(Interpolation::Lanczos) => "Lanczos",
+            Choice::Border(BorderMode::Constant) => "Constant",
+            Choice::Border(BorderMode::Replicate) => "Replicate",
+            Choice::Border(BorderMode::Reflect) => "Reflect",
+            Choice::Border(BorderMode::Wrap) => "Wrap",
+            Choice::Blend(BlendMode::Normal) => "Normal",
+            Choice::Blend(BlendMode::Multiply) => "Multiply",
+            Choice::Blend(BlendMode::Screen) => "Screen",
+            Choice::Blend(BlendMode::Overlay) => "Overlay",
+            Choice::Blend(BlendMode::Add) => "Add",
+            Choice::Blend(BlendMode::Subtract) => "Subtract",
+            Choice::Blend(BlendMode::Difference) => "Difference",
+            Choice::Blend(BlendMode::Darken) => "Darken",
+            Choice::Blend(BlendMode::Lighten) => "Lighten",
+            Choice::Alignment(Alignment::Stretch) => "Stretch",
+            Choice::Alignment(Alignment::TopLeft) => "Top left",
+            Choice::Alignment(Alignment::Center) => "Center",
+            Choice::Alignment(Alignment::Tile) => "Tile",
+            Choice::Operation(ScalarOperation::Add) => "a + b",
+            Choice::Operation(ScalarOperation::Subtract) => "a - b",
+            Choice::Operation(ScalarOperation::Multiply) => "a × b",
+            Choice::Operation(ScalarOperation::Divide) => "a ÷ b",
+            Choice::Operation(ScalarOperation::Power) => "a ^ b",
+            Choice::Operation(ScalarOperation::Modulo) => "a mod b",
+            Choice::Operation(ScalarOperation::Minimum) => "min(a, b)",
+            Choice::Operation(ScalarOperation::Maximum) => "max(a, b)",
+            Choice::Comparison(Comparison::Less) => "a < b",
+            Choice::Comparison(Comparison::LessOrEqual) => "a ≤ b",
+            Choice::Comparison(Comparison::Equal) => "a = b",
+            Choice::Comparison(Comparison::NotEqual) => "a ≠ b",
+            Choice::Comparison(Comparison::GreaterOrEqual) => "a ≥ b",
+            Choice::Comparison(Comparison::Greater) => "a > b",
+            Choice::Channel(channel) => channel.name(),
+        }
+    }
 }
 
 impl ValueType {
-    /// Tries to downcast this value type to an image
-    pub fn try_to_image(self) -> anyhow::Result<ColorImage> {
+    /// Tries to downcast this value type to an image, images are shared
+    pub fn try_to_image(&self) -> anyhow::Result<Arc<ColorImage>> {
         match self {
-            ValueType::Image { value } => Ok(value),
-            ValueType::Slice { value } => Ok(ImageSlice::to_image(&value)),
-            ValueType::ImageFetcher { value } => Ok(value.image),
+            ValueType::Image { value } => Ok(value.clone()),
+            ValueType::Slice { value } => Ok(Arc::new(ImageSlice::to_image(value))),
+            ValueType::ImageFetcher { value } => Ok(value.image.clone()),
+            ValueType::ImageSequence { value } => Ok(value.image.clone()),
+            ValueType::Animation { value } => value
+                .image()
+                .ok_or_else(|| anyhow::anyhow!("The animation has no frame")),
+            ValueType::FloatImage { image, .. } => Ok(image.clone()),
             _ => {
                 anyhow::bail!("Invalid cast to ColorImage".to_string())
             }
         }
     }
 
-    /// Tries to downcast this value type to a slice
-    pub fn try_to_slice(self, color: Option<SliceColor>) -> anyhow::Result<ImageSlice> {
+    /// The image in floating point, if the value holds one
+    pub fn float_image(&self) -> Option<Arc<FloatImage>> {
         match self {
-            ValueType::Slice { value } => Ok(value),
+            ValueType::FloatImage { value, .. } => Some(value.clone()),
+            ValueType::ImageFetcher { value } => value.float.clone(),
+            ValueType::ImageSequence { value } => value.float.clone(),
+            _ => None,
+        }
+    }
+
+    /// The image of the value, kept in floating point if it is one
+    pub fn try_to_precise_image(&self) -> anyhow::Result<ValueType> {
+        match self.float_image() {
+            Some(value) => Ok(ValueType::FloatImage {
+                value,
+                image: self.try_to_image()?,
+            }),
+            None => Ok(ValueType::Image {
+                value: self.try_to_image()?,
+            }),
+        }
+    }
+
+    /// Tries to downcast this value type to a slice, slices are shared
+    pub fn try_to_slice(&self, color: Option<SliceColor>) -> anyhow::Result<Arc<ImageSlice>> {
+        match self {
+            ValueType::Slice { value } => Ok(value.clone()),
             ValueType::Image { value } => {
-                if let Some(slice_color) = color {
-                    Ok(ImageSlice::from_image(value, slice_color))
-                } else {
-                    Ok(ImageSlice::from_image(value, SliceColor::Gray))
-                }
+                let image = ColorImage::clone(value);
+                let slice_color = color.unwrap_or(SliceColor::Gray);
+
+                Ok(Arc::new(ImageSlice::from_image(image, slice_color)))
             }
             _ => {
                 anyhow::bail!("Invalid cast to Color32".to_string())
@@ -115,49 +400,88 @@ impl ValueType {
     }
 
     /// Tries to downcast this value type to a color
-    pub fn _try_to_color(self) -> anyhow::Result<Color32> {
-        if let ValueType::_Color { value } = self {
-            Ok(value)
+    pub fn try_to_color(&self) -> anyhow::Result<Color32> {
+        if let ValueType::Color { value } = self {
+            Ok(*value)
         } else {
             anyhow::bail!("Invalid cast to Color32".to_string())
         }
     }
 
     /// Tries to downcast this value type to a scalar
-    pub fn try_to_scalar(self) -> anyhow::Result<f32> {
-        if let ValueType::Scalar { value } = self {
-            Ok(value)
+    pub fn try_to_scalar(&self) -> anyhow::Result<f32> {
+        if let ValueType::Scalar { value, .. } = self {
+            Ok(*value)
         } else {
             anyhow::bail!("Invalid cast to scalar".to_string())
         }
     }
 
     /// Tries to downcast this value type to a boolean
-    pub fn try_to_boolean(self) -> anyhow::Result<bool> {
+    pub fn try_to_boolean(&self) -> anyhow::Result<bool> {
         if let ValueType::Boolean { value } = self {
-            Ok(value)
+            Ok(*value)
         } else {
             anyhow::bail!("Invalid cast to boolean".to_string())
         }
     }
 
     /// Tries to downcast this value type to an integer
-    pub fn try_to_integer(self) -> anyhow::Result<i32> {
-        if let ValueType::Integer { value } = self {
-            Ok(value)
+    pub fn try_to_integer(&self) -> anyhow::Result<i32> {
+        if let ValueType::Integer { value, .. } = self {
+            Ok(*value)
         } else {
             anyhow::bail!("Invalid cast to integer".to_string())
         }
     }
+
+    /// Tries to downcast this value type to an expression
+    pub fn try_to_expression(&self) -> anyhow::Result<ExpressionEditor> {
+        if let ValueType::Expression { value } = self {
+            Ok(value.clone())
+        } else {
+            anyhow::bail!("Invalid cast to expression".to_string())
+        }
+    }
+
+    /// Tries to downcast this value type to a choice
+    pub fn try_to_choice(&self) -> anyhow::Result<Choice> {
+        if let ValueType::Choice { value } = self {
+            Ok(*value)
+        } else {
+            anyhow::bail!("Invalid cast to choice".to_string())
+        }
+    }
+
+    /// Tries to downcast this value type to a text
+    pub fn try_to_text(&self) -> anyhow::Result<String> {
+        if let ValueType::Text { value } = self {
+            Ok(value.clone())
+        } else {
+            anyhow::bail!("Invalid cast to text".to_string())
+        }
+    }
 }
 
 /// NodeTemplate is a mechanism to define node templates. It's what the graph
 /// will display in the "new node" popup. The user code needs to tell the
 /// library how to convert a NodeTemplate into a Node.
-#[derive(Clone, Copy)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum NodeTemplate {
     // Input
     ImageFetcher,
+    ImageSequence,
+    Animation,
+
+    // Values
+    ScalarValue,
+    IntegerValue,
+    BooleanValue,
+    ColorValue,
+    ScalarMath,
+    CompareScalars,
+    MeanColor,
+    ImageStatistics,
 
     // Transformation
     GrayScales,
@@ -173,6 +497,30 @@ pub enum NodeTemplate {
     HueRotate,
     FlipImage,
     RotateImage,
+
+    // Transform
+    ResizeImage,
+    CropImage,
+    PadImage,
+    RotateAngle,
+    AffineWarp,
+    PerspectiveWarp,
+
+    // Denoise
+    MedianFilter,
+    BilateralFilter,
+    NonLocalMeans,
+
+    // Composite
+    BlendImages,
+    Expression,
+
+    // Output
+    SaveImage,
+
+    // Groups
+    Group(Arc<GroupDefinition>),
+    GroupInputs,
 }
 
 /// The response type is used to encode side-effects produced when drawing a
@@ -185,10 +533,41 @@ pub enum Response {
     ScalarChanged,
     IntegerChanged,
     BooleanChanged,
+    ChoiceChanged,
+    ColorChanged,
+    ExpressionChanged,
+    FrameChanged,
+    TextChanged,
+    /// A value dragged or typed in is not edited anymore
+    EditFinished,
+    ProcessSequence(NodeId),
+    AddVariable(NodeId, DataType),
+    RemoveVariable(NodeId),
 }
 
-type OutputsCache = HashMap<OutputId, ValueType>;
-type OutputsImages = HashMap<OutputId, RetainedImage>;
+impl Response {
+    /// Returns true for the edits recorded in the history. The values that
+    /// change while they are dragged are only recorded once released, the
+    /// frames played and the images downloaded are not edits.
+    pub fn is_edit(&self) -> bool {
+        match self {
+            Response::ScalarChanged | Response::IntegerChanged => false,
+            Response::ImageFetched | Response::FrameChanged => false,
+            Response::ProcessSequence(_) => false,
+
+            Response::BooleanChanged
+            | Response::ChoiceChanged
+            | Response::ColorChanged
+            | Response::ExpressionChanged
+            | Response::TextChanged
+            | Response::EditFinished
+            | Response::AddVariable(_, _)
+            | Response::RemoveVariable(_) => true,
+        }
+    }
+}
+
+pub type OutputsThumbnails = HashMap<OutputId, Thumbnail>;
 
 /// The graph 'global' state. This state struct is passed around to the node and
 /// parameter drawing callbacks. The contents of this struct are entirely up to
@@ -196,7 +575,18 @@ type OutputsImages = HashMap<OutputId, RetainedImage>;
 #[derive(Default)]
 pub struct GraphState {
     pub outputs_cache: OutputsCache,
-    pub outputs_images: OutputsImages,
+    pub outputs_thumbnails: OutputsThumbnails,
+    pub node_errors: HashMap<NodeId, String>,
+
+    /// Cost of the nodes during the last evaluation
+    pub profiler: Profiler,
+
+    /// Incremented on each evaluation, to know when the previews computed
+    /// from the outputs are outdated
+    pub evaluation: usize,
+
+    /// Nodes selected together, to be copied or duplicated at once
+    pub selection: HashSet<NodeId>,
 }
 
 // =========== Then, you need to implement some traits ============
@@ -209,8 +599,11 @@ impl DataTypeTrait<GraphState> for DataType {
             DataType::Color => Color32::from_rgb(238, 207, 109),
             DataType::Slice => Color32::from_rgb(214, 65, 10),
             DataType::Scalar => Color32::from_rgb(24, 165, 37),
-            DataType::Integer => Color32::from_rgb(24, 165, 37),
-            DataType::Boolean => Color32::from_rgb(24, 165, 37),
+            DataType::Integer => Color32::from_rgb(24, 165, 140),
+            DataType::Boolean => Color32::from_rgb(150, 60, 190),
+            DataType::Choice => Color32::from_rgb(24, 165, 37),
+            DataType::Expression => Color32::from_rgb(24, 165, 37),
+            DataType::Text => Color32::from_rgb(200, 200, 200),
         }
     }
 
@@ -222,6 +615,9 @@ impl DataTypeTrait<GraphState> for DataType {
             DataType::Scalar => Cow::Borrowed("scalar"),
             DataType::Integer => Cow::Borrowed("integer"),
             DataType::Boolean => Cow::Borrowed("boolean"),
+            DataType::Choice => Cow::Borrowed("choice"),
+            DataType::Expression => Cow::Borrowed("expression"),
+            DataType::Text => Cow::Borrowed("text"),
         }
     }
 }
@@ -236,23 +632,15 @@ impl NodeTemplateTrait for NodeTemplate {
 
     fn node_finder_label(&self) -> &str {
         match self {
-            NodeTemplate::ImageFetcher => "Image fetcher",
-
-            NodeTemplate::GrayScales => "Gray scales",
-            NodeTemplate::ImageToSlice => "Image to RGB Slice",
-            NodeTemplate::SliceToImage => "RGB Slice to Image",
-
-            NodeTemplate::FourierSpace => "Fourier space",
-
-            NodeTemplate::GaussianBlur => "Gaussian blur",
-            NodeTemplate::BrightenImage => "Brighten Image",
-            NodeTemplate::ContrastImage => "Contrast Image",
-
-            NodeTemplate::InvertImage => "Invert Image",
-            NodeTemplate::HueRotate => "Hue Rotate",
-
-            NodeTemplate::FlipImage => "Flip Image",
-            NodeTemplate::RotateImage => "Rotate Image",
+            NodeTemplate::Group(group) => &group.name,
+            NodeTemplate::GroupInputs => "Group inputs",
+            template => match registry::describe(template) {
+                Some(node) => node.name,
+                None => panic!(
+                    "The template {:?} is not in the registry",
+                    std::mem::discriminant(template)
+                ),
+            },
         }
     }
 
@@ -263,7 +651,10 @@ impl NodeTemplateTrait for NodeTemplate {
     }
 
     fn user_data(&self) -> NodeData {
-        NodeData { template: *self }
+        NodeData {
+            template: self.clone(),
+            group: GroupInstance::default(),
+        }
     }
 
     fn build_node(
@@ -297,7 +688,7 @@ impl NodeTemplateTrait for NodeTemplate {
                 name.to_string(), // This is the name of the parameter
                 DataType::Image,  // The data type for this input
                 ValueType::Image {
-                    value: ColorImage::new([1, 1], Color32::BLACK),
+                    value: Arc::new(ColorImage::new([1, 1], Color32::BLACK)),
                 }, // The value type for this input
                 InputParamKind::ConnectionOnly, // The input parameter kind.
                 true,
@@ -310,43 +701,48 @@ impl NodeTemplateTrait for NodeTemplate {
                 name.to_string(), // This is the name of the parameter
                 DataType::Slice,  // The data type for this input
                 ValueType::Slice {
-                    value: ImageSlice::new(color, [1, 1]),
+                    value: Arc::new(ImageSlice::new(color, [1, 1])),
                 }, // The value type for this input
                 InputParamKind::ConnectionOnly, // The input parameter kind.
                 true,
             );
         };
-        /*
-        let input_color = |graph: &mut ProcessGraph, name: &str| {
+
+        let input_color = |graph: &mut ProcessGraph, name: &str, value: Color32| {
             graph.add_input_param(
                 node_id,
                 name.to_string(),
                 DataType::Color,
-                ValueType::Color { value: Color32::BLACK },
+                ValueType::Color { value },
                 InputParamKind::ConnectionOrConstant,
                 true,
             );
         };
-        */
 
-        let input_scalar = |graph: &mut ProcessGraph, name: &str| {
+        let input_scalar = |graph: &mut ProcessGraph, name: &str, info: ParamInfo| {
             graph.add_input_param(
                 node_id,
                 name.to_string(),
                 DataType::Scalar,
-                ValueType::Scalar { value: 0.0 },
-                InputParamKind::ConstantOnly,
+                ValueType::Scalar {
+                    value: info.default as f32,
+                    info,
+                },
+                InputParamKind::ConnectionOrConstant,
                 true,
             );
         };
 
-        let input_integer = |graph: &mut ProcessGraph, name: &str| {
+        let input_integer = |graph: &mut ProcessGraph, name: &str, info: ParamInfo| {
             graph.add_input_param(
                 node_id,
                 name.to_string(),
                 DataType::Integer,
-                ValueType::Integer { value: 0 },
-                InputParamKind::ConstantOnly,
+                ValueType::Integer {
+                    value: info.default as i32,
+                    info,
+                },
+                InputParamKind::ConnectionOrConstant,
                 true,
             );
         };
@@ -357,7 +753,30 @@ impl NodeTemplateTrait for NodeTemplate {
                 name.to_string(),  // This is the name of the parameter
                 DataType::Boolean, // The data type for this input
                 ValueType::Boolean { value: false }, // The value type for this input
-                InputParamKind::ConstantOnly, // The input parameter kind.
+                InputParamKind::ConnectionOrConstant, // The input parameter kind.
+                true,
+            );
+        };
+
+        // The value of a constant node can not be connected
+        let input_constant = |graph: &mut ProcessGraph, name: &str, typ: DataType, value| {
+            graph.add_input_param(
+                node_id,
+                name.to_string(),
+                typ,
+                value,
+                InputParamKind::ConstantOnly,
+                true,
+            );
+        };
+
+        let input_choice = |graph: &mut ProcessGraph, name: &str, value: Choice| {
+            graph.add_input_param(
+                node_id,
+                name.to_string(),
+                DataType::Choice,
+                ValueType::Choice { value },
+                InputParamKind::ConstantOnly,
                 true,
             );
         };
@@ -374,26 +793,130 @@ impl NodeTemplateTrait for NodeTemplate {
             graph.add_output_param(node_id, name.to_string(), DataType::Slice);
         };
 
-        let _output_color = |graph: &mut ProcessGraph, name: &str| {
+        let output_color = |graph: &mut ProcessGraph, name: &str| {
             graph.add_output_param(node_id, name.to_string(), DataType::Color);
         };
 
-        let _output_scalar = |graph: &mut ProcessGraph, name: &str| {
+        let output_scalar = |graph: &mut ProcessGraph, name: &str| {
             graph.add_output_param(node_id, name.to_string(), DataType::Scalar);
         };
 
+        let output_integer = |graph: &mut ProcessGraph, name: &str| {
+            graph.add_output_param(node_id, name.to_string(), DataType::Integer);
+        };
+
+        let output_boolean = |graph: &mut ProcessGraph, name: &str| {
+            graph.add_output_param(node_id, name.to_string(), DataType::Boolean);
+        };
+
         match self {
             NodeTemplate::ImageFetcher => {
                 input_fetcher_image(graph, LABEL_IMAGE_IN);
                 output_image(graph, LABEL_INPUT_IMAGE_OUT);
             }
+            NodeTemplate::ImageSequence => {
+                let value = ValueType::ImageSequence {
+                    value: Sequence::default(),
+                };
+
+                input_constant(graph, LABEL_SEQUENCE_IN, DataType::Image, value);
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_integer(graph, LABEL_INTEGER_FRAME_OUT);
+            }
+            NodeTemplate::Animation => {
+                let value = ValueType::Animation {
+                    value: Animation::default(),
+                };
+
+                input_constant(graph, LABEL_ANIMATION_IN, DataType::Image, value);
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_integer(graph, LABEL_INTEGER_FRAME_OUT);
+            }
+            NodeTemplate::ScalarValue => {
+                let value = ValueType::Scalar {
+                    value: 0.0,
+                    info: ParamInfo::default(),
+                };
+
+                input_constant(graph, LABEL_SCALAR_VALUE_IN, DataType::Scalar, value);
+                output_scalar(graph, LABEL_SCALAR_OUT);
+            }
+            NodeTemplate::IntegerValue => {
+                let value = ValueType::Integer {
+                    value: 0,
+                    info: ParamInfo::default().step(1.0),
+                };
+
+                input_constant(graph, LABEL_INTEGER_VALUE_IN, DataType::Integer, value);
+                output_integer(graph, LABEL_INTEGER_OUT);
+            }
+            NodeTemplate::BooleanValue => {
+                let value = ValueType::Boolean { value: false };
+
+                input_constant(graph, LABEL_BOOLEAN_VALUE_IN, DataType::Boolean, value);
+                output_boolean(graph, LABEL_BOOLEAN_OUT);
+            }
+            NodeTemplate::ColorValue => {
+                let value = ValueType::Color {
+                    value: Color32::WHITE,
+                };
+
+                input_constant(graph, LABEL_COLOR_VALUE_IN, DataType::Color, value);
+                output_color(graph, LABEL_COLOR_OUT);
+            }
+            NodeTemplate::ScalarMath => {
+                input_scalar(graph, LABEL_SCALAR_A_IN, ParamInfo::new(0.0));
+                input_scalar(graph, LABEL_SCALAR_B_IN, ParamInfo::new(0.0));
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_OPERATION_IN,
+                    Choice::Operation(ScalarOperation::Add),
+                );
+                output_scalar(graph, LABEL_SCALAR_OUT);
+                output_integer(graph, LABEL_INTEGER_OUT);
+            }
+            NodeTemplate::CompareScalars => {
+                input_scalar(graph, LABEL_SCALAR_A_IN, ParamInfo::new(0.0));
+                input_scalar(graph, LABEL_SCALAR_B_IN, ParamInfo::new(0.0));
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_COMPARISON_IN,
+                    Choice::Comparison(Comparison::Greater),
+                );
+                output_boolean(graph, LABEL_BOOLEAN_OUT);
+            }
+            NodeTemplate::MeanColor => {
+                input_image(graph, LABEL_IMAGE_IN);
+                output_color(graph, LABEL_COLOR_OUT);
+                output_scalar(graph, LABEL_SCALAR_OUT);
+            }
+            NodeTemplate::ImageStatistics => {
+                let percentile = ParamInfo::new(50.0).range(0.0, 100.0).unit(" %").slider();
+
+                input_image(graph, LABEL_IMAGE_IN);
+                input_slice(graph, LABEL_SLICE_S_IN, SliceColor::Gray);
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_CHANNEL_IN,
+                    Choice::Channel(Channel::Luminance),
+                );
+                input_scalar(graph, LABEL_SCALAR_PERCENTILE_IN, percentile);
+                output_scalar(graph, LABEL_SCALAR_MEAN_OUT);
+                output_scalar(graph, LABEL_SCALAR_STD_DEV_OUT);
+                output_scalar(graph, LABEL_SCALAR_MIN_OUT);
+                output_scalar(graph, LABEL_SCALAR_MAX_OUT);
+                output_scalar(graph, LABEL_SCALAR_PERCENTILE_OUT);
+                output_scalar(graph, LABEL_SCALAR_ENTROPY_OUT);
+            }
             NodeTemplate::GrayScales => {
                 input_image(graph, LABEL_IMAGE_IN);
                 output_slice(graph, LABEL_SLICE_S_OUT);
             }
             NodeTemplate::GaussianBlur => {
+                let sigma = ParamInfo::new(0.0).range(0.0, 20.0).unit(" px").slider();
+
                 input_image(graph, LABEL_IMAGE_IN);
-                input_scalar(graph, LABEL_SCALAR_SIGMA_IN);
+                input_scalar(graph, LABEL_SCALAR_SIGMA_IN, sigma);
                 output_image(graph, LABEL_IMAGE_OUT);
             }
             NodeTemplate::FourierSpace => {
@@ -413,13 +936,21 @@ impl NodeTemplateTrait for NodeTemplate {
                 output_slice(graph, LABEL_SLICE_B_OUT);
             }
             NodeTemplate::BrightenImage => {
+                let amount = ParamInfo::new(0.0).range(-255.0, 255.0).step(1.0).slider();
+
                 input_image(graph, LABEL_IMAGE_IN);
-                input_scalar(graph, LABEL_SCALAR_SIGMA_IN);
+                input_scalar(graph, LABEL_SCALAR_SIGMA_IN, amount);
                 output_image(graph, LABEL_IMAGE_OUT);
             }
             NodeTemplate::ContrastImage => {
+                let amount = ParamInfo::new(0.0)
+                    .range(-100.0, 100.0)
+                    .step(1.0)
+                    .unit(" %")
+                    .slider();
+
                 input_image(graph, LABEL_IMAGE_IN);
-                input_scalar(graph, LABEL_SCALAR_SIGMA_IN);
+                input_scalar(graph, LABEL_SCALAR_SIGMA_IN, amount);
                 output_image(graph, LABEL_IMAGE_OUT);
             }
             NodeTemplate::InvertImage => {
@@ -427,8 +958,10 @@ impl NodeTemplateTrait for NodeTemplate {
                 output_image(graph, LABEL_IMAGE_OUT);
             }
             NodeTemplate::HueRotate => {
+                let angle = ParamInfo::new(0.0).range(-180.0, 180.0).step(1.0).angle();
+
                 input_image(graph, LABEL_IMAGE_IN);
-                input_scalar(graph, LABEL_SCALAR_SIGMA_IN);
+                input_scalar(graph, LABEL_SCALAR_SIGMA_IN, angle);
                 output_image(graph, LABEL_IMAGE_OUT);
             }
             NodeTemplate::FlipImage => {
@@ -439,34 +972,202 @@ impl NodeTemplateTrait for NodeTemplate {
             }
             NodeTemplate::RotateImage => {
                 input_image(graph, LABEL_IMAGE_IN);
-                input_integer(graph, LABEL_INTEGER_SIGMA_IN);
+                let turns = ParamInfo::new(0.0).range(0.0, 3.0).unit(" × 90°").slider();
+
+                input_integer(graph, LABEL_INTEGER_SIGMA_IN, turns);
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::ResizeImage => {
+                input_image(graph, LABEL_IMAGE_IN);
+                let size = |default| ParamInfo::new(default).range(0.0, 8192.0).unit(" px");
+
+                input_integer(graph, LABEL_INTEGER_WIDTH_IN, size(256.0));
+                input_integer(graph, LABEL_INTEGER_HEIGHT_IN, size(0.0));
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_INTERPOLATION_IN,
+                    Choice::Interpolation(Interpolation::Bilinear),
+                );
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::CropImage => {
+                input_image(graph, LABEL_IMAGE_IN);
+                let size = ParamInfo::new(0.0).range(0.0, 8192.0).unit(" px");
+
+                for label in LABEL_INTEGER_REGION_IN {
+                    input_integer(graph, label, size);
+                }
                 output_image(graph, LABEL_IMAGE_OUT);
             }
+            NodeTemplate::PadImage => {
+                input_image(graph, LABEL_IMAGE_IN);
+                let size = ParamInfo::new(16.0).range(0.0, 4096.0).unit(" px");
+
+                for label in LABEL_INTEGER_PADDING_IN {
+                    input_integer(graph, label, size);
+                }
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_BORDER_IN,
+                    Choice::Border(BorderMode::Constant),
+                );
+                input_color(graph, LABEL_COLOR_FILL_IN, Color32::BLACK);
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::RotateAngle => {
+                input_image(graph, LABEL_IMAGE_IN);
+                let angle = ParamInfo::new(0.0).range(-180.0, 180.0).step(1.0).angle();
+
+                input_scalar(graph, LABEL_SCALAR_ANGLE_IN, angle);
+                input_boolean(graph, LABEL_BOOLEAN_EXPAND_IN);
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_INTERPOLATION_IN,
+                    Choice::Interpolation(Interpolation::Bilinear),
+                );
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::AffineWarp => {
+                input_image(graph, LABEL_IMAGE_IN);
+                // Start from the identity transform
+                let identity = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+                for (index, (label, value)) in
+                    LABEL_SCALAR_MATRIX_IN.iter().zip(identity).enumerate()
+                {
+                    // The last column is the translation, in pixels
+                    let info = match index % 3 {
+                        2 => ParamInfo::new(value).step(1.0).unit(" px"),
+                        _ => ParamInfo::new(value).step(0.01),
+                    };
+                    input_scalar(graph, label, info);
+                }
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_INTERPOLATION_IN,
+                    Choice::Interpolation(Interpolation::Bilinear),
+                );
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::PerspectiveWarp => {
+                input_image(graph, LABEL_IMAGE_IN);
+                // Corners are relative to the image size, start from the identity
+                let identity = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+                for (labels, values) in LABEL_SCALAR_CORNERS_IN.iter().zip(identity) {
+                    input_scalar(graph, labels[0], ParamInfo::new(values[0]).step(0.01));
+                    input_scalar(graph, labels[1], ParamInfo::new(values[1]).step(0.01));
+                }
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_INTERPOLATION_IN,
+                    Choice::Interpolation(Interpolation::Bilinear),
+                );
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::MedianFilter => {
+                input_image(graph, LABEL_IMAGE_IN);
+                input_slice(graph, LABEL_SLICE_S_IN, SliceColor::Gray);
+                let radius = ParamInfo::new(2.0).range(0.0, 20.0).unit(" px").slider();
+
+                input_integer(graph, LABEL_INTEGER_RADIUS_IN, radius);
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_slice(graph, LABEL_SLICE_S_OUT);
+            }
+            NodeTemplate::BilateralFilter => {
+                input_image(graph, LABEL_IMAGE_IN);
+                input_slice(graph, LABEL_SLICE_S_IN, SliceColor::Gray);
+                let spatial = ParamInfo::new(3.0).range(0.0, 20.0).unit(" px").slider();
+                let range = ParamInfo::new(25.0).range(0.0, 255.0).step(1.0).slider();
+
+                input_scalar(graph, LABEL_SCALAR_SIGMA_SPATIAL_IN, spatial);
+                input_scalar(graph, LABEL_SCALAR_SIGMA_RANGE_IN, range);
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_slice(graph, LABEL_SLICE_S_OUT);
+            }
+            NodeTemplate::NonLocalMeans => {
+                input_image(graph, LABEL_IMAGE_IN);
+                input_slice(graph, LABEL_SLICE_S_IN, SliceColor::Gray);
+                let strength = ParamInfo::new(10.0).range(0.0, 100.0).slider();
+                let patch = ParamInfo::new(1.0).range(0.0, 5.0).unit(" px").slider();
+                let search = ParamInfo::new(5.0).range(0.0, 15.0).unit(" px").slider();
+
+                input_scalar(graph, LABEL_SCALAR_STRENGTH_IN, strength);
+                input_integer(graph, LABEL_INTEGER_PATCH_IN, patch);
+                input_integer(graph, LABEL_INTEGER_SEARCH_IN, search);
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_slice(graph, LABEL_SLICE_S_OUT);
+            }
+            NodeTemplate::BlendImages => {
+                input_image(graph, LABEL_IMAGE_IN);
+                input_image(graph, LABEL_IMAGE_LAYER_IN);
+                input_slice(graph, LABEL_SLICE_MASK_IN, SliceColor::Gray);
+                let opacity = ParamInfo::new(1.0).range(0.0, 1.0).step(0.01).slider();
+
+                input_scalar(graph, LABEL_SCALAR_OPACITY_IN, opacity);
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_BLEND_IN,
+                    Choice::Blend(BlendMode::Normal),
+                );
+                input_choice(
+                    graph,
+                    LABEL_CHOICE_ALIGNMENT_IN,
+                    Choice::Alignment(Alignment::Stretch),
+                );
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::Expression => {
+                let variables = &EXPRESSION_VARIABLES[..2];
+
+                graph.add_input_param(
+                    node_id,
+                    LABEL_EXPRESSION_IN.to_string(),
+                    DataType::Expression,
+                    ValueType::Expression {
+                        value: ExpressionEditor::new("(a + b) / 2", variables),
+                    },
+                    InputParamKind::ConstantOnly,
+                    true,
+                );
+                for name in variables {
+                    add_variable_input(graph, node_id, name, DataType::Slice);
+                }
+                output_image(graph, LABEL_IMAGE_OUT);
+                output_slice(graph, LABEL_SLICE_S_OUT);
+            }
+            NodeTemplate::SaveImage => {
+                let value = ValueType::Text {
+                    value: DEFAULT_FILE_NAME.to_string(),
+                };
+
+                input_image(graph, LABEL_IMAGE_IN);
+                input_constant(graph, LABEL_TEXT_FILE_NAME_IN, DataType::Text, value);
+                output_image(graph, LABEL_IMAGE_OUT);
+            }
+            NodeTemplate::Group(group) => group.build_node(graph, node_id),
+            NodeTemplate::GroupInputs => {}
         }
     }
 }
 
-pub struct AllNodeTemplates;
+pub struct AllNodeTemplates<'a> {
+    /// Groups saved by the user, listed after the built-in nodes
+    pub library: &'a [Arc<GroupDefinition>],
+}
 
-impl NodeTemplateIter for AllNodeTemplates {
+impl<'a> NodeTemplateIter for AllNodeTemplates<'a> {
     type Item = NodeTemplate;
 
     fn all_kinds(&self) -> Vec<Self::Item> {
         // This function must return a list of node kinds, which the node finder
         // will use to display it to the user. Crates like strum can reduce the
         // boilerplate in enumerating all variants of an enum.
-        vec![
-            NodeTemplate::ImageFetcher,
-            NodeTemplate::GrayScales,
-            NodeTemplate::GaussianBlur,
-            NodeTemplate::FourierSpace,
-            NodeTemplate::SliceToImage,
-            NodeTemplate::ImageToSlice,
-            NodeTemplate::BrightenImage,
-            NodeTemplate::ContrastImage,
-            NodeTemplate::InvertImage,
-            NodeTemplate::HueRotate,
-        ]
+        let mut kinds: Vec<NodeTemplate> = registry::NODES
+            .iter()
+            .map(|node| node.template.clone())
+            .collect();
+
+        kinds.extend(self.library.iter().cloned().map(NodeTemplate::Group));
+        kinds
     }
 }
 
@@ -483,27 +1184,60 @@ impl WidgetValueTrait for ValueType {
                     responses.push(Response::ImageFetched); // Notify when input image changes
                 }
             }
+            ValueType::ImageSequence { value } => {
+                if value.show(ui) {
+                    responses.push(Response::FrameChanged); // Notify when the frame changes
+                }
+            }
+            ValueType::Animation { value } => {
+                if value.show(ui) {
+                    responses.push(Response::FrameChanged); // Notify when the frame changes
+                }
+            }
             ValueType::Image { value: _ } => {}
-            ValueType::_Color { value: _ } => {}
+            ValueType::FloatImage { .. } => {}
             ValueType::Slice { value: _ } => {}
-            ValueType::Scalar { value } => {
+            ValueType::Color { value } => {
                 ui.horizontal(|ui| {
                     ui.label(param_name);
 
-                    let drag_value =
-                        ui.add(DragValue::new(value).speed(0.1).clamp_range(0.0..=1000.0));
-                    if drag_value.drag_released() || drag_value.lost_focus() {
+                    // The color changes while dragging in the picker, it is
+                    // only notified once the pointer is released
+                    let id = ui.make_persistent_id(param_name);
+                    let mut pending = ui.data().get_temp::<bool>(id).unwrap_or(false);
+
+                    pending |= ui.color_edit_button_srgba(value).changed();
+                    if pending && !ui.input().pointer.any_down() {
+                        responses.push(Response::ColorChanged); // Notify when color changes
+                        pending = false;
+                    }
+
+                    ui.data().insert_temp(id, pending);
+                });
+            }
+            ValueType::Scalar { value, info } => {
+                ui.horizontal(|ui| {
+                    ui.label(param_name);
+
+                    let edit = info.show_scalar(ui, value);
+                    if edit.changed {
                         responses.push(Response::ScalarChanged); // Notify when scalar changes
                     }
+                    if edit.done {
+                        responses.push(Response::EditFinished);
+                    }
                 });
             }
-            ValueType::Integer { value } => {
+            ValueType::Integer { value, info } => {
                 ui.horizontal(|ui| {
                     ui.label(param_name);
 
-                    let drag_value = ui.add(DragValue::new(value).clamp_range(0.0..=1000.0));
-                    if drag_value.drag_released() || drag_value.lost_focus() {
-                        responses.push(Response::IntegerChanged); // Notify when scalar changes
+                    let edit = info.show_integer(ui, value);
+                    if edit.changed {
+                        responses.push(Response::IntegerChanged); // Notify when integer changes
+                    }
+                    if edit.done {
+                        responses.push(Response::EditFinished);
                     }
                 });
             }
@@ -512,6 +1246,37 @@ impl WidgetValueTrait for ValueType {
                     responses.push(Response::BooleanChanged); // Notify when boolean changes
                 }
             }
+            ValueType::Choice { value } => {
+                ui.horizontal(|ui| {
+                    ui.label(param_name);
+
+                    egui::ComboBox::from_id_source(ui.make_persistent_id(param_name))
+                        .selected_text(value.label())
+                        .show_ui(ui, |ui| {
+                            for option in value.options() {
+                                if ui.selectable_value(value, option, option.label()).clicked() {
+                                    responses.push(Response::ChoiceChanged); // Notify when choice changes
+                                }
+                            }
+                        });
+                });
+            }
+            ValueType::Expression { value } => {
+                if value.show(ui) {
+                    responses.push(Response::ExpressionChanged); // Notify when expression changes
+                }
+            }
+            ValueType::Text { value } => {
+                ui.horizontal(|ui| {
+                    ui.label(param_name);
+
+                    // Notified once typed, when the text loses the focus
+                    let response = ui.text_edit_singleline(value);
+                    if ParamEdit::of(ui, response.id, &[&response]).done {
+                        responses.push(Response::TextChanged); // Notify when text changes
+                    }
+                });
+            }
         }
         responses
     }
@@ -540,7 +1305,7 @@ impl NodeDataTrait for NodeData {
         // current node we're drawing is the active one, by comparing against
         // the value stored in the global user state, and draw different button
         // UIs based on that.
-        let responses: Vec<NodeResponse<Response, NodeData>> = vec![];
+        let mut responses: Vec<NodeResponse<Response, NodeData>> = vec![];
 
         let find_node = _graph.nodes.iter().find(|(id, _data)| *id == node_id);
 
@@ -550,28 +1315,100 @@ impl NodeDataTrait for NodeData {
         if let Some((_node_id, node_data)) = find_node {
             // Check if we have an output with an image or slice output
             for (label, id) in node_data.outputs.iter() {
-                if let Some(image) = user_state.outputs_images.get(id) {
-                    egui::CollapsingHeader::new(format!("Image for {label}"))
+                if let Some(thumbnail) = user_state.outputs_thumbnails.get(id) {
+                    let preview = egui::CollapsingHeader::new(format!("Image for {label}"))
                         .default_open(first_header)
                         .show(ui, |ui| {
-                            display::image_frame::show(ui, image);
+                            thumbnail.show(ui);
                         });
 
+                    // Collapsed previews do not keep their texture
+                    if preview.body_returned.is_none() {
+                        thumbnail.release();
+                    }
+
                     first_header = false;
                 }
+
+                // Values computed by the node are displayed next to their name
+                match user_state.outputs_cache.get(id) {
+                    Some(ValueType::Scalar { value, .. }) => {
+                        ui.label(format!("{}: {}", label, value));
+                    }
+                    Some(ValueType::Integer { value, .. }) => {
+                        ui.label(format!("{}: {}", label, value));
+                    }
+                    Some(ValueType::Boolean { value }) => {
+                        ui.label(format!("{}: {}", label, value));
+                    }
+                    Some(ValueType::Color { value }) => {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}:", label));
+                            egui::widgets::color_picker::show_color(
+                                ui,
+                                *value,
+                                ui.spacing().interact_size,
+                            );
+                        });
+                    }
+                    _ => {}
+                }
+            }
+
+            // Expression nodes can bind any number of inputs to variables
+            if let NodeTemplate::Expression = node_data.user_data.template {
+                ui.horizontal(|ui| {
+                    for (label, typ) in [
+                        ("➕ Slice", DataType::Slice),
+                        ("➕ Image", DataType::Image),
+                        ("➕ Scalar", DataType::Scalar),
+                    ] {
+                        if ui.button(label).clicked() {
+                            responses.push(NodeResponse::User(Response::AddVariable(node_id, typ)));
+                        }
+                    }
+                    if ui.button("➖").clicked() {
+                        responses.push(NodeResponse::User(Response::RemoveVariable(node_id)));
+                    }
+                });
+            }
+
+            // The graph is evaluated for each frame of a sequence, the images
+            // of the save nodes are written along the way
+            if let NodeTemplate::ImageSequence | NodeTemplate::Animation =
+                node_data.user_data.template
+            {
+                if ui
+                    .button("▶ Process all")
+                    .on_hover_text("Write the images of the save nodes for every frame")
+                    .clicked()
+                {
+                    responses.push(NodeResponse::User(Response::ProcessSequence(node_id)));
+                }
             }
         }
+
+        // Display the reason why the last evaluation of the node failed
+        if let Some(error) = user_state.node_errors.get(&node_id) {
+            ui.colored_label(Color32::RED, error);
+        }
+
         responses
     }
 
     fn titlebar_color(
         &self,
         _ui: &egui::Ui,
-        _node_id: NodeId,
+        node_id: NodeId,
         _graph: &Graph<Self, Self::DataType, Self::ValueType>,
-        _user_state: &Self::UserState,
+        user_state: &Self::UserState,
     ) -> Option<egui::Color32> {
-        None
+        // Highlight the nodes that are part of a multiple selection
+        if user_state.selection.len() > 1 && user_state.selection.contains(&node_id) {
+            Some(Color32::from_rgb(60, 90, 140))
+        } else {
+            None
+        }
     }
 }
 
@@ -588,29 +1425,343 @@ pub fn create_node(state: &mut state::AppState, node_kind: NodeTemplate, pos: eg
     state.graph.node_positions.insert(new_node, pos);
 
     state.graph.node_order.push(new_node);
+
+    state.history.commit(&state.graph);
+}
+
+/// Returns the `[x, y, width, height]` region of a crop node, along with the
+/// output connected to the image it applies to.
+pub fn crop_region(graph: &ProcessGraph, node_id: NodeId) -> Option<(OutputId, [usize; 4])> {
+    let node = &graph[node_id];
+
+    if !matches!(node.user_data.template, NodeTemplate::CropImage) {
+        return None;
+    }
+
+    let source = graph.connection(node.get_input(LABEL_IMAGE_IN).ok()?)?;
+
+    let mut region = [0; 4];
+    for (value, label) in region.iter_mut().zip(LABEL_INTEGER_REGION_IN) {
+        let input_id = node.get_input(label).ok()?;
+
+        // A region computed by other nodes can not be edited
+        if graph.connection(input_id).is_some() {
+            return None;
+        }
+
+        *value = graph[input_id].value.try_to_integer().ok()?.max(0) as usize;
+    }
+
+    Some((source, region))
+}
+
+/// Overwrites the constant `[x, y, width, height]` inputs of a crop node.
+pub fn set_crop_region(graph: &mut ProcessGraph, node_id: NodeId, region: [usize; 4]) {
+    for (value, label) in region.into_iter().zip(LABEL_INTEGER_REGION_IN) {
+        if let Ok(input_id) = graph[node_id].get_input(label) {
+            if let ValueType::Integer { value: current, .. } = &mut graph[input_id].value {
+                *current = value as i32;
+            }
+        }
+    }
+}
+
+/// Returns the value holding the frames of an image sequence or an animation
+/// node.
+pub fn frames_mut(graph: &mut ProcessGraph, node_id: NodeId) -> Option<&mut ValueType> {
+    let node = graph.nodes.get(node_id)?;
+    let input_id = node
+        .get_input(LABEL_SEQUENCE_IN)
+        .or_else(|_| node.get_input(LABEL_ANIMATION_IN))
+        .ok()?;
+
+    match &mut graph[input_id].value {
+        value @ (ValueType::ImageSequence { .. } | ValueType::Animation { .. }) => Some(value),
+        _ => None,
+    }
+}
+
+/// Returns the format of the file an input node decoded its image from.
+pub fn source_format(graph: &ProcessGraph, node_id: NodeId) -> Option<&'static str> {
+    graph
+        .nodes
+        .get(node_id)?
+        .inputs
+        .iter()
+        .find_map(|(_, input_id)| match &graph[*input_id].value {
+            ValueType::ImageFetcher { value } => value.format,
+            ValueType::ImageSequence { value } => value.format,
+            ValueType::Animation { value } => value.format,
+            _ => None,
+        })
+}
+
+/// Returns the save nodes of the graph, with their image output and the
+/// template of the name of the files they write.
+pub fn save_targets(graph: &ProcessGraph) -> Vec<(NodeId, OutputId, String)> {
+    graph
+        .nodes
+        .iter()
+        .filter(|(_, node)| matches!(node.user_data.template, NodeTemplate::SaveImage))
+        .filter_map(|(node_id, node)| {
+            let output_id = node.get_output(LABEL_IMAGE_OUT).ok()?;
+            let input_id = node.get_input(LABEL_TEXT_FILE_NAME_IN).ok()?;
+            let template = graph[input_id].value.try_to_text().ok()?;
+
+            Some((node_id, output_id, template))
+        })
+        .collect()
+}
+
+/// Adds an input bound to a variable of an expression node.
+fn add_variable_input(graph: &mut ProcessGraph, node_id: NodeId, name: &str, typ: DataType) {
+    let (value, kind) = match typ {
+        DataType::Image => (
+            ValueType::Image {
+                value: Arc::new(ColorImage::new([1, 1], Color32::BLACK)),
+            },
+            InputParamKind::ConnectionOnly,
+        ),
+        DataType::Slice => (
+            ValueType::Slice {
+                value: Arc::new(ImageSlice::new(SliceColor::Gray, [1, 1])),
+            },
+            InputParamKind::ConnectionOnly,
+        ),
+        _ => (
+            ValueType::Scalar {
+                value: 0.0,
+                info: ParamInfo::default(),
+            },
+            InputParamKind::ConnectionOrConstant,
+        ),
+    };
+
+    graph.add_input_param(node_id, name.to_string(), typ, value, kind, true);
+}
+
+/// Returns the inputs of an expression node that are bound to variables.
+fn expression_variables(graph: &ProcessGraph, node_id: NodeId) -> Vec<(String, InputId)> {
+    graph[node_id]
+        .inputs
+        .iter()
+        .filter(|(name, _)| name != LABEL_EXPRESSION_IN)
+        .cloned()
+        .collect()
+}
+
+/// Keeps the variables known by the expression in sync with the node inputs.
+fn update_expression_variables(graph: &mut ProcessGraph, node_id: NodeId) {
+    let variables = expression_variables(graph, node_id)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+
+    if let Ok(input_id) = graph[node_id].get_input(LABEL_EXPRESSION_IN) {
+        if let ValueType::Expression { value } = &mut graph[input_id].value {
+            value.variables = variables;
+            value.check();
+        }
+    }
+}
+
+/// Binds a new input of the given type to the next free variable name.
+pub fn add_expression_variable(graph: &mut ProcessGraph, node_id: NodeId, typ: DataType) {
+    let count = expression_variables(graph, node_id).len();
+
+    if let Some(name) = EXPRESSION_VARIABLES.get(count) {
+        add_variable_input(graph, node_id, name, typ);
+        update_expression_variables(graph, node_id);
+    }
+}
+
+/// Removes the last variable input of an expression node, keeping at least one.
+pub fn remove_expression_variable(graph: &mut ProcessGraph, node_id: NodeId) {
+    let variables = expression_variables(graph, node_id);
+
+    if let (true, Some((_, input_id))) = (variables.len() > 1, variables.last()) {
+        graph.remove_connection(*input_id);
+        graph.inputs.remove(*input_id);
+        graph[node_id].inputs.retain(|(_, id)| id != input_id);
+
+        update_expression_variables(graph, node_id);
+    }
+}
+
+/// Replaces every variable input of an expression node.
+pub fn set_expression_variables(
+    graph: &mut ProcessGraph,
+    node_id: NodeId,
+    variables: &[(String, DataType)],
+) {
+    for (_, input_id) in expression_variables(graph, node_id) {
+        graph.remove_connection(input_id);
+        graph.inputs.remove(input_id);
+        graph[node_id].inputs.retain(|(_, id)| *id != input_id);
+    }
+
+    for (name, typ) in variables {
+        add_variable_input(graph, node_id, name, *typ);
+    }
+
+    update_expression_variables(graph, node_id);
 }
 
 pub fn evaluate_graph(state: &mut EditorState) {
     // Reset the computed cache & images
     state.user_state.outputs_cache.clear();
-    state.user_state.outputs_images.clear();
+    state.user_state.outputs_thumbnails.clear();
+    state.user_state.node_errors.clear();
+    state.user_state.profiler.clear();
+    state.user_state.evaluation += 1;
+
+    let _span = tracing::info_span!("evaluate_graph").entered();
 
-    // Compute and store the result for each node
-    for (id, _node) in state.graph.nodes.iter() {
-        let _result = evaluate_node(&state.graph, id, &mut state.user_state.outputs_cache);
+    let graph = &state.graph;
+    let user_state = &mut state.user_state;
+
+    // Number of inputs still to be evaluated that read each output, the
+    // outputs nobody is waiting for can be evicted from the cache
+    let mut consumers: HashMap<OutputId, usize> = HashMap::new();
+    for (_, output_id) in graph.connections.iter() {
+        *consumers.entry(*output_id).or_default() += 1;
     }
 
-    // Then process all the resulting image to prepare the rendering
-    for (key, value) in state.user_state.outputs_cache.clone().into_iter() {
-        if let Ok(image) = ValueType::try_to_image(value) {
-            state.user_state.outputs_images.insert(
-                key,
-                RetainedImage::from_color_image(
-                    format!("Retained image for the output {:?}", key),
-                    image,
-                ),
-            );
+    let mut evaluated = HashSet::new();
+
+    // Compute and store the result for each node, once
+    for node_id in evaluation_order(graph) {
+        let sources: Vec<NodeId> = graph[node_id]
+            .inputs
+            .iter()
+            .filter_map(|(_, input_id)| graph.connection(*input_id))
+            .map(|output_id| graph[output_id].node)
+            .collect();
+
+        // The nodes depending on a node that failed are not evaluated, a
+        // node that comes before its own dependencies is part of a cycle
+        let failure = sources.iter().find_map(|source| {
+            if !evaluated.contains(source) {
+                Some("The node is part of a cycle".to_string())
+            } else {
+                user_state.node_errors.get(source).cloned()
+            }
+        });
+
+        let result = match failure {
+            Some(error) => Err(anyhow::anyhow!(error)),
+            None => evaluate_node(
+                graph,
+                node_id,
+                &mut user_state.outputs_cache,
+                &mut user_state.profiler,
+            ),
+        };
+
+        if let Err(error) = result {
+            user_state.node_errors.insert(node_id, error.to_string());
+        }
+
+        evaluated.insert(node_id);
+
+        for (_, input_id) in graph[node_id].inputs.iter() {
+            if let Some(count) = graph
+                .connection(*input_id)
+                .and_then(|output_id| consumers.get_mut(&output_id))
+            {
+                *count -= 1;
+            }
         }
+
+        // The thumbnails are made while the outputs of the node are cached,
+        // before the images that are not needed anymore are evicted
+        make_thumbnails(graph, user_state, node_id);
+        user_state
+            .outputs_cache
+            .trim(|output_id| consumers.get(output_id).map_or(false, |count| *count > 0));
+    }
+}
+
+// Nodes sorted so that each node comes after the nodes connected to its
+// inputs, the nodes of a cycle come before some of their dependencies
+fn evaluation_order(graph: &ProcessGraph) -> Vec<NodeId> {
+    let mut visited = HashSet::new();
+    let mut order = Vec::with_capacity(graph.nodes.len());
+
+    // Depth first search with a stack of the nodes being visited and the
+    // index of their next input, long chains of nodes would overflow the
+    // call stack of a recursive search
+    let mut stack: Vec<(NodeId, usize)> = vec![];
+
+    for (root, _) in graph.nodes.iter() {
+        if visited.insert(root) {
+            stack.push((root, 0));
+        }
+
+        while let Some((node_id, next)) = stack.pop() {
+            match graph[node_id].inputs.get(next) {
+                Some((_, input_id)) => {
+                    stack.push((node_id, next + 1));
+
+                    if let Some(output_id) = graph.connection(*input_id) {
+                        let source = graph[output_id].node;
+                        if visited.insert(source) {
+                            stack.push((source, 0));
+                        }
+                    }
+                }
+                None => order.push(node_id),
+            }
+        }
+    }
+
+    order
+}
+
+// Make the thumbnails displayed in the node, the full resolution images are
+// only uploaded for the outputs being inspected
+fn make_thumbnails(graph: &ProcessGraph, user_state: &mut GraphState, node_id: NodeId) {
+    for (_, output_id) in graph[node_id].outputs.iter() {
+        let name = format!("Thumbnail for the output {:?}", output_id);
+
+        let thumbnail = match user_state.outputs_cache.get(output_id) {
+            Some(ValueType::Image { value }) => Thumbnail::new(name, value),
+            Some(ValueType::FloatImage { image, .. }) => Thumbnail::new(name, image),
+            Some(ValueType::Slice { value }) => Thumbnail::new(name, &value.to_image()),
+            Some(ValueType::ImageFetcher { value }) => Thumbnail::new(name, &value.image),
+            _ => continue,
+        };
+
+        user_state.outputs_thumbnails.insert(*output_id, thumbnail);
+    }
+}
+
+/// Returns the value of an evaluated output, it is computed again if it was
+/// evicted from the cache since
+pub fn output_value(state: &mut EditorState, output_id: OutputId) -> Option<ValueType> {
+    let user_state = &mut state.user_state;
+    let evaluated = user_state.outputs_thumbnails.contains_key(&output_id);
+
+    if evaluated && !user_state.outputs_cache.contains_key(&output_id) {
+        let node_id = state.graph.outputs.get(output_id)?.node;
+        evaluate_node(
+            &state.graph,
+            node_id,
+            &mut user_state.outputs_cache,
+            &mut user_state.profiler,
+        )
+        .ok()?;
+        user_state.outputs_cache.trim(|id| *id == output_id);
+    }
+
+    user_state.outputs_cache.fetch(&output_id).cloned()
+}
+
+/// Free the thumbnails of a deleted node
+pub fn release_outputs(state: &mut EditorState, node: &Node<NodeData>) {
+    for (_, output_id) in node.outputs.iter() {
+        state.user_state.outputs_thumbnails.remove(output_id);
+        state.user_state.outputs_cache.remove(output_id);
     }
 }
 
@@ -619,6 +1770,36 @@ pub fn evaluate_node(
     graph: &ProcessGraph,
     node_id: NodeId,
     outputs_cache: &mut OutputsCache,
+    profiler: &mut Profiler,
+) -> anyhow::Result<ValueType> {
+    let node = &graph[node_id];
+    let _span = tracing::debug_span!("evaluate_node", node = %node.label).entered();
+
+    profiler.start();
+    let result = compute_node(graph, node_id, outputs_cache, profiler);
+
+    let bytes = node
+        .outputs
+        .iter()
+        .map(|(_, output_id)| outputs_cache.bytes(output_id))
+        .sum();
+    let time = profiler.finish(node_id, bytes);
+
+    tracing::debug!(
+        time_ms = time.as_secs_f64() * 1000.0,
+        bytes,
+        failed = result.is_err(),
+        "evaluated node"
+    );
+
+    result
+}
+
+fn compute_node(
+    graph: &ProcessGraph,
+    node_id: NodeId,
+    outputs_cache: &mut OutputsCache,
+    profiler: &mut Profiler,
 ) -> anyhow::Result<ValueType> {
     // To solve a similar problem as creating node types above, we define an
     // Evaluator as a convenience. It may be overkill for this small example,
@@ -628,6 +1809,7 @@ pub fn evaluate_node(
     struct Evaluator<'a> {
         graph: &'a ProcessGraph,
         outputs_cache: &'a mut OutputsCache,
+        profiler: &'a mut Profiler,
         node_id: NodeId,
     }
 
@@ -635,19 +1817,27 @@ pub fn evaluate_node(
         fn new(
             graph: &'a ProcessGraph,
             outputs_cache: &'a mut OutputsCache,
+            profiler: &'a mut Profiler,
             node_id: NodeId,
         ) -> Self {
             Self {
                 graph,
                 outputs_cache,
+                profiler,
                 node_id,
             }
         }
 
-        fn evaluate_input(&mut self, name: &str) -> anyhow::Result<ValueType> {
+        fn evaluate_input(&mut self, name: &str) -> anyhow::Result<&ValueType> {
             // Calling `evaluate_input` recursively evaluates other nodes in the
             // graph until the input value for a paramater has been computed.
-            evaluate_input(self.graph, self.node_id, name, self.outputs_cache)
+            evaluate_input(
+                self.graph,
+                self.node_id,
+                name,
+                self.outputs_cache,
+                self.profiler,
+            )
         }
 
         fn populate_output(&mut self, name: &str, value: ValueType) -> anyhow::Result<ValueType> {
@@ -666,48 +1856,265 @@ pub fn evaluate_node(
             // the graphs, you can come up with your own evaluation semantics!
             populate_output(self.graph, self.outputs_cache, self.node_id, name, value)
         }
-        fn input_image(&mut self, name: &str) -> anyhow::Result<ColorImage> {
+        fn is_connected(&self, name: &str) -> anyhow::Result<bool> {
+            let input_id = self.graph[self.node_id].get_input(name)?;
+            Ok(self.graph.connection(input_id).is_some())
+        }
+        // All the outputs of a node are computed when it is only previewed,
+        // once some of them are connected only those are computed
+        fn wants_output(&self, name: &str) -> anyhow::Result<bool> {
+            let node = &self.graph[self.node_id];
+            let output_id = node.get_output(name)?;
+
+            let connected = |output_id: OutputId| {
+                self.graph
+                    .connections
+                    .iter()
+                    .any(|(_, output)| *output == output_id)
+            };
+
+            Ok(connected(output_id) || !node.outputs.iter().any(|(_, id)| connected(*id)))
+        }
+        fn input_image(&mut self, name: &str) -> anyhow::Result<Arc<ColorImage>> {
             self.evaluate_input(name)?.try_to_image()
         }
+        fn input_precise_image(&mut self, name: &str) -> anyhow::Result<ValueType> {
+            self.evaluate_input(name)?.try_to_precise_image()
+        }
         fn input_slice(
             &mut self,
             name: &str,
             color: Option<SliceColor>,
-        ) -> anyhow::Result<ImageSlice> {
+        ) -> anyhow::Result<Arc<ImageSlice>> {
             self.evaluate_input(name)?.try_to_slice(color)
         }
+        // The values computed by other nodes are brought in the range of the
+        // parameter, as the widgets do for the values edited in the node
         fn input_scalar(&mut self, name: &str) -> anyhow::Result<f32> {
-            self.evaluate_input(name)?.try_to_scalar()
+            let value = self.evaluate_input(name)?.try_to_scalar()?;
+            if !value.is_finite() {
+                anyhow::bail!("The input {} is not a finite number", name);
+            }
+
+            Ok(self.input_info(name)?.clamp(value as f64) as f32)
         }
         fn input_integer(&mut self, name: &str) -> anyhow::Result<i32> {
-            self.evaluate_input(name)?.try_to_integer()
+            let value = self.evaluate_input(name)?.try_to_integer()?;
+            Ok(self.input_info(name)?.clamp(value as f64) as i32)
+        }
+        fn input_info(&self, name: &str) -> anyhow::Result<ParamInfo> {
+            let input_id = self.graph[self.node_id].get_input(name)?;
+            match &self.graph[input_id].value {
+                ValueType::Scalar { info, .. } | ValueType::Integer { info, .. } => Ok(*info),
+                _ => Ok(ParamInfo::default()),
+            }
+        }
+        fn input_color(&mut self, name: &str) -> anyhow::Result<Color32> {
+            self.evaluate_input(name)?.try_to_color()
         }
         fn input_boolean(&mut self, name: &str) -> anyhow::Result<bool> {
             self.evaluate_input(name)?.try_to_boolean()
         }
-        fn output_image(&mut self, name: &str, value: ColorImage) -> anyhow::Result<ValueType> {
+        fn input_text(&mut self, name: &str) -> anyhow::Result<String> {
+            self.evaluate_input(name)?.try_to_text()
+        }
+        fn input_expression(&mut self, name: &str) -> anyhow::Result<ExpressionEditor> {
+            self.evaluate_input(name)?.try_to_expression()
+        }
+        fn input_size(&mut self, name: &str) -> anyhow::Result<usize> {
+            Ok(self.input_integer(name)?.max(0) as usize)
+        }
+        fn input_interpolation(&mut self, name: &str) -> anyhow::Result<Interpolation> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Interpolation(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to interpolation".to_string()),
+            }
+        }
+        fn input_border(&mut self, name: &str) -> anyhow::Result<BorderMode> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Border(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to border mode".to_string()),
+            }
+        }
+        fn input_blend(&mut self, name: &str) -> anyhow::Result<BlendMode> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Blend(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to blend mode".to_string()),
+            }
+        }
+        fn input_alignment(&mut self, name: &str) -> anyhow::Result<Alignment> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Alignment(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to alignment".to_string()),
+            }
+        }
+        fn input_operation(&mut self, name: &str) -> anyhow::Result<ScalarOperation> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Operation(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to operation".to_string()),
+            }
+        }
+        fn input_channel(&mut self, name: &str) -> anyhow::Result<Channel> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Channel(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to channel".to_string()),
+            }
+        }
+        fn input_comparison(&mut self, name: &str) -> anyhow::Result<Comparison> {
+            match self.evaluate_input(name)?.try_to_choice()? {
+                Choice::Comparison(value) => Ok(value),
+                _ => anyhow::bail!("Invalid cast to comparison".to_string()),
+            }
+        }
+        fn output_image(
+            &mut self,
+            name: &str,
+            value: impl Into<Arc<ColorImage>>,
+        ) -> anyhow::Result<ValueType> {
+            let value = value.into();
             self.populate_output(name, ValueType::Image { value })
         }
-        fn output_slice(&mut self, name: &str, value: ImageSlice) -> anyhow::Result<ValueType> {
+        fn output_slice(
+            &mut self,
+            name: &str,
+            value: impl Into<Arc<ImageSlice>>,
+        ) -> anyhow::Result<ValueType> {
+            let value = value.into();
             self.populate_output(name, ValueType::Slice { value })
         }
-        /*
         fn output_scalar(&mut self, name: &str, value: f32) -> anyhow::Result<ValueType> {
-            self.populate_output(name, ValueType::Scalar { value })
+            let info = ParamInfo::default();
+            self.populate_output(name, ValueType::Scalar { value, info })
+        }
+        fn output_integer(&mut self, name: &str, value: i32) -> anyhow::Result<ValueType> {
+            let info = ParamInfo::default();
+            self.populate_output(name, ValueType::Integer { value, info })
+        }
+        fn output_boolean(&mut self, name: &str, value: bool) -> anyhow::Result<ValueType> {
+            self.populate_output(name, ValueType::Boolean { value })
+        }
+        fn output_color(&mut self, name: &str, value: Color32) -> anyhow::Result<ValueType> {
+            self.populate_output(name, ValueType::Color { value })
         }
-        */
     }
 
     let node = &graph[node_id];
-    let mut evaluator = Evaluator::new(graph, outputs_cache, node_id);
-    match node.user_data.template {
+    let mut evaluator = Evaluator::new(graph, outputs_cache, profiler, node_id);
+    match &node.user_data.template {
         NodeTemplate::ImageFetcher => {
+            if let ValueType::ImageFetcher { value } = evaluator.evaluate_input(LABEL_IMAGE_IN)? {
+                if value.refetch {
+                    anyhow::bail!("The image of {} is not fetched yet", value.url)
+                }
+            }
+
+            let image = evaluator.input_precise_image(LABEL_IMAGE_IN)?;
+            evaluator.populate_output(LABEL_INPUT_IMAGE_OUT, image)
+        }
+        NodeTemplate::ImageSequence => {
+            let (image, frame) = match evaluator.evaluate_input(LABEL_SEQUENCE_IN)? {
+                ValueType::ImageSequence { value } if value.files.is_empty() => {
+                    anyhow::bail!("No image file is listed")
+                }
+                value @ ValueType::ImageSequence { value: sequence } => {
+                    (value.try_to_precise_image()?, sequence.frame as i32)
+                }
+                _ => anyhow::bail!("Invalid cast to image sequence"),
+            };
+
+            evaluator.output_integer(LABEL_INTEGER_FRAME_OUT, frame)?;
+            evaluator.populate_output(LABEL_IMAGE_OUT, image)
+        }
+        NodeTemplate::Animation => {
+            let (image, frame) = match evaluator.evaluate_input(LABEL_ANIMATION_IN)? {
+                ValueType::Animation { value } => match value.image() {
+                    Some(image) => (image, value.frame as i32),
+                    None => anyhow::bail!("No animation is loaded"),
+                },
+                _ => anyhow::bail!("Invalid cast to animation"),
+            };
+
+            evaluator.output_integer(LABEL_INTEGER_FRAME_OUT, frame)?;
+            evaluator.output_image(LABEL_IMAGE_OUT, image)
+        }
+        NodeTemplate::SaveImage => {
+            // The image is only written when processing a sequence or an animation
+            let file_name = evaluator.input_text(LABEL_TEXT_FILE_NAME_IN)?;
+            if !is_writable(std::path::Path::new(&file_name)) {
+                anyhow::bail!("Cannot write images in {}", file_name);
+            }
+
+            let image = evaluator.input_precise_image(LABEL_IMAGE_IN)?;
+            evaluator.populate_output(LABEL_IMAGE_OUT, image)
+        }
+        NodeTemplate::ScalarValue => {
+            let value = evaluator.input_scalar(LABEL_SCALAR_VALUE_IN)?;
+            evaluator.output_scalar(LABEL_SCALAR_OUT, value)
+        }
+        NodeTemplate::IntegerValue => {
+            let value = evaluator.input_integer(LABEL_INTEGER_VALUE_IN)?;
+            evaluator.output_integer(LABEL_INTEGER_OUT, value)
+        }
+        NodeTemplate::BooleanValue => {
+            let value = evaluator.input_boolean(LABEL_BOOLEAN_VALUE_IN)?;
+            evaluator.output_boolean(LABEL_BOOLEAN_OUT, value)
+        }
+        NodeTemplate::ColorValue => {
+            let value = evaluator.input_color(LABEL_COLOR_VALUE_IN)?;
+            evaluator.output_color(LABEL_COLOR_OUT, value)
+        }
+        NodeTemplate::ScalarMath => {
+            let a = evaluator.input_scalar(LABEL_SCALAR_A_IN)?;
+            let b = evaluator.input_scalar(LABEL_SCALAR_B_IN)?;
+            let operation = evaluator.input_operation(LABEL_CHOICE_OPERATION_IN)?;
+
+            let result = apply_operation(a, b, operation);
+
+            evaluator.output_integer(LABEL_INTEGER_OUT, result.round() as i32)?;
+            evaluator.output_scalar(LABEL_SCALAR_OUT, result)
+        }
+        NodeTemplate::CompareScalars => {
+            let a = evaluator.input_scalar(LABEL_SCALAR_A_IN)?;
+            let b = evaluator.input_scalar(LABEL_SCALAR_B_IN)?;
+            let comparison = evaluator.input_comparison(LABEL_CHOICE_COMPARISON_IN)?;
+
+            evaluator.output_boolean(LABEL_BOOLEAN_OUT, compare(a, b, comparison))
+        }
+        NodeTemplate::MeanColor => {
             let image = evaluator.input_image(LABEL_IMAGE_IN)?;
-            evaluator.output_image(LABEL_INPUT_IMAGE_OUT, image)
+
+            let color = mean_color(&image);
+
+            evaluator.output_scalar(LABEL_SCALAR_OUT, luminance(color))?;
+            evaluator.output_color(LABEL_COLOR_OUT, color)
+        }
+        NodeTemplate::ImageStatistics => {
+            // The slice is preferred, the channel only applies to images
+            let statistics = if evaluator.is_connected(LABEL_SLICE_S_IN)? {
+                Statistics::of_slice(&*evaluator.input_slice(LABEL_SLICE_S_IN, None)?)
+            } else if evaluator.is_connected(LABEL_IMAGE_IN)? {
+                let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+                let channel = evaluator.input_channel(LABEL_CHOICE_CHANNEL_IN)?;
+
+                Statistics::of_image(&image, channel)
+            } else {
+                anyhow::bail!("Connect an image or a slice to compute its statistics");
+            };
+
+            let percent = evaluator.input_scalar(LABEL_SCALAR_PERCENTILE_IN)?;
+            let percentile = statistics.percentile(percent) as f32;
+
+            evaluator.output_scalar(LABEL_SCALAR_MEAN_OUT, statistics.mean)?;
+            evaluator.output_scalar(LABEL_SCALAR_STD_DEV_OUT, statistics.std_dev)?;
+            evaluator.output_scalar(LABEL_SCALAR_MIN_OUT, statistics.min as f32)?;
+            evaluator.output_scalar(LABEL_SCALAR_MAX_OUT, statistics.max as f32)?;
+            evaluator.output_scalar(LABEL_SCALAR_PERCENTILE_OUT, percentile)?;
+            evaluator.output_scalar(LABEL_SCALAR_ENTROPY_OUT, statistics.entropy)
         }
         NodeTemplate::FourierSpace => {
             let image = evaluator.input_slice(LABEL_SLICE_S_IN, None)?;
 
+            let image = Arc::try_unwrap(image).unwrap_or_else(|image| (*image).clone());
             let computed = fft::mat_fft(image);
 
             evaluator.output_slice(LABEL_SLICE_S_OUT, computed)
@@ -756,19 +2163,26 @@ pub fn evaluate_node(
         NodeTemplate::ImageToSlice => {
             let image = evaluator.input_image(LABEL_IMAGE_IN)?;
 
-            let mut slice_r = ImageSlice::new(SliceColor::Red, image.size);
-            let mut slice_g = ImageSlice::new(SliceColor::Green, image.size);
-            let mut slice_b = ImageSlice::new(SliceColor::Blue, image.size);
+            let channels = [
+                (LABEL_SLICE_R_OUT, SliceColor::Red, 0),
+                (LABEL_SLICE_G_OUT, SliceColor::Green, 1),
+                (LABEL_SLICE_B_OUT, SliceColor::Blue, 2),
+            ];
 
-            for (id, px) in image.pixels.iter().enumerate() {
-                slice_r.pixels[id] = px.r();
-                slice_g.pixels[id] = px.g();
-                slice_b.pixels[id] = px.b();
-            }
+            let mut result = Err(anyhow::anyhow!("The image has no channel to extract"));
+            for (name, color, channel) in channels {
+                if !evaluator.wants_output(name)? {
+                    continue;
+                }
 
-            let _res_r = evaluator.output_slice(LABEL_SLICE_R_OUT, slice_r);
-            let _res_g = evaluator.output_slice(LABEL_SLICE_G_OUT, slice_g);
-            evaluator.output_slice(LABEL_SLICE_B_OUT, slice_b)
+                let mut slice = ImageSlice::new(color, image.size);
+                for (value, px) in slice.pixels.iter_mut().zip(image.pixels.iter()) {
+                    *value = px.to_array()[channel];
+                }
+
+                result = evaluator.output_slice(name, slice);
+            }
+            result
         }
         NodeTemplate::BrightenImage => {
             let image = evaluator.input_image(LABEL_IMAGE_IN)?;
@@ -818,6 +2232,218 @@ pub fn evaluate_node(
 
             evaluator.output_image(LABEL_IMAGE_OUT, rotated)
         }
+        NodeTemplate::ResizeImage => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let width = evaluator.input_size(LABEL_INTEGER_WIDTH_IN)?;
+            let height = evaluator.input_size(LABEL_INTEGER_HEIGHT_IN)?;
+            let interpolation = evaluator.input_interpolation(LABEL_CHOICE_INTERPOLATION_IN)?;
+
+            let resized = resize_image(&image, width, height, interpolation);
+
+            evaluator.output_image(LABEL_IMAGE_OUT, resized)
+        }
+        NodeTemplate::CropImage => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let x = evaluator.input_size(LABEL_INTEGER_X_IN)?;
+            let y = evaluator.input_size(LABEL_INTEGER_Y_IN)?;
+            let width = evaluator.input_size(LABEL_INTEGER_WIDTH_IN)?;
+            let height = evaluator.input_size(LABEL_INTEGER_HEIGHT_IN)?;
+
+            let cropped = crop_image(&image, x, y, width, height);
+
+            evaluator.output_image(LABEL_IMAGE_OUT, cropped)
+        }
+        NodeTemplate::PadImage => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let mut padding = [0; 4];
+            for (value, label) in padding.iter_mut().zip(LABEL_INTEGER_PADDING_IN) {
+                *value = evaluator.input_size(label)?;
+            }
+            let border = evaluator.input_border(LABEL_CHOICE_BORDER_IN)?;
+            let fill = evaluator.input_color(LABEL_COLOR_FILL_IN)?;
+
+            let padded = pad_image(&image, padding, border, fill);
+
+            evaluator.output_image(LABEL_IMAGE_OUT, padded)
+        }
+        NodeTemplate::RotateAngle => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let angle = evaluator.input_scalar(LABEL_SCALAR_ANGLE_IN)?;
+            let expand = evaluator.input_boolean(LABEL_BOOLEAN_EXPAND_IN)?;
+            let interpolation = evaluator.input_interpolation(LABEL_CHOICE_INTERPOLATION_IN)?;
+
+            let rotated = rotate_image_angle(&image, angle, expand, interpolation);
+
+            evaluator.output_image(LABEL_IMAGE_OUT, rotated)
+        }
+        NodeTemplate::AffineWarp => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let mut matrix = [0.0; 6];
+            for (value, label) in matrix.iter_mut().zip(LABEL_SCALAR_MATRIX_IN) {
+                *value = evaluator.input_scalar(label)?;
+            }
+            let interpolation = evaluator.input_interpolation(LABEL_CHOICE_INTERPOLATION_IN)?;
+
+            let warped = affine_warp(&image, matrix, interpolation)?;
+
+            evaluator.output_image(LABEL_IMAGE_OUT, warped)
+        }
+        NodeTemplate::PerspectiveWarp => {
+            let image = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let mut corners = [[0.0; 2]; 4];
+            for (corner, labels) in corners.iter_mut().zip(LABEL_SCALAR_CORNERS_IN) {
+                corner[0] = evaluator.input_scalar(labels[0])?;
+                corner[1] = evaluator.input_scalar(labels[1])?;
+            }
+            let interpolation = evaluator.input_interpolation(LABEL_CHOICE_INTERPOLATION_IN)?;
+
+            let warped = perspective_warp(&image, corners, interpolation)?;
+
+            evaluator.output_image(LABEL_IMAGE_OUT, warped)
+        }
+        NodeTemplate::MedianFilter
+        | NodeTemplate::BilateralFilter
+        | NodeTemplate::NonLocalMeans => {
+            let filter = match node.user_data.template {
+                NodeTemplate::MedianFilter => Denoise::Median {
+                    radius: evaluator.input_size(LABEL_INTEGER_RADIUS_IN)?,
+                },
+                NodeTemplate::BilateralFilter => Denoise::Bilateral {
+                    sigma_spatial: evaluator.input_scalar(LABEL_SCALAR_SIGMA_SPATIAL_IN)?,
+                    sigma_range: evaluator.input_scalar(LABEL_SCALAR_SIGMA_RANGE_IN)?,
+                },
+                _ => Denoise::NonLocalMeans {
+                    strength: evaluator.input_scalar(LABEL_SCALAR_STRENGTH_IN)?,
+                    patch_radius: evaluator.input_size(LABEL_INTEGER_PATCH_IN)?,
+                    search_radius: evaluator.input_size(LABEL_INTEGER_SEARCH_IN)?,
+                },
+            };
+
+            // Either input can be filtered, the other output is derived from it
+            let image_connected = evaluator.is_connected(LABEL_IMAGE_IN)?;
+            let slice_connected = evaluator.is_connected(LABEL_SLICE_S_IN)?;
+
+            if !image_connected && !slice_connected {
+                anyhow::bail!("Connect an image or a slice to denoise");
+            }
+
+            // Both inputs are only filtered when both outputs are used
+            let wants_image = evaluator.wants_output(LABEL_IMAGE_OUT)?;
+            let wants_slice = evaluator.wants_output(LABEL_SLICE_S_OUT)?;
+
+            let image = if image_connected && (wants_image || !slice_connected) {
+                Some(denoise_image(
+                    &*evaluator.input_image(LABEL_IMAGE_IN)?,
+                    filter,
+                ))
+            } else {
+                None
+            };
+            let slice = if slice_connected && (wants_slice || !image_connected) {
+                Some(denoise_slice(
+                    &*evaluator.input_slice(LABEL_SLICE_S_IN, None)?,
+                    filter,
+                ))
+            } else {
+                None
+            };
+
+            let (image, slice) = match (image, slice) {
+                (Some(image), None) => {
+                    let slice = wants_slice.then(|| image_to_gray(&image));
+                    (Some(image), slice)
+                }
+                (None, Some(slice)) => (wants_image.then(|| slice_to_image(&slice)), Some(slice)),
+                (image, slice) => (image, slice),
+            };
+
+            let mut result = Err(anyhow::anyhow!("The filter has no output"));
+            if let (true, Some(slice)) = (wants_slice, slice) {
+                result = evaluator.output_slice(LABEL_SLICE_S_OUT, slice);
+            }
+            if let (true, Some(image)) = (wants_image, image) {
+                result = evaluator.output_image(LABEL_IMAGE_OUT, image);
+            }
+            result
+        }
+        NodeTemplate::BlendImages => {
+            let base = evaluator.input_image(LABEL_IMAGE_IN)?;
+            let layer = evaluator.input_image(LABEL_IMAGE_LAYER_IN)?;
+
+            // The mask is optional, without it the whole layer is blended
+            let mask = if evaluator.is_connected(LABEL_SLICE_MASK_IN)? {
+                Some(evaluator.input_slice(LABEL_SLICE_MASK_IN, None)?)
+            } else {
+                None
+            };
+
+            let opacity = evaluator.input_scalar(LABEL_SCALAR_OPACITY_IN)?;
+            let mode = evaluator.input_blend(LABEL_CHOICE_BLEND_IN)?;
+            let alignment = evaluator.input_alignment(LABEL_CHOICE_ALIGNMENT_IN)?;
+
+            let blended = blend_images(&base, &layer, mask.as_deref(), opacity, mode, alignment);
+
+            evaluator.output_image(LABEL_IMAGE_OUT, blended)
+        }
+        NodeTemplate::Expression => {
+            let expression = evaluator.input_expression(LABEL_EXPRESSION_IN)?.parse()?;
+
+            let mut operands = vec![];
+            for (name, input_id) in expression_variables(graph, node_id) {
+                // Unconnected images and slices have no size, they are read as zero
+                let operand = match graph[input_id].typ {
+                    DataType::Image if evaluator.is_connected(&name)? => {
+                        Operand::Image(evaluator.input_image(&name)?)
+                    }
+                    DataType::Slice if evaluator.is_connected(&name)? => {
+                        Operand::Slice(evaluator.input_slice(&name, None)?)
+                    }
+                    DataType::Scalar => Operand::Scalar(evaluator.input_scalar(&name)?),
+                    _ => Operand::Scalar(0.0),
+                };
+                operands.push(operand);
+            }
+
+            let size = match operands_size(&operands) {
+                Some(size) => size,
+                None => anyhow::bail!("Connect an image or a slice to evaluate the expression"),
+            };
+
+            let mut result = Err(anyhow::anyhow!("The expression has no output"));
+            if evaluator.wants_output(LABEL_SLICE_S_OUT)? {
+                let slice = evaluate_slice(&expression, &operands, size);
+                result = evaluator.output_slice(LABEL_SLICE_S_OUT, slice);
+            }
+            if evaluator.wants_output(LABEL_IMAGE_OUT)? {
+                let image = evaluate_image(&expression, &operands, size);
+                result = evaluator.output_image(LABEL_IMAGE_OUT, image);
+            }
+            result
+        }
+        NodeTemplate::Group(group) => {
+            let mut connections = HashMap::new();
+            let mut constants = HashMap::new();
+
+            for port in group.inputs.iter().chain(&group.parameters) {
+                let value = evaluator.evaluate_input(&port.name)?.clone();
+
+                if evaluator.is_connected(&port.name)? {
+                    connections.insert(port.name.clone(), value);
+                } else {
+                    constants.insert(port.name.clone(), value);
+                }
+            }
+
+            let mut result = Err(anyhow::anyhow!("The group has no output"));
+            let outputs = group.evaluate(&node.user_data.group, &connections, &constants)?;
+            for (name, value) in outputs {
+                result = Ok(evaluator.populate_output(&name, value)?);
+            }
+            result
+        }
+        NodeTemplate::GroupInputs => {
+            anyhow::bail!("The inputs of a group are given by the group node")
+        }
     }
 }
 
@@ -834,37 +2460,126 @@ fn populate_output(
     Ok(value)
 }
 
-// Evaluates the input value of
-fn evaluate_input(
-    graph: &ProcessGraph,
+// Evaluates the input value of a node, the value is borrowed from the cache
+// or from the graph instead of being copied
+fn evaluate_input<'a>(
+    graph: &'a ProcessGraph,
     node_id: NodeId,
     param_name: &str,
-    outputs_cache: &mut OutputsCache,
-) -> anyhow::Result<ValueType> {
+    outputs_cache: &'a mut OutputsCache,
+    profiler: &mut Profiler,
+) -> anyhow::Result<&'a ValueType> {
     let input_id = graph[node_id].get_input(param_name)?;
 
     // The output of another node is connected.
     if let Some(other_output_id) = graph.connection(input_id) {
-        // The value was already computed due to the evaluation of some other
-        // node. We simply return value from the cache.
-        if let Some(other_value) = outputs_cache.get(&other_output_id) {
-            Ok(other_value.clone())
+        let other_node_id = graph[other_output_id].node;
+
+        // The nodes are evaluated after the nodes they depend on, so the
+        // value is usually in the cache already.
+        if outputs_cache.contains_key(&other_output_id) {
+            profiler.cache_hit(other_node_id);
         }
-        // This is the first time encountering this node, so we need to
-        // recursively evaluate it.
+        // Otherwise it was evicted, or the node is evaluated on its own, so
+        // we need to recursively evaluate the other node.
         else {
             // Calling this will populate the cache
-            evaluate_node(graph, graph[other_output_id].node, outputs_cache)?;
+            evaluate_node(graph, other_node_id, outputs_cache, profiler)?;
+        }
 
-            // Now that we know the value is cached, return it
-            Ok(outputs_cache
-                .get(&other_output_id)
-                .expect("Cache should be populated")
-                .clone())
+        match outputs_cache.fetch(&other_output_id) {
+            Some(value) => Ok(value),
+            None => anyhow::bail!("The input {} could not be computed", param_name),
         }
     }
     // No existing connection, take the inline value instead.
     else {
-        Ok(graph[input_id].value.clone())
+        Ok(&graph[input_id].value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::math::expression::PIXEL_VARIABLES;
+
+    #[test]
+    fn expression_variables_do_not_shadow_the_pixel_variables() {
+        for name in EXPRESSION_VARIABLES {
+            assert!(
+                !PIXEL_VARIABLES.contains(&name),
+                "{} is a pixel variable",
+                name
+            );
+        }
+    }
+
+    fn add_node(graph: &mut ProcessGraph, template: NodeTemplate) -> NodeId {
+        graph.add_node(
+            template.node_graph_label(),
+            template.user_data(),
+            |graph, node_id| template.build_node(graph, &GraphState::default(), node_id),
+        )
+    }
+
+    // A node reading a slice, to connect the outputs of the tested node
+    fn add_consumer(graph: &mut ProcessGraph, output_id: OutputId) {
+        let node_id = add_node(graph, NodeTemplate::SliceToImage);
+        let input_id = graph[node_id].get_input(LABEL_SLICE_R_IN).unwrap();
+        graph.add_connection(output_id, input_id);
+    }
+
+    #[test]
+    fn evaluation_order_handles_long_chains() {
+        let mut graph = ProcessGraph::new();
+
+        let mut previous = add_node(&mut graph, NodeTemplate::GrayScales);
+        for _ in 0..100_000 {
+            let node_id = add_node(&mut graph, NodeTemplate::FourierSpace);
+            let output_id = graph[previous].get_output(LABEL_SLICE_S_OUT).unwrap();
+            let input_id = graph[node_id].get_input(LABEL_SLICE_S_IN).unwrap();
+            graph.add_connection(output_id, input_id);
+            previous = node_id;
+        }
+
+        let order = evaluation_order(&graph);
+        assert_eq!(order.len(), graph.nodes.len());
+
+        let position: HashMap<NodeId, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (*id, index))
+            .collect();
+        for (input_id, output_id) in graph.connections.iter() {
+            let source = graph[*output_id].node;
+            let target = graph[input_id].node;
+            assert!(position[&source] < position[&target]);
+        }
+    }
+
+    #[test]
+    fn image_to_slice_only_computes_the_connected_channels() {
+        let mut graph = ProcessGraph::new();
+        let node_id = add_node(&mut graph, NodeTemplate::ImageToSlice);
+        let output = |graph: &ProcessGraph, name| graph[node_id].get_output(name).unwrap();
+        let red = output(&graph, LABEL_SLICE_R_OUT);
+        let green = output(&graph, LABEL_SLICE_G_OUT);
+        let blue = output(&graph, LABEL_SLICE_B_OUT);
+
+        // Every channel is previewed while none is connected
+        let mut cache = OutputsCache::default();
+        let mut profiler = Profiler::default();
+        evaluate_node(&graph, node_id, &mut cache, &mut profiler).unwrap();
+        assert!(cache.contains_key(&red));
+        assert!(cache.contains_key(&green));
+        assert!(cache.contains_key(&blue));
+
+        add_consumer(&mut graph, green);
+
+        let mut cache = OutputsCache::default();
+        evaluate_node(&graph, node_id, &mut cache, &mut profiler).unwrap();
+        assert!(cache.contains_key(&green));
+        assert!(!cache.contains_key(&red));
+        assert!(!cache.contains_key(&blue));
     }
 }
@@ -0,0 +1,230 @@
+use egui::{DragValue, Slider};
+
+/// How a numeric parameter is edited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamWidget {
+    Drag,
+    Slider,
+    /// A dial next to the value, in degrees
+    Angle,
+}
+
+/// Range, step, default value and unit of a numeric parameter. It is declared
+/// when building the node and kept along the value of the parameter, so that
+/// the node and the inspector display the same widget.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamInfo {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+    pub default: f64,
+    pub unit: &'static str,
+    pub widget: ParamWidget,
+}
+
+/// What happened to a parameter while its widget was displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParamEdit {
+    /// The value changed, the graph is computed again
+    pub changed: bool,
+    /// The value is not dragged or typed anymore, the edit is recorded
+    pub done: bool,
+}
+
+impl ParamEdit {
+    /// The value changes while the widgets are dragged or typed in, the edit
+    /// is done once they are released or lose the focus
+    pub fn of(ui: &egui::Ui, id: egui::Id, responses: &[&egui::Response]) -> Self {
+        let changed = responses.iter().any(|response| response.changed());
+        let released = responses
+            .iter()
+            .any(|response| response.drag_released() || response.lost_focus());
+
+        // Releasing a widget without changing its value is not an edit
+        let id = id.with("pending edit");
+        let pending = ui.data().get_temp::<bool>(id).unwrap_or(false) || changed;
+        ui.data().insert_temp(id, pending && !released);
+
+        Self {
+            changed,
+            done: pending && released,
+        }
+    }
+}
+
+impl Default for ParamInfo {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+impl ParamInfo {
+    /// An unbounded parameter edited by dragging
+    pub fn new(default: f64) -> Self {
+        Self {
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+            step: 0.1,
+            default,
+            unit: "",
+            widget: ParamWidget::Drag,
+        }
+    }
+
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn step(mut self, step: f64) -> Self {
+        self.step = step;
+        self
+    }
+
+    pub fn unit(mut self, unit: &'static str) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Brings a value in the range of the parameter
+    pub fn clamp(&self, value: f64) -> f64 {
+        value.max(self.min).min(self.max)
+    }
+
+    /// Edit with a slider, the range must be bounded
+    pub fn slider(mut self) -> Self {
+        self.widget = ParamWidget::Slider;
+        self
+    }
+
+    /// Edit with an angle dial, the value is in degrees
+    pub fn angle(mut self) -> Self {
+        self.widget = ParamWidget::Angle;
+        self.unit = "°";
+        self
+    }
+
+    /// Display the widget of a scalar
+    pub fn show_scalar(&self, ui: &mut egui::Ui, value: &mut f32) -> ParamEdit {
+        let mut dial = None;
+
+        if self.widget == ParamWidget::Angle {
+            let mut degrees = *value as f64;
+            let response = angle_dial(ui, &mut degrees);
+
+            if response.changed() {
+                *value = self.wrap_angle(degrees) as f32;
+            }
+            dial = Some(response);
+        }
+
+        let response = match self.widget {
+            ParamWidget::Slider => ui.add(
+                Slider::new(value, self.min as f32..=self.max as f32)
+                    .step_by(self.step)
+                    .suffix(self.unit),
+            ),
+            _ => ui.add(
+                DragValue::new(value)
+                    .speed(self.step)
+                    .clamp_range(self.min..=self.max)
+                    .suffix(self.unit),
+            ),
+        };
+
+        match &dial {
+            Some(dial) => ParamEdit::of(ui, response.id, &[&response, dial]),
+            None => ParamEdit::of(ui, response.id, &[&response]),
+        }
+    }
+
+    /// Display the widget of an integer
+    pub fn show_integer(&self, ui: &mut egui::Ui, value: &mut i32) -> ParamEdit {
+        let step = self.step.max(1.0).round();
+
+        let response = match self.widget {
+            ParamWidget::Slider => ui.add(
+                Slider::new(value, self.min as i32..=self.max as i32)
+                    .step_by(step)
+                    .suffix(self.unit),
+            ),
+            _ => ui.add(
+                DragValue::new(value)
+                    .speed(step)
+                    .clamp_range(self.min..=self.max)
+                    .suffix(self.unit),
+            ),
+        };
+
+        ParamEdit::of(ui, response.id, &[&response])
+    }
+
+    // Bring an angle of the dial in the range of the parameter
+    fn wrap_angle(&self, mut degrees: f64) -> f64 {
+        if self.max - self.min >= 360.0 {
+            while degrees < self.min {
+                degrees += 360.0;
+            }
+            while degrees > self.max {
+                degrees -= 360.0;
+            }
+        }
+
+        degrees.clamp(self.min, self.max)
+    }
+}
+
+// A small dial pointing in the direction of the angle, clockwise from the
+// right as the images are rotated. Dragging it points the dial toward the
+// cursor.
+fn angle_dial(ui: &mut egui::Ui, degrees: &mut f64) -> egui::Response {
+    let size = ui.spacing().interact_size.y;
+    let (rect, mut response) =
+        ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::click_and_drag());
+
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let delta = pointer - rect.center();
+
+        if delta.length() > 1.0 {
+            *degrees = delta.y.atan2(delta.x).to_degrees() as f64;
+            response.mark_changed();
+        }
+    }
+
+    if ui.is_rect_visible(rect) {
+        let visuals = ui.style().interact(&response);
+        let radius = rect.width() / 2.0;
+        let radians = degrees.to_radians() as f32;
+        let direction = egui::vec2(radians.cos(), radians.sin());
+
+        let painter = ui.painter();
+        painter.circle(
+            rect.center(),
+            radius - 1.0,
+            visuals.bg_fill,
+            visuals.fg_stroke,
+        );
+        painter.line_segment(
+            [rect.center(), rect.center() + direction * (radius - 2.0)],
+            visuals.fg_stroke,
+        );
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_keeps_values_in_the_range() {
+        let radius = ParamInfo::new(2.0).range(0.0, 20.0);
+
+        assert_eq!(radius.clamp(-3.0), 0.0);
+        assert_eq!(radius.clamp(5.0), 5.0);
+        assert_eq!(radius.clamp(1e9), 20.0);
+        assert_eq!(ParamInfo::new(0.0).clamp(-1e9), -1e9);
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use egui_node_graph::NodeId;
+use instant::Instant;
+
+/// What the evaluation of a node cost during the last evaluation of the graph
+#[derive(Clone, Copy, Default)]
+pub struct NodeProfile {
+    /// Time spent computing the node itself, without its dependencies
+    pub time: Duration,
+    /// Memory taken by the images and slices of its outputs, in bytes
+    pub bytes: usize,
+    /// Number of times the node was computed
+    pub evaluations: usize,
+    /// Number of times its outputs were read from the cache instead
+    pub cache_hits: usize,
+}
+
+/// Records the cost of the nodes while the graph is evaluated. The nodes
+/// evaluate their dependencies while they are computed, so the time spent in
+/// the dependencies is subtracted from the time of the node reading them.
+#[derive(Default)]
+pub struct Profiler {
+    pub nodes: HashMap<NodeId, NodeProfile>,
+    // Start of the nodes being computed, with the time spent in their dependencies
+    running: Vec<(Instant, Duration)>,
+}
+
+impl Profiler {
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.running.clear();
+    }
+
+    pub fn start(&mut self) {
+        self.running.push((Instant::now(), Duration::ZERO));
+    }
+
+    /// Record the end of the evaluation of a node, returns its own time
+    pub fn finish(&mut self, node_id: NodeId, bytes: usize) -> Duration {
+        let (start, dependencies) = self.running.pop().expect("The node should be started");
+        let elapsed = start.elapsed();
+
+        if let Some((_, parent_dependencies)) = self.running.last_mut() {
+            *parent_dependencies += elapsed;
+        }
+
+        let time = elapsed.saturating_sub(dependencies);
+
+        let profile = self.nodes.entry(node_id).or_default();
+        profile.time += time;
+        profile.bytes = bytes;
+        profile.evaluations += 1;
+
+        time
+    }
+
+    pub fn cache_hit(&mut self, node_id: NodeId) {
+        self.nodes.entry(node_id).or_default().cache_hits += 1;
+    }
+
+    pub fn get(&self, node_id: NodeId) -> Option<&NodeProfile> {
+        self.nodes.get(&node_id)
+    }
+
+    /// Time spent computing all the nodes
+    pub fn total(&self) -> Duration {
+        self.nodes.values().map(|profile| profile.time).sum()
+    }
+}
@@ -0,0 +1,477 @@
+use egui_node_graph::{DataTypeTrait, Graph, NodeTemplateTrait};
+
+use crate::app::components::graph::node::*;
+use crate::app::components::graph::utils::fuzzy_score;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Input,
+    Value,
+    Convert,
+    Process,
+    Transform,
+    Denoise,
+    Composite,
+    Output,
+}
+
+impl Category {
+    pub const ALL: [Category; 8] = [
+        Category::Input,
+        Category::Value,
+        Category::Convert,
+        Category::Process,
+        Category::Transform,
+        Category::Denoise,
+        Category::Composite,
+        Category::Output,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Input => "🖼 Input",
+            Category::Value => "🔢 Value",
+            Category::Convert => "↔ Convert",
+            Category::Process => "＃ Process",
+            Category::Transform => "📐 Transform",
+            Category::Denoise => "✨ Denoise",
+            Category::Composite => "🎨 Composite",
+            Category::Output => "💾 Output",
+        }
+    }
+}
+
+/// Everything the interface shows about a built-in node
+pub struct NodeDescription {
+    pub template: NodeTemplate,
+    pub category: Category,
+    pub icon: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// Every built-in node, in the order they are listed. Adding a node to this
+/// list makes it available in the side panel, the node finder and the docs.
+pub const NODES: &[NodeDescription] = &[
+    NodeDescription {
+        template: NodeTemplate::ImageFetcher,
+        category: Category::Input,
+        icon: "🔃",
+        name: "Image fetcher",
+        description: "Download an image from an URL",
+    },
+    NodeDescription {
+        template: NodeTemplate::ImageSequence,
+        category: Category::Input,
+        icon: "🎞",
+        name: "Image sequence",
+        description: "The image files of a folder or a pattern, one frame at a time",
+    },
+    NodeDescription {
+        template: NodeTemplate::Animation,
+        category: Category::Input,
+        icon: "🎬",
+        name: "Animation",
+        description: "The frames of an animated GIF or PNG, or of a Y4M video",
+    },
+    NodeDescription {
+        template: NodeTemplate::ScalarValue,
+        category: Category::Value,
+        icon: "🔢",
+        name: "Scalar",
+        description: "A constant scalar to connect to parameters",
+    },
+    NodeDescription {
+        template: NodeTemplate::IntegerValue,
+        category: Category::Value,
+        icon: "#",
+        name: "Integer",
+        description: "A constant integer to connect to parameters",
+    },
+    NodeDescription {
+        template: NodeTemplate::BooleanValue,
+        category: Category::Value,
+        icon: "☑",
+        name: "Boolean",
+        description: "A constant boolean to connect to parameters",
+    },
+    NodeDescription {
+        template: NodeTemplate::ColorValue,
+        category: Category::Value,
+        icon: "🖌",
+        name: "Color",
+        description: "A constant color to connect to parameters",
+    },
+    NodeDescription {
+        template: NodeTemplate::ScalarMath,
+        category: Category::Value,
+        icon: "➕",
+        name: "Scalar Math",
+        description: "Combine two scalars, the result is also given rounded",
+    },
+    NodeDescription {
+        template: NodeTemplate::CompareScalars,
+        category: Category::Value,
+        icon: "⚖",
+        name: "Compare Scalars",
+        description: "Compare two scalars into a boolean",
+    },
+    NodeDescription {
+        template: NodeTemplate::MeanColor,
+        category: Category::Value,
+        icon: "💧",
+        name: "Mean Color",
+        description: "Average color and luminance of an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::ImageStatistics,
+        category: Category::Value,
+        icon: "📊",
+        name: "Image Statistics",
+        description: "Mean, deviation, extrema, percentile and entropy of a channel",
+    },
+    NodeDescription {
+        template: NodeTemplate::GrayScales,
+        category: Category::Convert,
+        icon: "▓",
+        name: "Gray scales",
+        description: "Convert an image to a gray slice",
+    },
+    NodeDescription {
+        template: NodeTemplate::ImageToSlice,
+        category: Category::Convert,
+        icon: "✖",
+        name: "Image to RGB Slice",
+        description: "Split an image into its red, green and blue slices",
+    },
+    NodeDescription {
+        template: NodeTemplate::SliceToImage,
+        category: Category::Convert,
+        icon: "➗",
+        name: "RGB Slice to Image",
+        description: "Merge red, green and blue slices into an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::GaussianBlur,
+        category: Category::Process,
+        icon: "👓",
+        name: "Gaussian blur",
+        description: "Blur an image with a gaussian kernel of the given sigma",
+    },
+    NodeDescription {
+        template: NodeTemplate::FourierSpace,
+        category: Category::Process,
+        icon: "〰",
+        name: "Fourier space",
+        description: "Display the frequency spectrum of a slice",
+    },
+    NodeDescription {
+        template: NodeTemplate::BrightenImage,
+        category: Category::Process,
+        icon: "🌕",
+        name: "Brighten Image",
+        description: "Add a constant to every channel of an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::ContrastImage,
+        category: Category::Process,
+        icon: "🌗",
+        name: "Contrast Image",
+        description: "Increase or decrease the contrast of an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::InvertImage,
+        category: Category::Process,
+        icon: "🔅",
+        name: "Invert Image",
+        description: "Invert the colors of an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::HueRotate,
+        category: Category::Process,
+        icon: "🌈",
+        name: "Hue Rotate",
+        description: "Rotate the hue of an image by the given angle in degrees",
+    },
+    NodeDescription {
+        template: NodeTemplate::FlipImage,
+        category: Category::Process,
+        icon: "↪",
+        name: "Flip Image",
+        description: "Mirror an image horizontally and/or vertically",
+    },
+    NodeDescription {
+        template: NodeTemplate::RotateImage,
+        category: Category::Process,
+        icon: "⟳",
+        name: "Rotate Image",
+        description: "Rotate an image by quarter turns",
+    },
+    NodeDescription {
+        template: NodeTemplate::ResizeImage,
+        category: Category::Transform,
+        icon: "🔍",
+        name: "Resize Image",
+        description: "Scale an image, a null dimension keeps the aspect ratio",
+    },
+    NodeDescription {
+        template: NodeTemplate::CropImage,
+        category: Category::Transform,
+        icon: "✂",
+        name: "Crop Image",
+        description: "Keep a rectangular region, editable on the preview",
+    },
+    NodeDescription {
+        template: NodeTemplate::PadImage,
+        category: Category::Transform,
+        icon: "🔲",
+        name: "Pad Image",
+        description: "Add borders around an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::RotateAngle,
+        category: Category::Transform,
+        icon: "🔄",
+        name: "Rotate by Angle",
+        description: "Rotate an image by any angle, optionally expanding it",
+    },
+    NodeDescription {
+        template: NodeTemplate::AffineWarp,
+        category: Category::Transform,
+        icon: "🔀",
+        name: "Affine Warp",
+        description: "Apply a 2x3 affine matrix to an image",
+    },
+    NodeDescription {
+        template: NodeTemplate::PerspectiveWarp,
+        category: Category::Transform,
+        icon: "🔳",
+        name: "Perspective Warp",
+        description: "Map the corners of an image to new positions",
+    },
+    NodeDescription {
+        template: NodeTemplate::MedianFilter,
+        category: Category::Denoise,
+        icon: "〽",
+        name: "Median Filter",
+        description: "Replace each pixel by the median of its neighbourhood",
+    },
+    NodeDescription {
+        template: NodeTemplate::BilateralFilter,
+        category: Category::Denoise,
+        icon: "🔆",
+        name: "Bilateral Filter",
+        description: "Smooth an image while preserving its edges",
+    },
+    NodeDescription {
+        template: NodeTemplate::NonLocalMeans,
+        category: Category::Denoise,
+        icon: "🧩",
+        name: "Non-local Means",
+        description: "Average the pixels whose surroundings look alike",
+    },
+    NodeDescription {
+        template: NodeTemplate::BlendImages,
+        category: Category::Composite,
+        icon: "🎨",
+        name: "Blend Images",
+        description: "Blend a layer over an image, with an optional mask",
+    },
+    NodeDescription {
+        template: NodeTemplate::Expression,
+        category: Category::Composite,
+        icon: "🖩",
+        name: "Expression",
+        description: "Compute each pixel from a formula of the inputs",
+    },
+    NodeDescription {
+        template: NodeTemplate::SaveImage,
+        category: Category::Output,
+        icon: "💾",
+        name: "Save image",
+        description: "Write the image of each frame, the .gif files keep all the frames",
+    },
+];
+
+/// Returns the description of a built-in node
+pub fn describe(template: &NodeTemplate) -> Option<&'static NodeDescription> {
+    NODES
+        .iter()
+        .find(|node| std::mem::discriminant(&node.template) == std::mem::discriminant(template))
+}
+
+/// Returns the built-in nodes matching the search, best matches first
+pub fn search(query: &str) -> Vec<&'static NodeDescription> {
+    let mut matches: Vec<(i32, &NodeDescription)> = NODES
+        .iter()
+        .filter_map(|node| {
+            let name = fuzzy_score(query, node.name);
+            let description = fuzzy_score(query, node.description).map(|score| score / 2);
+
+            name.max(description).map(|score| (score, node))
+        })
+        .collect();
+
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, node)| node).collect()
+}
+
+/// Names and types of the parameters of a node
+pub type Ports = Vec<(String, String)>;
+
+/// Returns the names and types of the inputs and outputs of a node
+pub fn ports(template: &NodeTemplate) -> (Ports, Ports) {
+    let mut graph: ProcessGraph = Graph::new();
+    let user_state = GraphState::default();
+
+    let node_id = graph.add_node(
+        template.node_graph_label(),
+        template.user_data(),
+        |graph, node_id| template.build_node(graph, &user_state, node_id),
+    );
+
+    let node = &graph[node_id];
+
+    let inputs = node
+        .inputs
+        .iter()
+        .map(|(name, id)| (name.clone(), graph[*id].typ.name().into()))
+        .collect();
+    let outputs = node
+        .outputs
+        .iter()
+        .map(|(name, id)| (name.clone(), graph[*id].typ.name().into()))
+        .collect();
+
+    (inputs, outputs)
+}
+
+/// Display the documentation of every built-in node
+pub fn show_documentation(ui: &mut egui::Ui) {
+    for category in Category::ALL {
+        egui::CollapsingHeader::new(category.label())
+            .default_open(true)
+            .show(ui, |ui| {
+                for node in NODES.iter().filter(|node| node.category == category) {
+                    ui.strong(format!("{} {}", node.icon, node.name));
+                    ui.label(node.description);
+
+                    let (inputs, outputs) = ports(&node.template);
+                    for (name, typ) in inputs {
+                        ui.weak(format!("  ⮊ {} ({})", name, typ));
+                    }
+                    for (name, typ) in outputs {
+                        ui.weak(format!("  ⮈ {} ({})", name, typ));
+                    }
+
+                    ui.add_space(4.0);
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The templates of the built-in nodes, the match below stops compiling
+    // when a template is added until it is listed here
+    const BUILT_IN: [NodeTemplate; 34] = [
+        NodeTemplate::ImageFetcher,
+        NodeTemplate::ImageSequence,
+        NodeTemplate::Animation,
+        NodeTemplate::ScalarValue,
+        NodeTemplate::IntegerValue,
+        NodeTemplate::BooleanValue,
+        NodeTemplate::ColorValue,
+        NodeTemplate::ScalarMath,
+        NodeTemplate::CompareScalars,
+        NodeTemplate::MeanColor,
+        NodeTemplate::ImageStatistics,
+        NodeTemplate::GrayScales,
+        NodeTemplate::ImageToSlice,
+        NodeTemplate::SliceToImage,
+        NodeTemplate::GaussianBlur,
+        NodeTemplate::FourierSpace,
+        NodeTemplate::BrightenImage,
+        NodeTemplate::ContrastImage,
+        NodeTemplate::InvertImage,
+        NodeTemplate::HueRotate,
+        NodeTemplate::FlipImage,
+        NodeTemplate::RotateImage,
+        NodeTemplate::ResizeImage,
+        NodeTemplate::CropImage,
+        NodeTemplate::PadImage,
+        NodeTemplate::RotateAngle,
+        NodeTemplate::AffineWarp,
+        NodeTemplate::PerspectiveWarp,
+        NodeTemplate::MedianFilter,
+        NodeTemplate::BilateralFilter,
+        NodeTemplate::NonLocalMeans,
+        NodeTemplate::BlendImages,
+        NodeTemplate::Expression,
+        NodeTemplate::SaveImage,
+    ];
+
+    fn is_built_in(template: &NodeTemplate) -> bool {
+        match template {
+            NodeTemplate::Group(_) | NodeTemplate::GroupInputs => false,
+            NodeTemplate::ImageFetcher
+            | NodeTemplate::ImageSequence
+            | NodeTemplate::Animation
+            | NodeTemplate::ScalarValue
+            | NodeTemplate::IntegerValue
+            | NodeTemplate::BooleanValue
+            | NodeTemplate::ColorValue
+            | NodeTemplate::ScalarMath
+            | NodeTemplate::CompareScalars
+            | NodeTemplate::MeanColor
+            | NodeTemplate::ImageStatistics
+            | NodeTemplate::GrayScales
+            | NodeTemplate::ImageToSlice
+            | NodeTemplate::SliceToImage
+            | NodeTemplate::GaussianBlur
+            | NodeTemplate::FourierSpace
+            | NodeTemplate::BrightenImage
+            | NodeTemplate::ContrastImage
+            | NodeTemplate::InvertImage
+            | NodeTemplate::HueRotate
+            | NodeTemplate::FlipImage
+            | NodeTemplate::RotateImage
+            | NodeTemplate::ResizeImage
+            | NodeTemplate::CropImage
+            | NodeTemplate::PadImage
+            | NodeTemplate::RotateAngle
+            | NodeTemplate::AffineWarp
+            | NodeTemplate::PerspectiveWarp
+            | NodeTemplate::MedianFilter
+            | NodeTemplate::BilateralFilter
+            | NodeTemplate::NonLocalMeans
+            | NodeTemplate::BlendImages
+            | NodeTemplate::Expression
+            | NodeTemplate::SaveImage => true,
+        }
+    }
+
+    #[test]
+    fn every_built_in_template_is_registered_once() {
+        for template in &BUILT_IN {
+            assert!(is_built_in(template));
+            assert!(
+                describe(template).is_some(),
+                "{:?} is not registered",
+                std::mem::discriminant(template)
+            );
+        }
+
+        assert_eq!(NODES.len(), BUILT_IN.len());
+        assert!(NODES.iter().all(|node| is_built_in(&node.template)));
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (index, node) in NODES.iter().enumerate() {
+            assert!(NODES[..index].iter().all(|other| other.name != node.name));
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use egui::epaint::ColorImage;
+
+use crate::app::math::animation::{self, Frame};
+
+/// The frames of an animated GIF or PNG, or of a Y4M video, played in the node
+pub struct Animation {
+    pub path: String,
+    pub frames: Vec<Frame>,
+    pub frame: usize,
+    pub playing: bool,
+
+    /// Format detected when decoding the file
+    pub format: Option<&'static str>,
+
+    // Time at which the current frame was displayed while playing
+    shown: f64,
+
+    /// Why the file could not be decoded
+    pub error: Option<String>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            frames: vec![],
+            frame: 0,
+            playing: false,
+            format: None,
+            shown: 0.0,
+            error: None,
+        }
+    }
+}
+
+impl Clone for Animation {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            frames: self.frames.clone(),
+            frame: self.frame,
+            playing: false,
+            format: self.format,
+            shown: 0.0,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl Animation {
+    /// Returns true when another frame is displayed
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            let path = ui.add(
+                egui::TextEdit::singleline(&mut self.path)
+                    .hint_text("capture.gif")
+                    .desired_width(f32::INFINITY),
+            );
+
+            if path.lost_focus() {
+                changed |= self.load();
+            }
+            if ui
+                .button("🔃")
+                .on_hover_text("Read the file again")
+                .clicked()
+            {
+                changed |= self.load();
+            }
+        });
+
+        if !self.frames.is_empty() {
+            let last = self.frames.len() - 1;
+            let mut frame = self.frame;
+            let now = ui.input().time;
+
+            ui.horizontal(|ui| {
+                let play = if self.playing { "⏸" } else { "⏵" };
+                if ui.button(play).clicked() {
+                    self.playing = !self.playing;
+                    self.shown = now;
+                }
+
+                ui.add(egui::Slider::new(&mut frame, 0..=last).show_value(false));
+                ui.label(format!("{} / {}", self.frame + 1, self.frames.len()));
+            });
+
+            // The frames are skipped when the graph takes longer than their delay
+            if self.playing && now - self.shown >= self.frames[self.frame].delay as f64 {
+                frame = (self.frame + 1) % self.frames.len();
+                self.shown = now;
+            }
+            if self.playing {
+                ui.ctx().request_repaint();
+            }
+
+            if frame != self.frame {
+                self.frame = frame;
+                changed = true;
+            }
+
+            ui.weak(format!("{:.2} / {:.2} s", self.time(), self.duration()));
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        changed
+    }
+
+    /// Decode the frames of the file, returns true when it could be read
+    pub fn load(&mut self) -> bool {
+        self.playing = false;
+
+        match animation::load_animation_file(Path::new(&self.path)) {
+            Ok(decoded) => {
+                self.frames = decoded.frames;
+                self.format = Some(decoded.format);
+                self.frame = 0;
+                self.error = None;
+                true
+            }
+            Err(error) => {
+                self.frames.clear();
+                self.error = Some(error);
+                false
+            }
+        }
+    }
+
+    pub fn image(&self) -> Option<Arc<ColorImage>> {
+        self.frames.get(self.frame).map(|frame| frame.image.clone())
+    }
+
+    /// Seconds from the start to the current frame
+    pub fn time(&self) -> f32 {
+        self.frames[..self.frame]
+            .iter()
+            .map(|frame| frame.delay)
+            .sum()
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.delay).sum()
+    }
+}
@@ -0,0 +1,81 @@
+use crate::app::math::expression::{self, Expr, ParseError, PIXEL_VARIABLES};
+
+#[derive(Clone)]
+pub struct ExpressionEditor {
+    pub source: String,
+
+    /// Names of the variables bound to the node inputs
+    pub variables: Vec<String>,
+
+    /// Last parse error, displayed under the text field
+    pub error: Option<String>,
+}
+
+impl Default for ExpressionEditor {
+    fn default() -> Self {
+        Self {
+            source: "a".to_string(),
+            variables: vec![],
+            error: None,
+        }
+    }
+}
+
+impl ExpressionEditor {
+    pub fn new(source: &str, variables: &[&str]) -> Self {
+        let mut editor = Self {
+            source: source.to_string(),
+            variables: variables.iter().map(|name| name.to_string()).collect(),
+            error: None,
+        };
+
+        editor.check();
+        editor
+    }
+
+    pub fn parse(&self) -> Result<Expr, ParseError> {
+        let variables: Vec<&str> = self.variables.iter().map(String::as_str).collect();
+
+        expression::parse(&self.source, &variables)
+    }
+
+    /// Parse the source again to refresh the displayed error
+    pub fn check(&mut self) {
+        self.error = self.parse().err().map(|error| error.to_string());
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut expression_changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("=");
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.source)
+                    .code_editor()
+                    .desired_width(f32::INFINITY),
+            );
+
+            if response.changed() {
+                self.check();
+            }
+
+            expression_changed = response.lost_focus();
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        } else {
+            let variables: Vec<&str> = self
+                .variables
+                .iter()
+                .map(String::as_str)
+                .chain(PIXEL_VARIABLES)
+                .collect();
+
+            ui.weak(format!("Variables: {}", variables.join(", ")));
+        }
+
+        expression_changed
+    }
+}
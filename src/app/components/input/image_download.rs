@@ -0,0 +1,1233 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use poll_promise::Promise;
+
+use crate::app::math::image::{self, DecodedImage};
+
+/// Memory used by the decoded images kept in memory, fetching one of them
+/// again does not send a request
+const MEMORY_CACHE_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Size of the largest image that can be downloaded
+const MAX_DOWNLOAD_SIZE: usize = 512 * 1024 * 1024;
+
+/// Memory reserved for a download from the size announced by the server, the
+/// rest is allocated as the content is received
+const MAX_PREALLOCATION: usize = 64 * 1024 * 1024;
+
+/// Number of times a request is sent again when the server fails
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_RETRIES: u32 = 2;
+
+/// Time waited before sending a failed request again, doubled on each retry
+#[cfg(not(target_arch = "wasm32"))]
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// What is sent to the server to fetch an image
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageRequest {
+    pub url: String,
+    /// Sent along the request, for the servers that need authentication
+    pub headers: Vec<(String, String)>,
+    /// Seconds before the request is abandoned
+    pub timeout: u64,
+}
+
+impl ImageRequest {
+    /// Identifies the response in the caches, as the same URL may give
+    /// another image with other headers. The hash does not depend on the
+    /// version of Rust, so that the disk cache is still found after an update.
+    pub fn cache_key(&self) -> u64 {
+        // 64 bits FNV-1a
+        let hash = |hash: u64, bytes: &[u8]| {
+            bytes.iter().fold(hash, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+        };
+
+        self.headers().fold(
+            hash(0xcbf2_9ce4_8422_2325, self.url.as_bytes()),
+            |key, (name, value)| {
+                let key = hash(key, &[0]);
+                let key = hash(key, name.to_ascii_lowercase().as_bytes());
+                let key = hash(key, &[0]);
+                hash(key, value.as_bytes())
+            },
+        )
+    }
+
+    /// The responses to authenticated requests are not written on the disk
+    pub fn is_private(&self) -> bool {
+        self.headers().any(|(name, _)| {
+            name.eq_ignore_ascii_case("Authorization") || name.eq_ignore_ascii_case("Cookie")
+        })
+    }
+
+    /// The headers sent, the ones left empty in the node are ignored
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .filter(|(name, value)| !name.trim().is_empty() && !value.is_empty())
+            .map(|(name, value)| (name.trim(), value.as_str()))
+    }
+}
+
+/// Progress of a fetch, updated from the thread downloading the image
+#[derive(Default)]
+pub struct Progress {
+    received: AtomicUsize,
+    /// Size announced by the server, 0 when it is unknown
+    total: AtomicUsize,
+    decoding: AtomicBool,
+}
+
+impl Progress {
+    pub fn received(&self) -> usize {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn decoding(&self) -> bool {
+        self.decoding.load(Ordering::Relaxed)
+    }
+}
+
+// Images with the key of their request, from the least to the most recently used
+type CachedImages = VecDeque<(u64, DecodedImage)>;
+
+/// The decoded images of the last requests, shared by all the fetchers
+#[derive(Clone)]
+pub struct ImageCache {
+    images: Arc<Mutex<CachedImages>>,
+    /// Memory the images may use before the least recently used are dropped
+    budget: usize,
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::with_budget(MEMORY_CACHE_BUDGET)
+    }
+}
+
+impl ImageCache {
+    /// The cache stored in the memory of the interface
+    pub fn of(ctx: &egui::Context) -> Self {
+        ctx.data()
+            .get_temp_mut_or_default::<ImageCache>(egui::Id::new("image cache"))
+            .clone()
+    }
+
+    pub fn with_budget(budget: usize) -> Self {
+        Self {
+            images: Default::default(),
+            budget,
+        }
+    }
+
+    pub fn get(&self, request: &ImageRequest) -> Option<DecodedImage> {
+        let key = request.cache_key();
+        let mut images = self.images.lock().unwrap();
+        let index = images.iter().position(|(cached, _)| *cached == key)?;
+
+        // The image becomes the most recently used
+        let entry = images.remove(index)?;
+        let image = entry.1.clone();
+        images.push_back(entry);
+
+        Some(image)
+    }
+
+    pub fn insert(&self, request: &ImageRequest, image: DecodedImage) {
+        let key = request.cache_key();
+        let mut images = self.images.lock().unwrap();
+        images.retain(|(cached, _)| *cached != key);
+        images.push_back((key, image));
+
+        // The last image is kept, even if it is larger than the budget
+        while images.len() > 1 && memory(&images) > self.budget {
+            images.pop_front();
+        }
+    }
+}
+
+fn memory(images: &CachedImages) -> usize {
+    images
+        .iter()
+        .map(|(_, decoded)| {
+            let float = decoded.float.as_ref().map_or(0, |float| float.bytes());
+            decoded.image.pixels.len() * std::mem::size_of::<egui::Color32>() + float
+        })
+        .sum()
+}
+
+/// Fetch and decode an image without blocking the interface. The image is
+/// taken from the caches unless it is reloaded.
+pub fn fetch(
+    request: ImageRequest,
+    reload: bool,
+    progress: Arc<Progress>,
+    cache: ImageCache,
+    ctx: egui::Context,
+) -> Promise<Result<DecodedImage, String>> {
+    if !reload {
+        if let Some(image) = cache.get(&request) {
+            return Promise::from_ready(Ok(image));
+        }
+    }
+
+    fetch_image(request, reload, progress, cache, ctx)
+}
+
+fn too_large() -> String {
+    format!(
+        "The image is larger than {} MB",
+        MAX_DOWNLOAD_SIZE / (1024 * 1024)
+    )
+}
+
+// Only the responses of image type can be decoded
+fn check_content_type(content_type: &str) -> Result<(), String> {
+    if content_type.starts_with("image/") {
+        Ok(())
+    } else if content_type.is_empty() {
+        Err("The server did not send the type of the content".to_string())
+    } else {
+        Err(format!(
+            "The server sent {} instead of an image",
+            content_type
+        ))
+    }
+}
+
+// Download and decode the image on a thread, so that large images do not
+// freeze the interface
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch_image(
+    request: ImageRequest,
+    reload: bool,
+    progress: Arc<Progress>,
+    cache: ImageCache,
+    ctx: egui::Context,
+) -> Promise<Result<DecodedImage, String>> {
+    Promise::spawn_thread("image fetcher", move || {
+        let disk = disk_cache::DiskCache::default();
+        let result = load(&request, reload, &progress, &cache, &disk, || {
+            ctx.request_repaint()
+        });
+
+        ctx.request_repaint();
+        result
+    })
+}
+
+/// Download and decode an image, the bytes are taken from the disk cache
+/// unless it is reloaded. The decoded image is put in the memory cache.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(
+    request: &ImageRequest,
+    reload: bool,
+    progress: &Progress,
+    cache: &ImageCache,
+    disk: &disk_cache::DiskCache,
+    on_progress: impl Fn(),
+) -> Result<DecodedImage, String> {
+    let cached = if reload || request.is_private() {
+        None
+    } else {
+        disk.read(request.cache_key())
+    };
+
+    let bytes = match cached {
+        Some(bytes) => bytes,
+        None => {
+            let bytes = download(request, progress, &on_progress)?;
+            if !request.is_private() {
+                disk.write(request.cache_key(), &bytes);
+            }
+            bytes
+        }
+    };
+
+    progress.decoding.store(true, Ordering::Relaxed);
+    on_progress();
+
+    let image = image::decode_image(&bytes, &request.url)?;
+    cache.insert(request, image.clone());
+
+    Ok(image)
+}
+
+/// Download the content of an image, `on_progress` is called each time a
+/// part of it is received. The request is sent again when the server fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn download(
+    request: &ImageRequest,
+    progress: &Progress,
+    on_progress: impl Fn(),
+) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    use std::time::Duration;
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(request.timeout))
+        .build();
+
+    let mut retries = 0;
+    let response = loop {
+        let mut call = agent.get(&request.url);
+        for (name, value) in request.headers() {
+            call = call.set(name, value);
+        }
+
+        match call.call() {
+            Ok(response) => break response,
+            Err(ureq::Error::Status(status, _)) if status >= 500 && retries < MAX_RETRIES => {
+                std::thread::sleep(RETRY_DELAY * 2u32.pow(retries));
+                retries += 1;
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                return Err(format!("{} {}", status, response.status_text()))
+            }
+            Err(ureq::Error::Transport(transport)) => return Err(transport.to_string()),
+        }
+    };
+    check_content_type(response.content_type())?;
+
+    let total = response
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    if total > MAX_DOWNLOAD_SIZE {
+        return Err(too_large());
+    }
+    progress.total.store(total, Ordering::Relaxed);
+
+    let mut bytes = Vec::with_capacity(total.min(MAX_PREALLOCATION));
+    let mut reader = response.into_reader();
+    let mut chunk = [0; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut chunk).map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        if bytes.len() + read > MAX_DOWNLOAD_SIZE {
+            return Err(too_large());
+        }
+
+        bytes.extend_from_slice(&chunk[..read]);
+        progress.received.store(bytes.len(), Ordering::Relaxed);
+        on_progress();
+    }
+
+    Ok(bytes)
+}
+
+// The downloaded images are kept in the cache directory of the user, so that
+// they are not requested again when the application is restarted
+#[cfg(not(target_arch = "wasm32"))]
+pub mod disk_cache {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    /// Size of the files kept on the disk
+    const DISK_CACHE_BUDGET: u64 = 512 * 1024 * 1024;
+
+    /// Age after which a file is downloaded again
+    const DISK_CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    /// The responses written in a directory, by the key of their request
+    pub struct DiskCache {
+        pub directory: PathBuf,
+        pub budget: u64,
+        pub max_age: Duration,
+    }
+
+    impl Default for DiskCache {
+        fn default() -> Self {
+            Self {
+                directory: user_cache_directory().join("carbaseus"),
+                budget: DISK_CACHE_BUDGET,
+                max_age: DISK_CACHE_MAX_AGE,
+            }
+        }
+    }
+
+    // The responses may need authentication, they are not shared with the
+    // other users of the machine
+    fn user_cache_directory() -> PathBuf {
+        let var = |name| {
+            std::env::var_os(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+
+        let directory = if cfg!(windows) {
+            var("LOCALAPPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library").join("Caches"))
+        } else {
+            var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+        };
+
+        directory.unwrap_or_else(std::env::temp_dir)
+    }
+
+    impl DiskCache {
+        fn path(&self, key: u64) -> PathBuf {
+            self.directory.join(format!("{:016x}", key))
+        }
+
+        pub fn read(&self, key: u64) -> Option<Vec<u8>> {
+            let path = self.path(key);
+            let age = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .unwrap_or_default();
+
+            if age > self.max_age {
+                std::fs::remove_file(&path).ok();
+                return None;
+            }
+
+            std::fs::read(path).ok()
+        }
+
+        pub fn write(&self, key: u64, bytes: &[u8]) {
+            let path = self.path(key);
+
+            let result = self
+                .create_directory()
+                .and_then(|_| std::fs::write(&path, bytes));
+
+            match result {
+                Ok(()) => self.trim(),
+                Err(err) => tracing::warn!("Failed to cache the image in {:?}: {}", path, err),
+            }
+        }
+
+        // Only the user can read the directory
+        fn create_directory(&self) -> std::io::Result<()> {
+            let mut builder = std::fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            builder.create(&self.directory)
+        }
+
+        // Remove the oldest files while the cache is larger than its budget
+        fn trim(&self) {
+            let entries = match std::fs::read_dir(&self.directory) {
+                Ok(entries) => entries,
+                Err(_) => return,
+            };
+
+            let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let metadata = entry.metadata().ok()?;
+                    let modified = metadata.modified().ok()?;
+                    Some((modified, metadata.len(), entry.path()))
+                })
+                .filter(|(_, _, path)| path.is_file())
+                .collect();
+            files.sort();
+
+            let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+            for (_, len, path) in files {
+                if size <= self.budget {
+                    break;
+                }
+                if std::fs::remove_file(&path).is_ok() {
+                    size -= len;
+                }
+            }
+        }
+    }
+}
+
+// The page hands the fetches to a Web Worker, which downloads and decodes
+// the images without blocking the interface. The responses are kept by the
+// cache of the browser, the requests that take too long are abandoned by the
+// fetcher.
+#[cfg(target_arch = "wasm32")]
+fn fetch_image(
+    request: ImageRequest,
+    reload: bool,
+    progress: Arc<Progress>,
+    cache: ImageCache,
+    ctx: egui::Context,
+) -> Promise<Result<DecodedImage, String>> {
+    worker::fetch(request, reload, progress, cache, ctx)
+}
+
+/// The Web Worker fetching the images in the browser. It loads the module of
+/// the application from `docs/decode_worker.js`, then calls
+/// `start_decode_worker` to answer the messages of the page. The page sends
+/// the requests and receives the progress of the downloads, then the pixels
+/// of the decoded images.
+#[cfg(target_arch = "wasm32")]
+pub mod worker {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use eframe::wasm_bindgen::{self, prelude::*, JsCast};
+    use egui::{Color32, ColorImage};
+    use js_sys::{Array, Float32Array, Object, Reflect, Uint8Array};
+    use poll_promise::{Promise, Sender};
+    use wasm_bindgen_futures::JsFuture;
+
+    use super::*;
+    use crate::app::math::float_image::FloatImage;
+
+    const WORKER_SCRIPT: &str = "./decode_worker.js";
+
+    // Images decoded by all the browsers, the other formats and the 16 bits
+    // images are decoded by the application
+    const BROWSER_FORMATS: &[&str] = &[
+        "image/png",
+        "image/jpeg",
+        "image/gif",
+        "image/webp",
+        "image/bmp",
+    ];
+
+    // A fetch waiting for the worker
+    struct Pending {
+        request: ImageRequest,
+        sender: Sender<Result<DecodedImage, String>>,
+        progress: Arc<Progress>,
+        cache: ImageCache,
+        ctx: egui::Context,
+    }
+
+    // The worker of the page and the fetches sent to it, by their id
+    struct Client {
+        worker: web_sys::Worker,
+        next_id: u32,
+        pending: HashMap<u32, Pending>,
+    }
+
+    thread_local! {
+        static CLIENT: RefCell<Option<Client>> = const { RefCell::new(None) };
+    }
+
+    /// Sends a request to the worker, which is started on the first fetch
+    pub fn fetch(
+        request: ImageRequest,
+        reload: bool,
+        progress: Arc<Progress>,
+        cache: ImageCache,
+        ctx: egui::Context,
+    ) -> Promise<Result<DecodedImage, String>> {
+        let (sender, promise) = Promise::new();
+
+        let sent = CLIENT.with(|client| {
+            let mut client = client.borrow_mut();
+            if client.is_none() {
+                *client = Some(start_client()?);
+            }
+            let client = match client.as_mut() {
+                Some(client) => client,
+                None => return Err("The worker is not available".to_string()),
+            };
+
+            let id = client.next_id;
+            client.next_id += 1;
+
+            let headers = Array::new();
+            for (name, value) in request.headers() {
+                headers.push(&Array::of2(&name.into(), &value.into()));
+            }
+
+            let message = Object::new();
+            set(&message, "id", &id.into());
+            set(&message, "url", &request.url.as_str().into());
+            set(&message, "headers", &headers);
+            set(&message, "reload", &reload.into());
+            client.worker.post_message(&message).map_err(js_error)?;
+
+            Ok(id)
+        });
+
+        match sent {
+            Ok(id) => CLIENT.with(|client| {
+                if let Some(client) = client.borrow_mut().as_mut() {
+                    let pending = Pending {
+                        request,
+                        sender,
+                        progress,
+                        cache,
+                        ctx,
+                    };
+                    client.pending.insert(id, pending);
+                }
+            }),
+            Err(error) => sender.send(Err(error)),
+        }
+
+        promise
+    }
+
+    fn start_client() -> Result<Client, String> {
+        let worker = web_sys::Worker::new(WORKER_SCRIPT).map_err(js_error)?;
+
+        let on_message =
+            Closure::wrap(
+                Box::new(|event: web_sys::MessageEvent| receive(event.data()))
+                    as Box<dyn FnMut(web_sys::MessageEvent)>,
+            );
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        Ok(Client {
+            worker,
+            next_id: 0,
+            pending: HashMap::new(),
+        })
+    }
+
+    // A message of the worker, the progress of a download or its result
+    fn receive(message: JsValue) {
+        let id = get(&message, "id").as_f64().unwrap_or(-1.0) as u32;
+
+        CLIENT.with(|client| {
+            let mut client = client.borrow_mut();
+            let pending = match client.as_mut() {
+                Some(client) => &mut client.pending,
+                None => return,
+            };
+
+            if let Some(received) = get(&message, "received").as_f64() {
+                if let Some(fetch) = pending.get(&id) {
+                    let total = get(&message, "total").as_f64().unwrap_or(0.0);
+                    let progress = &fetch.progress;
+                    progress
+                        .received
+                        .store(received as usize, Ordering::Relaxed);
+                    progress.total.store(total as usize, Ordering::Relaxed);
+                    fetch.ctx.request_repaint();
+                }
+                return;
+            }
+
+            if get(&message, "decoding").is_truthy() {
+                if let Some(fetch) = pending.get(&id) {
+                    fetch.progress.decoding.store(true, Ordering::Relaxed);
+                    fetch.ctx.request_repaint();
+                }
+                return;
+            }
+
+            let fetch = match pending.remove(&id) {
+                Some(fetch) => fetch,
+                None => return,
+            };
+
+            let result = match get(&message, "error").as_string() {
+                Some(error) => Err(error),
+                None => read_image(&message),
+            };
+            if let Ok(image) = &result {
+                fetch.cache.insert(&fetch.request, image.clone());
+            }
+
+            fetch.sender.send(result);
+            fetch.ctx.request_repaint();
+        });
+    }
+
+    // The pixels are already premultiplied by the worker, they are only copied
+    fn read_image(message: &JsValue) -> Result<DecodedImage, String> {
+        let size = [
+            get(message, "width").as_f64().unwrap_or(0.0) as usize,
+            get(message, "height").as_f64().unwrap_or(0.0) as usize,
+        ];
+
+        let bytes = Uint8Array::new(&get(message, "pixels")).to_vec();
+        if bytes.len() != size[0] * size[1] * 4 {
+            return Err("The decoded image does not match its size".to_string());
+        }
+        let pixels = bytes
+            .chunks_exact(4)
+            .map(|pixel| Color32::from_rgba_premultiplied(pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect();
+
+        let float = get(message, "float");
+        let float = if float.is_undefined() {
+            None
+        } else {
+            let channels = Float32Array::new(&float).to_vec();
+            Some(Arc::new(FloatImage {
+                size,
+                pixels: channels
+                    .chunks_exact(4)
+                    .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+                    .collect(),
+                linear: get(message, "linear").is_truthy(),
+            }))
+        };
+
+        let format = get(message, "format")
+            .as_string()
+            .and_then(::image::ImageFormat::from_extension)
+            .map_or("Unknown", image::format_name);
+
+        Ok(DecodedImage {
+            image: Arc::new(ColorImage { size, pixels }),
+            float,
+            format,
+        })
+    }
+
+    /// Entry point of the worker, called by `docs/decode_worker.js` once the
+    /// module is loaded
+    #[wasm_bindgen]
+    pub fn start_decode_worker() -> Result<(), JsValue> {
+        let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global().dyn_into()?;
+
+        let on_message = Closure::wrap(Box::new(|event: web_sys::MessageEvent| {
+            wasm_bindgen_futures::spawn_local(answer(event.data()))
+        }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+        scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        Ok(())
+    }
+
+    async fn answer(message: JsValue) {
+        let id = get(&message, "id");
+
+        let (reply, transfer) = match download_and_decode(&id, &message).await {
+            Ok(reply) => reply,
+            Err(error) => {
+                let reply = Object::new();
+                set(&reply, "error", &error.into());
+                (reply, Array::new())
+            }
+        };
+
+        set(&reply, "id", &id);
+        post(&reply, &transfer);
+    }
+
+    async fn download_and_decode(
+        id: &JsValue,
+        message: &JsValue,
+    ) -> Result<(Object, Array), String> {
+        let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global()
+            .dyn_into()
+            .map_err(|_| "The decoder is not in a worker")?;
+        let url = get(message, "url").as_string().unwrap_or_default();
+
+        let headers = web_sys::Headers::new().map_err(js_error)?;
+        for header in Array::from(&get(message, "headers")).iter() {
+            let header = Array::from(&header);
+            let name = header.get(0).as_string().unwrap_or_default();
+            let value = header.get(1).as_string().unwrap_or_default();
+            headers.append(&name, &value).map_err(js_error)?;
+        }
+
+        // Reloading skips the cache of the browser
+        let init = Object::new();
+        set(&init, "headers", &headers);
+        let mode = if get(message, "reload").is_truthy() {
+            "reload"
+        } else {
+            "default"
+        };
+        set(&init, "cache", &mode.into());
+
+        let response = scope.fetch_with_str_and_init(&url, init.unchecked_ref());
+        let response: web_sys::Response = JsFuture::from(response)
+            .await
+            .map_err(js_error)?
+            .dyn_into()
+            .map_err(js_error)?;
+
+        if !response.ok() {
+            return Err(format!("{} {}", response.status(), response.status_text()));
+        }
+
+        let header = |name: &str| response.headers().get(name).ok().flatten();
+        let content_type = header("Content-Type").unwrap_or_default();
+        check_content_type(&content_type)?;
+
+        let total = header("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        if total > MAX_DOWNLOAD_SIZE {
+            return Err(too_large());
+        }
+
+        // The content is read as it arrives, to report the progress
+        let body = response.body().ok_or("The response has no content")?;
+        let reader = call(&body, "getReader")?;
+        let mut bytes = Vec::with_capacity(total.min(MAX_PREALLOCATION));
+
+        loop {
+            let read = js_sys::Promise::from(call(&reader, "read")?);
+            let chunk = JsFuture::from(read).await.map_err(js_error)?;
+            if get(&chunk, "done").is_truthy() {
+                break;
+            }
+
+            let value = Uint8Array::new(&get(&chunk, "value"));
+            let start = bytes.len();
+            if start + value.length() as usize > MAX_DOWNLOAD_SIZE {
+                return Err(too_large());
+            }
+            bytes.resize(start + value.length() as usize, 0);
+            value.copy_to(&mut bytes[start..]);
+
+            let progress = Object::new();
+            set(&progress, "id", id);
+            set(&progress, "received", &(bytes.len() as f64).into());
+            set(&progress, "total", &(total as f64).into());
+            post(&progress, &Array::new());
+        }
+
+        let decoding = Object::new();
+        set(&decoding, "id", id);
+        set(&decoding, "decoding", &true.into());
+        post(&decoding, &Array::new());
+
+        decode(&scope, &bytes, &content_type, &url).await
+    }
+
+    // The browser decodes its formats faster than the application, except
+    // the 16 bits PNG that it reduces to 8 bits
+    async fn decode(
+        scope: &web_sys::DedicatedWorkerGlobalScope,
+        bytes: &[u8],
+        content_type: &str,
+        url: &str,
+    ) -> Result<(Object, Array), String> {
+        let (image, float, format) = if decoded_by_browser(content_type, bytes) {
+            let image = decode_in_browser(scope, bytes, content_type).await?;
+            let format = ::image::ImageFormat::from_mime_type(content_type);
+            (Arc::new(image), None, format)
+        } else {
+            let format = image::guess_format(bytes, url);
+            let decoded = image::decode_image(bytes, url)?;
+            (decoded.image, decoded.float, format)
+        };
+
+        let reply = Object::new();
+        let transfer = Array::new();
+
+        let pixels: Vec<u8> = image
+            .pixels
+            .iter()
+            .flat_map(|color| color.to_array())
+            .collect();
+        let pixels = Uint8Array::from(pixels.as_slice());
+        set(&reply, "width", &(image.size[0] as f64).into());
+        set(&reply, "height", &(image.size[1] as f64).into());
+        set(&reply, "pixels", &pixels);
+        transfer.push(&pixels.buffer());
+
+        if let Some(float) = float {
+            let channels: Vec<f32> = float.pixels.iter().flatten().copied().collect();
+            let channels = Float32Array::from(channels.as_slice());
+            set(&reply, "float", &channels);
+            set(&reply, "linear", &float.linear.into());
+            transfer.push(&channels.buffer());
+        }
+
+        if let Some(extension) = format.and_then(|format| format.extensions_str().first()) {
+            set(&reply, "format", &(*extension).into());
+        }
+
+        Ok((reply, transfer))
+    }
+
+    fn decoded_by_browser(content_type: &str, bytes: &[u8]) -> bool {
+        // The depth of a PNG is the byte following its size in the header
+        if content_type == "image/png" {
+            return bytes.get(24).map_or(false, |depth| *depth <= 8);
+        }
+
+        BROWSER_FORMATS.contains(&content_type)
+    }
+
+    async fn decode_in_browser(
+        scope: &web_sys::DedicatedWorkerGlobalScope,
+        bytes: &[u8],
+        content_type: &str,
+    ) -> Result<ColorImage, String> {
+        let error = |err: JsValue| format!("Failed to decode the image: {:?}", err);
+
+        let parts = Array::of1(&Uint8Array::from(bytes));
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_(content_type);
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+            .map_err(error)?;
+
+        let bitmap = scope.create_image_bitmap_with_blob(&blob).map_err(error)?;
+        let bitmap: web_sys::ImageBitmap = JsFuture::from(bitmap)
+            .await
+            .map_err(error)?
+            .dyn_into()
+            .map_err(error)?;
+
+        // The pixels are read back by drawing the bitmap on a canvas
+        let (width, height) = (bitmap.width(), bitmap.height());
+        let canvas = web_sys::OffscreenCanvas::new(width, height).map_err(error)?;
+        let context: web_sys::OffscreenCanvasRenderingContext2d = canvas
+            .get_context("2d")
+            .map_err(error)?
+            .and_then(|context| context.dyn_into().ok())
+            .ok_or("The canvas has no 2d context")?;
+
+        context
+            .draw_image_with_image_bitmap(&bitmap, 0.0, 0.0)
+            .map_err(error)?;
+        let data = context
+            .get_image_data(0.0, 0.0, width as f64, height as f64)
+            .map_err(error)?;
+
+        let size = [width as usize, height as usize];
+        Ok(ColorImage::from_rgba_unmultiplied(size, &data.data().0))
+    }
+
+    fn post(message: &JsValue, transfer: &Array) {
+        if let Ok(scope) = js_sys::global().dyn_into::<web_sys::DedicatedWorkerGlobalScope>() {
+            scope.post_message_with_transfer(message, transfer).ok();
+        }
+    }
+
+    fn get(object: &JsValue, key: &str) -> JsValue {
+        Reflect::get(object, &key.into()).unwrap_or(JsValue::UNDEFINED)
+    }
+
+    fn set(object: &JsValue, key: &str, value: &JsValue) {
+        Reflect::set(object, &key.into(), value).ok();
+    }
+
+    // Calls a method without arguments, the streams are used through their
+    // JavaScript interface
+    fn call(object: &JsValue, method: &str) -> Result<JsValue, String> {
+        let function: js_sys::Function = get(object, method).dyn_into().map_err(js_error)?;
+        function.call0(object).map_err(js_error)
+    }
+
+    fn js_error(err: JsValue) -> String {
+        err.as_string().unwrap_or_else(|| format!("{:?}", err))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::disk_cache::DiskCache;
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    // Answers the connections with the given responses, one per connection,
+    // and returns the URL of the server and the requests it received. An
+    // empty response keeps the connection open without answering.
+    fn serve(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(_) => return,
+                };
+
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                received
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).to_string());
+
+                if response.is_empty() {
+                    std::thread::sleep(Duration::from_secs(5));
+                } else {
+                    stream.write_all(&response).ok();
+                }
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = vec![];
+        ::image::DynamicImage::ImageRgba8(::image::RgbaImage::new(3, 2))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                ::image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        bytes
+    }
+
+    fn request(url: &str, headers: &[(&str, &str)]) -> ImageRequest {
+        ImageRequest {
+            url: url.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            timeout: 1,
+        }
+    }
+
+    // A disk cache of its own for each test
+    fn disk(name: &str) -> DiskCache {
+        let directory = std::env::temp_dir().join("carbaseus-tests").join(format!(
+            "{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&directory).ok();
+
+        DiskCache {
+            directory,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn download_times_out() {
+        let (url, _) = serve(vec![vec![]]);
+        let started = Instant::now();
+
+        let result = download(&request(&url, &[]), &Progress::default(), || {});
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn download_retries_server_errors() {
+        let (url, requests) = serve(vec![
+            response("503 Service Unavailable", "text/plain", b"busy"),
+            response("200 OK", "image/png", &png()),
+        ]);
+
+        let bytes = download(&request(&url, &[]), &Progress::default(), || {}).unwrap();
+
+        assert_eq!(bytes, png());
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn download_does_not_retry_client_errors() {
+        let (url, requests) = serve(vec![
+            response("404 Not Found", "text/plain", b"missing"),
+            response("200 OK", "image/png", &png()),
+        ]);
+
+        let result = download(&request(&url, &[]), &Progress::default(), || {});
+
+        assert_eq!(result, Err("404 Not Found".to_string()));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn download_rejects_other_content() {
+        let (url, _) = serve(vec![response("200 OK", "text/html", b"<html>")]);
+
+        let result = download(&request(&url, &[]), &Progress::default(), || {});
+
+        assert!(result.unwrap_err().contains("text/html"));
+    }
+
+    #[test]
+    fn download_rejects_announced_sizes_over_the_limit() {
+        let fake = b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\n\
+            Content-Length: 18446744073709551615\r\nConnection: close\r\n\r\n";
+        let (url, _) = serve(vec![fake.to_vec()]);
+
+        let result = download(&request(&url, &[]), &Progress::default(), || {});
+
+        assert!(result.unwrap_err().contains("larger than"));
+    }
+
+    #[test]
+    fn download_sends_the_headers() {
+        let (url, requests) = serve(vec![response("200 OK", "image/png", &png())]);
+        let headers = [("X-Api-Key", "secret"), ("Authorization", "")];
+
+        download(&request(&url, &headers), &Progress::default(), || {}).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let sent = requests[0].to_ascii_lowercase();
+        assert!(sent.contains("x-api-key: secret"));
+        assert!(!sent.contains("authorization"));
+    }
+
+    #[test]
+    fn load_reads_the_disk_cache() {
+        let (url, requests) = serve(vec![response("200 OK", "image/png", &png())]);
+        let disk = disk("load");
+        let request = request(&url, &[]);
+        let progress = Progress::default();
+
+        let first = load(
+            &request,
+            false,
+            &progress,
+            &ImageCache::default(),
+            &disk,
+            || {},
+        );
+        let second = load(
+            &request,
+            false,
+            &progress,
+            &ImageCache::default(),
+            &disk,
+            || {},
+        );
+
+        assert_eq!(first.unwrap().image.size, [3, 2]);
+        assert_eq!(second.unwrap().image.size, [3, 2]);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn load_does_not_write_private_responses() {
+        let (url, requests) = serve(vec![
+            response("200 OK", "image/png", &png()),
+            response("200 OK", "image/png", &png()),
+        ]);
+        let disk = disk("private");
+        let request = request(&url, &[("Authorization", "Bearer token")]);
+        let progress = Progress::default();
+
+        load(
+            &request,
+            false,
+            &progress,
+            &ImageCache::default(),
+            &disk,
+            || {},
+        )
+        .unwrap();
+
+        assert!(disk.read(request.cache_key()).is_none());
+
+        load(
+            &request,
+            false,
+            &progress,
+            &ImageCache::default(),
+            &disk,
+            || {},
+        )
+        .unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn memory_cache_is_keyed_by_headers() {
+        let cache = ImageCache::default();
+        let public = request("http://localhost/image.png", &[]);
+        let private = request("http://localhost/image.png", &[("Cookie", "session=1")]);
+
+        let image = image::decode_image(&png(), "image.png").unwrap();
+        cache.insert(&private, image);
+
+        assert!(cache.get(&private).is_some());
+        assert!(cache.get(&public).is_none());
+    }
+
+    #[test]
+    fn memory_cache_drops_the_least_recently_used() {
+        // Each image of 3x2 pixels uses 24 bytes
+        let cache = ImageCache::with_budget(50);
+        let image = image::decode_image(&png(), "image.png").unwrap();
+        let [a, b, c] = ["a", "b", "c"].map(|name| request(name, &[]));
+
+        cache.insert(&a, image.clone());
+        cache.insert(&b, image.clone());
+        cache.get(&a);
+        cache.insert(&c, image);
+
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&c).is_some());
+    }
+
+    #[test]
+    fn cache_key_is_stable() {
+        // Reference value of the 64 bits FNV-1a hash of "a"
+        assert_eq!(request("a", &[]).cache_key(), 0xaf63_dc4c_8601_ec8c);
+
+        let accept = request("a", &[("Accept", "image/png")]);
+        assert_ne!(accept.cache_key(), request("a", &[]).cache_key());
+        assert_eq!(
+            accept.cache_key(),
+            request("a", &[("accept", "image/png")]).cache_key()
+        );
+    }
+
+    #[test]
+    fn disk_cache_expires_and_stays_in_budget() {
+        let mut disk = disk("budget");
+        disk.budget = 10;
+
+        disk.write(1, &[0; 6]);
+        std::thread::sleep(Duration::from_millis(20));
+        disk.write(2, &[0; 6]);
+
+        assert!(disk.read(1).is_none());
+        assert!(disk.read(2).is_some());
+
+        disk.max_age = Duration::ZERO;
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(disk.read(2).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn disk_cache_is_only_readable_by_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let disk = disk("private");
+        disk.write(1, &[0; 6]);
+
+        let mode = std::fs::metadata(&disk.directory)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
@@ -1,7 +1,13 @@
-use crate::app::math::image;
+use crate::app::components::input::image_download::{self, ImageCache, ImageRequest, Progress};
+use crate::app::math::float_image::FloatImage;
+use crate::app::math::image::DecodedImage;
 use egui::epaint::ColorImage;
-use poll_promise::{Promise, Sender};
+use poll_promise::Promise;
 use std::string::String;
+use std::sync::Arc;
+
+/// Seconds before a request is abandoned, unless the node sets another timeout
+const DEFAULT_TIMEOUT: u64 = 30;
 
 // #[derive(serde::Deserialize, serde::Serialize)]
 // #[serde(default)]
@@ -9,19 +15,55 @@ use std::string::String;
 pub struct Fetcher {
     pub url: String,
 
+    /// Headers sent along the request, for the servers that need authentication
+    pub headers: Vec<(String, String)>,
+
+    /// Seconds before the request is abandoned
+    pub timeout: u64,
+
+    // #[serde(skip)] // opt-out serialization
+    pub promise: Option<Promise<Result<DecodedImage, String>>>,
+
+    // #[serde(skip)] // opt-out serialization
+    pub progress: Arc<Progress>,
+
+    // Time at which the pending request was sent
+    // #[serde(skip)] // opt-out serialization
+    pub started: f64,
+
+    /// Why the last fetch failed, displayed in the node
     // #[serde(skip)] // opt-out serialization
-    pub promise: Option<Promise<Result<Option<ehttp::Response>, String>>>,
+    pub error: Option<String>,
 
     // #[serde(skip)] // opt-out serialization
-    pub image: ColorImage,
+    pub image: Arc<ColorImage>,
+
+    /// The image in floating point, when it has more than 8 bits per channel
+    // #[serde(skip)] // opt-out serialization
+    pub float: Option<Arc<FloatImage>>,
+
+    /// Format detected when decoding the image
+    pub format: Option<&'static str>,
+
+    /// The image was not kept along the URL, as in the groups of the library,
+    /// it is fetched again once the node is displayed
+    pub refetch: bool,
 }
 
 impl Default for Fetcher {
     fn default() -> Self {
         Self {
             url: "https://picsum.photos/seed/0/640".to_string(),
+            headers: vec![],
+            timeout: DEFAULT_TIMEOUT,
             promise: Default::default(),
-            image: ColorImage::new([1, 1], egui::Color32::BLACK),
+            progress: Default::default(),
+            started: 0.0,
+            error: None,
+            image: Arc::new(ColorImage::new([1, 1], egui::Color32::BLACK)),
+            float: None,
+            format: None,
+            refetch: false,
         }
     }
 }
@@ -30,72 +72,99 @@ impl Clone for Fetcher {
     fn clone(&self) -> Self {
         Self {
             url: self.url.clone(),
+            headers: self.headers.clone(),
+            timeout: self.timeout,
             promise: None,
+            progress: Default::default(),
+            started: 0.0,
+            error: self.error.clone(),
             image: self.image.clone(),
+            float: self.float.clone(),
+            format: self.format,
+            refetch: self.refetch,
         }
     }
 }
 
-type HTTPSender = Sender<Result<Option<ehttp::Response>, String>>;
-type HTTPPromise = Promise<Result<Option<ehttp::Response>, String>>;
-
 impl Fetcher {
+    /// The request sent for the URL, with the headers and the timeout
+    pub fn request(&self) -> ImageRequest {
+        ImageRequest {
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            timeout: self.timeout,
+        }
+    }
+
     pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
         let mut image_fetched = false;
-        let mut promise_loading = false;
-        let mut trigger_fetch = false;
-
-        if let Some(promise) = &self.promise {
-            // Extract and store the image in the state if the request was successfull
-            if let Some(result) = promise.ready() {
-                match result {
-                    Ok(Some(response)) => {
-                        if let Some(image) = image::load_image_bytes(&response.bytes) {
-                            self.image = image
-                        }
-                    }
-                    Ok(None) => {}
-                    Err(_) => {}
-                }
 
-                // We reset the promise to avoid recomputing
-                self.promise = Default::default();
+        // Extract and store the image in the state once it is downloaded and decoded
+        if let Some(promise) = self.promise.take() {
+            match promise.try_take() {
+                Ok(Ok(decoded)) => {
+                    self.image = decoded.image;
+                    self.float = decoded.float;
+                    self.format = Some(decoded.format);
+                    self.error = None;
+                    self.refetch = false;
 
-                image_fetched = true; // Notify frame update
-            } else {
-                promise_loading = true; // Notify loading
+                    image_fetched = true; // Notify frame update
+                }
+                Ok(Err(error)) => self.error = Some(error),
+                Err(promise) => self.promise = Some(promise),
             }
         }
 
-        if promise_loading {
-            self.ui_loading(ui);
-        } else {
-            trigger_fetch = self.ui_url(ui);
+        // The requests of the browser can not be given a timeout, they are
+        // abandoned instead
+        let elapsed = ui.input().time - self.started;
+        if cfg!(target_arch = "wasm32")
+            && self.promise.is_some()
+            && !self.progress.decoding()
+            && elapsed > self.timeout as f64
+        {
+            self.promise = None;
+            self.error = Some(format!("No response after {} seconds", self.timeout));
         }
 
-        if trigger_fetch {
-            let (sender, promise): (HTTPSender, HTTPPromise) = Promise::new();
+        let mut fetch = None;
 
-            let request = ehttp::Request::get(&self.url);
+        if self.refetch && self.promise.is_none() && self.error.is_none() {
+            fetch = Some(false);
+        }
 
-            // Fetch the content from the given url
-            ehttp::fetch(request, move |response: ehttp::Result<ehttp::Response>| {
-                let result = response.map(|response| -> Option<ehttp::Response> {
-                    let content_type = response.content_type().unwrap_or_default();
+        if self.promise.is_some() {
+            self.ui_loading(ui);
+        } else {
+            fetch = self.ui_url(ui);
 
-                    // Keep the content only if it's of image type
-                    if content_type.starts_with("image/") {
-                        Some(response)
-                    } else {
-                        None
+            if let Some(error) = &self.error {
+                ui.horizontal_wrapped(|ui| {
+                    ui.colored_label(egui::Color32::RED, error);
+                    if ui.button("🔁 Retry").clicked() {
+                        fetch = Some(true);
                     }
                 });
+            }
 
-                sender.send(result);
-            });
+            self.ui_request(ui);
+        }
+
+        if let Some(reload) = fetch {
+            let request = self.request();
+
+            self.progress = Default::default();
+            self.started = ui.input().time;
 
             // We then store the promise in the cache
-            self.promise = Some(promise);
+            self.promise = Some(image_download::fetch(
+                request,
+                reload,
+                self.progress.clone(),
+                ImageCache::of(ui.ctx()),
+                ui.ctx().clone(),
+            ));
         }
 
         ui.separator();
@@ -104,35 +173,92 @@ impl Fetcher {
     }
 
     pub fn ui_loading(&mut self, ui: &mut egui::Ui) {
+        let received = self.progress.received();
+        let total = self.progress.total();
+
         ui.allocate_ui_with_layout(
             egui::Vec2::new(228.0, 1.0),
-            egui::Layout::centered_and_justified(egui::Direction::TopDown),
+            egui::Layout::top_down_justified(egui::Align::Center),
             |ui| {
-                ui.spinner();
+                if self.progress.decoding() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Decoding");
+                    });
+                } else if total > 0 {
+                    let text = format!("{} / {} KB", received / 1024, total / 1024);
+                    ui.add(egui::ProgressBar::new(received as f32 / total as f32).text(text));
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("Downloading {} KB", received / 1024));
+                    });
+                }
             },
         );
     }
 
-    pub fn ui_url(&mut self, ui: &mut egui::Ui) -> bool {
-        let mut trigger_fetch = false;
+    /// Returns whether the image must be fetched, and if it must be reloaded
+    /// instead of being taken from the cache
+    pub fn ui_url(&mut self, ui: &mut egui::Ui) -> Option<bool> {
+        let mut fetch = None;
 
         ui.horizontal(|ui| {
             ui.label("URL:");
-            trigger_fetch |= ui
+            if ui
                 .add(egui::TextEdit::singleline(&mut self.url).desired_width(f32::INFINITY))
-                .lost_focus();
-            if ui.button("🔃").clicked() {
-                trigger_fetch = true;
+                .lost_focus()
+            {
+                fetch = Some(false);
+            }
+            if ui.button("🔃").on_hover_text("Reload").clicked() {
+                fetch = Some(true);
             }
 
             if ui.button("🎲").clicked() {
                 let seed = ui.input().time;
                 let side = 640;
                 self.url = format!("https://picsum.photos/seed/{}/{}", seed, side);
-                trigger_fetch = true;
+                fetch = Some(false);
             }
         });
 
-        trigger_fetch
+        fetch
+    }
+
+    pub fn ui_request(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Request")
+            .id_source("fetcher request")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Timeout:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.timeout)
+                            .clamp_range(1..=600)
+                            .suffix(" s"),
+                    );
+                });
+
+                let mut removed = None;
+                for (index, (name, value)) in self.headers.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(name).desired_width(80.0));
+                        ui.label(":");
+                        ui.add(egui::TextEdit::singleline(value).desired_width(110.0));
+                        if ui.button("➖").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+
+                if let Some(index) = removed {
+                    self.headers.remove(index);
+                }
+
+                if ui.button("➕ Header").clicked() {
+                    self.headers
+                        .push(("Authorization".to_string(), String::new()));
+                }
+            });
     }
 }
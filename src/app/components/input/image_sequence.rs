@@ -0,0 +1,288 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use egui::epaint::ColorImage;
+
+use crate::app::math::float_image::FloatImage;
+use crate::app::math::image;
+
+/// The image files of a directory, or the files matching a pattern such as
+/// `captures/*.png`, displayed one frame at a time
+pub struct Sequence {
+    /// Directory, or path with `*` and `?` wildcards in the file name
+    pub pattern: String,
+    pub files: Vec<PathBuf>,
+    pub frame: usize,
+
+    /// Why the files could not be listed or the frame could not be read
+    pub error: Option<String>,
+
+    pub image: Arc<ColorImage>,
+
+    /// The frame in floating point, when it has more than 8 bits per channel
+    pub float: Option<Arc<FloatImage>>,
+
+    /// Format detected when decoding the frame
+    pub format: Option<&'static str>,
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self {
+            pattern: String::new(),
+            files: vec![],
+            frame: 0,
+            error: None,
+            image: Arc::new(ColorImage::new([1, 1], egui::Color32::BLACK)),
+            float: None,
+            format: None,
+        }
+    }
+}
+
+impl Clone for Sequence {
+    fn clone(&self) -> Self {
+        Self {
+            pattern: self.pattern.clone(),
+            files: self.files.clone(),
+            frame: self.frame,
+            error: self.error.clone(),
+            image: self.image.clone(),
+            float: self.float.clone(),
+            format: self.format,
+        }
+    }
+}
+
+impl Sequence {
+    /// Returns true when another frame is displayed
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Files:");
+            let pattern = ui.add(
+                egui::TextEdit::singleline(&mut self.pattern)
+                    .hint_text("captures/*.png")
+                    .desired_width(f32::INFINITY),
+            );
+
+            if pattern.lost_focus() {
+                changed |= self.list();
+            }
+            if ui
+                .button("🔃")
+                .on_hover_text("List the files again")
+                .clicked()
+            {
+                changed |= self.list();
+            }
+        });
+
+        if !self.files.is_empty() {
+            let last = self.files.len() - 1;
+            let mut frame = self.frame;
+
+            ui.horizontal(|ui| {
+                if ui.button("⏮").clicked() {
+                    frame = 0;
+                }
+                if ui.button("⏴").clicked() {
+                    frame = frame.saturating_sub(1);
+                }
+
+                ui.label(format!("{} / {}", self.frame + 1, self.files.len()));
+
+                if ui.button("⏵").clicked() {
+                    frame = (frame + 1).min(last);
+                }
+                if ui.button("⏭").clicked() {
+                    frame = last;
+                }
+            });
+
+            if frame != self.frame {
+                changed |= self.set_frame(frame).is_ok();
+            }
+
+            if let Some(name) = self.current_file().and_then(Path::file_name) {
+                ui.weak(name.to_string_lossy().to_string());
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.separator();
+
+        changed
+    }
+
+    pub fn current_file(&self) -> Option<&Path> {
+        self.files.get(self.frame).map(PathBuf::as_path)
+    }
+
+    /// List the files matching the pattern and read the first one, returns
+    /// true when a frame could be read
+    pub fn list(&mut self) -> bool {
+        match list_files(&self.pattern) {
+            Ok(files) if files.is_empty() => {
+                self.files = files;
+                self.error = Some(format!("No image matches {}", self.pattern));
+                false
+            }
+            Ok(files) => {
+                self.files = files;
+                self.frame = 0;
+                self.set_frame(0).is_ok()
+            }
+            Err(error) => {
+                self.files.clear();
+                self.error = Some(error);
+                false
+            }
+        }
+    }
+
+    /// Read the image of a frame
+    pub fn set_frame(&mut self, frame: usize) -> Result<(), String> {
+        let path = self
+            .files
+            .get(frame)
+            .ok_or_else(|| format!("There is no frame {}", frame + 1))?;
+
+        self.frame = frame;
+
+        match image::load_image_file(path) {
+            Ok(decoded) => {
+                self.image = decoded.image;
+                self.float = decoded.float;
+                self.format = Some(decoded.format);
+                self.error = None;
+                Ok(())
+            }
+            Err(error) => {
+                self.error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+}
+
+/// The image files of a directory, or the files of a directory whose name
+/// matches the wildcards of the pattern, sorted by name
+pub fn list_files(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+
+    let (directory, name_pattern) = if path.is_dir() {
+        (path, "*")
+    } else {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        (directory, name)
+    };
+
+    let entries = std::fs::read_dir(directory)
+        .map_err(|err| format!("Failed to list {}: {}", directory.display(), err))?;
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_image(path))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| matches(name_pattern, name))
+        })
+        .collect();
+
+    files.sort();
+
+    Ok(files)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            image::IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+// Match a name with a pattern where `*` stands for any characters and `?` for
+// a single one
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position in the pattern after the last star, and in the name where it
+    // started to match, to backtrack when the rest does not match
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_characters() {
+        assert!(matches("*.png", "frame_001.png"));
+        assert!(matches("*.png", ".png"));
+        assert!(matches("frame_*_*.png", "frame_1_left.png"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYbZc"));
+    }
+
+    #[test]
+    fn question_mark_matches_a_single_character() {
+        assert!(matches("frame_???.png", "frame_001.png"));
+        assert!(!matches("frame_???.png", "frame_01.png"));
+        assert!(!matches("frame_???.png", "frame_0001.png"));
+        assert!(matches("é?", "éa"));
+    }
+
+    #[test]
+    fn names_not_matching_are_rejected() {
+        assert!(!matches("*.png", "frame.jpg"));
+        assert!(!matches("*.png", "frame.png.bak"));
+        assert!(!matches("frame*", "clip_frame"));
+        assert!(!matches("", "frame.png"));
+        assert!(matches("", ""));
+    }
+}
@@ -1,3 +1,7 @@
+pub mod animation_player;
+pub mod expression_editor;
+pub mod image_download;
 pub mod image_fetcher;
 pub mod image_painter;
+pub mod image_sequence;
 pub mod image_uploader;
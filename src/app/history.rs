@@ -0,0 +1,352 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::app::components::graph::node::{
+    evaluate_graph, EditorState, NodeId, ProcessGraph, ValueType,
+};
+use crate::app::state::{AppState, SelectedNode};
+
+/// Maximum number of edits that can be undone
+const HISTORY_MAX_ENTRIES: usize = 100;
+
+/// Memory the history may use before the oldest edits are forgotten
+const HISTORY_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// The state of the graph editor between two edits. Only what the user edits
+/// is stored, the outputs are computed again when a snapshot is restored.
+struct Snapshot {
+    graph: ProcessGraph,
+    node_positions: Vec<(NodeId, egui::Pos2)>,
+    node_order: Vec<NodeId>,
+}
+
+impl Snapshot {
+    fn new(editor: &EditorState) -> Self {
+        Self {
+            graph: editor.graph.clone(),
+            node_positions: editor
+                .node_positions
+                .iter()
+                .map(|(id, pos)| (id, *pos))
+                .collect(),
+            node_order: editor.node_order.clone(),
+        }
+    }
+
+    fn restore(&self, editor: &mut EditorState) {
+        editor.graph = self.graph.clone();
+
+        editor.node_positions.clear();
+        for (id, pos) in &self.node_positions {
+            editor.node_positions.insert(*id, *pos);
+        }
+
+        editor.node_order = self.node_order.clone();
+        editor.selected_node = None;
+        editor.connection_in_progress = None;
+    }
+
+    // Approximate size of the snapshot, fetched images are shared between
+    // snapshots and are only counted the first time they are seen
+    fn memory_size(&self, seen: &mut HashSet<*const ()>) -> usize {
+        let mut size = self.graph.inputs.len() * std::mem::size_of::<ValueType>()
+            + self.node_positions.len() * std::mem::size_of::<(NodeId, egui::Pos2)>()
+            + self.node_order.len() * std::mem::size_of::<NodeId>();
+
+        for (_, input) in self.graph.inputs.iter() {
+            let (images, float) = match &input.value {
+                ValueType::ImageFetcher { value } => (vec![&value.image], &value.float),
+                ValueType::ImageSequence { value } => (vec![&value.image], &value.float),
+                ValueType::Animation { value } => (
+                    value.frames.iter().map(|frame| &frame.image).collect(),
+                    &None,
+                ),
+                _ => continue,
+            };
+
+            for image in images {
+                if seen.insert(Arc::as_ptr(image) as *const ()) {
+                    size += image.pixels.len() * std::mem::size_of::<egui::Color32>();
+                }
+            }
+
+            // The high bit depth images are also kept in floating point
+            if let Some(float) = float {
+                if seen.insert(Arc::as_ptr(float) as *const ()) {
+                    size += float.bytes();
+                }
+            }
+        }
+
+        size
+    }
+}
+
+/// Undo and redo stacks of the graph edits
+pub struct History {
+    current: Snapshot,
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// Memory the snapshots may use, in bytes
+    budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            current: Snapshot {
+                graph: ProcessGraph::new(),
+                node_positions: vec![],
+                node_order: vec![],
+            },
+            undo: VecDeque::new(),
+            redo: vec![],
+            budget: HISTORY_MEMORY_BUDGET,
+        }
+    }
+}
+
+impl History {
+    /// Forget every edit and start again from the current state of the editor
+    pub fn reset(&mut self, editor: &EditorState) {
+        self.current = Snapshot::new(editor);
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Record the current state of the editor as a new edit
+    pub fn commit(&mut self, editor: &EditorState) {
+        let previous = std::mem::replace(&mut self.current, Snapshot::new(editor));
+
+        self.undo.push_back(previous);
+        self.redo.clear();
+
+        self.enforce_budget();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Returns true if nodes were moved since the last recorded edit
+    pub fn nodes_moved(&self, editor: &EditorState) -> bool {
+        self.current
+            .node_positions
+            .iter()
+            .any(|(id, pos)| editor.node_positions.get(*id) != Some(pos))
+    }
+
+    fn undo(&mut self, editor: &mut EditorState) -> bool {
+        match self.undo.pop_back() {
+            Some(snapshot) => {
+                snapshot.restore(editor);
+                self.redo
+                    .push(std::mem::replace(&mut self.current, snapshot));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn redo(&mut self, editor: &mut EditorState) -> bool {
+        match self.redo.pop() {
+            Some(snapshot) => {
+                snapshot.restore(editor);
+                self.undo
+                    .push_back(std::mem::replace(&mut self.current, snapshot));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Forget the oldest edits while the history is too long or too large
+    fn enforce_budget(&mut self) {
+        while self.undo.len() > HISTORY_MAX_ENTRIES {
+            self.undo.pop_front();
+        }
+
+        while !self.undo.is_empty() && self.memory_size() > self.budget {
+            self.undo.pop_front();
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        let mut seen = HashSet::new();
+
+        // The images of the current snapshot are shared with the editor
+        self.current.memory_size(&mut seen);
+
+        self.undo
+            .iter()
+            .chain(self.redo.iter())
+            .map(|snapshot| snapshot.memory_size(&mut seen))
+            .sum()
+    }
+}
+
+/// Revert the last edit of the graph
+pub fn undo(state: &mut AppState) {
+    if state.history.undo(&mut state.graph) {
+        refresh(state);
+    }
+}
+
+/// Apply again the last reverted edit of the graph
+pub fn redo(state: &mut AppState) {
+    if state.history.redo(&mut state.graph) {
+        refresh(state);
+    }
+}
+
+// The outputs of the restored graph are not known anymore
+fn refresh(state: &mut AppState) {
+    state.selected_node = SelectedNode::default();
+
+    if state.auto_compute {
+        evaluate_graph(&mut state.graph);
+    } else {
+        state.graph.user_state.outputs_cache.clear();
+        state.graph.user_state.outputs_thumbnails.clear();
+        state.graph.user_state.node_errors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{Color32, ColorImage};
+    use egui_node_graph::{InputParamKind, NodeTemplateTrait};
+
+    use super::*;
+    use crate::app::components::graph::node::{DataType, GraphState, NodeTemplate};
+    use crate::app::components::input::image_fetcher::Fetcher;
+
+    fn editor() -> EditorState {
+        EditorState::new(1.0, GraphState::default())
+    }
+
+    // Add a node holding a fetched image taking 400 bytes
+    fn add_image(editor: &mut EditorState) -> NodeId {
+        let data = NodeTemplate::ImageFetcher.user_data();
+        let node_id = editor.graph.add_node("Image".to_string(), data, |_, _| {});
+        let value = Fetcher {
+            image: Arc::new(ColorImage::new([10, 10], Color32::RED)),
+            ..Default::default()
+        };
+        editor.graph.add_input_param(
+            node_id,
+            "image".to_string(),
+            DataType::Image,
+            ValueType::ImageFetcher { value },
+            InputParamKind::ConstantOnly,
+            true,
+        );
+        editor.node_positions.insert(node_id, egui::Pos2::ZERO);
+        editor.node_order.push(node_id);
+
+        node_id
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_edits() {
+        let mut editor = editor();
+        let mut history = History::default();
+        history.reset(&editor);
+
+        add_image(&mut editor);
+        history.commit(&editor);
+        add_image(&mut editor);
+        history.commit(&editor);
+
+        assert!(history.undo(&mut editor));
+        assert_eq!(editor.graph.nodes.len(), 1);
+        assert!(history.undo(&mut editor));
+        assert_eq!(editor.graph.nodes.len(), 0);
+        assert!(!history.undo(&mut editor));
+
+        assert!(history.redo(&mut editor));
+        assert_eq!(editor.graph.nodes.len(), 1);
+        assert_eq!(editor.node_order.len(), 1);
+        assert!(history.can_undo() && history.can_redo());
+    }
+
+    #[test]
+    fn new_edits_forget_the_redo_stack() {
+        let mut editor = editor();
+        let mut history = History::default();
+        history.reset(&editor);
+
+        add_image(&mut editor);
+        history.commit(&editor);
+        history.undo(&mut editor);
+        assert!(history.can_redo());
+
+        add_image(&mut editor);
+        history.commit(&editor);
+
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut editor));
+    }
+
+    #[test]
+    fn moved_nodes_are_detected() {
+        let mut editor = editor();
+        let mut history = History::default();
+        let node_id = add_image(&mut editor);
+        history.reset(&editor);
+
+        assert!(!history.nodes_moved(&editor));
+
+        editor.node_positions[node_id] = egui::pos2(10.0, 0.0);
+        assert!(history.nodes_moved(&editor));
+    }
+
+    #[test]
+    fn oldest_edits_are_forgotten_past_the_memory_budget() {
+        let mut editor = editor();
+        let node_id = add_image(&mut editor);
+        let input_id = editor.graph[node_id].get_input("image").unwrap();
+
+        let mut history = History::default();
+        history.reset(&editor);
+        // Room for three edits and a bit, but not for four
+        history.budget = 4 * 400 + 3 * std::mem::size_of::<ValueType>();
+
+        // Each edit fetches another image, the previous ones are only kept by
+        // the history
+        for _ in 0..10 {
+            editor.graph.inputs[input_id].value = ValueType::ImageFetcher {
+                value: Fetcher {
+                    image: Arc::new(ColorImage::new([10, 10], Color32::RED)),
+                    ..Default::default()
+                },
+            };
+            history.commit(&editor);
+        }
+
+        assert!(history.memory_size() <= history.budget);
+        assert_eq!(history.undo.len(), 3);
+    }
+
+    #[test]
+    fn oldest_edits_are_forgotten_past_the_maximum_count() {
+        let mut editor = editor();
+        let mut history = History::default();
+        history.reset(&editor);
+
+        for i in 0..HISTORY_MAX_ENTRIES + 10 {
+            editor.node_order.push(editor.graph.add_node(
+                i.to_string(),
+                NodeTemplate::ScalarValue.user_data(),
+                |_, _| {},
+            ));
+            history.commit(&editor);
+        }
+
+        assert_eq!(history.undo.len(), HISTORY_MAX_ENTRIES);
+    }
+}
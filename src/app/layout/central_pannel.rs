@@ -1,9 +1,17 @@
 use egui::TextStyle;
 
+use crate::app::components::display::node_profiler;
+
+use crate::app::components::graph::batch;
+use crate::app::components::graph::clipboard;
+use crate::app::components::graph::group;
 use crate::app::components::graph::node::*;
+use crate::app::history;
 use crate::app::state::{self, SelectedNode};
 
-use egui_node_graph::NodeResponse;
+use egui_node_graph::{NodeId, NodeResponse};
+
+const MEGABYTE: f64 = 1024.0 * 1024.0;
 
 pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context) {
     show_background(ui);
@@ -12,7 +20,61 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
         show_state(state, ui, ctx);
     }
 
-    let responses = state.graph.draw_graph_editor(ui, AllNodeTemplates);
+    show_shortcuts(state, ui, ctx);
+
+    let editor_rect = ui.max_rect();
+    let responses = state.graph.draw_graph_editor(
+        ui,
+        AllNodeTemplates {
+            library: &state.library,
+        },
+    );
+
+    if state.d_profiler && state.o_profiler.badges {
+        show_timings(state, ui, editor_rect);
+    }
+
+    // Apply the changes of node inputs requested from inside the nodes
+    responses
+        .node_responses
+        .iter()
+        .for_each(|event| match event {
+            NodeResponse::User(Response::AddVariable(node_id, typ)) => {
+                add_expression_variable(&mut state.graph.graph, *node_id, *typ)
+            }
+            NodeResponse::User(Response::RemoveVariable(node_id)) => {
+                remove_expression_variable(&mut state.graph.graph, *node_id)
+            }
+            NodeResponse::User(Response::ProcessSequence(node_id)) => batch::start(state, *node_id),
+            _ => {}
+        });
+
+    // Record the edits of the graph so that they can be undone
+    let graph_edited = responses.node_responses.iter().any(|event| {
+        matches!(
+            event,
+            NodeResponse::CreatedNode(_)
+                | NodeResponse::DeleteNodeFull {
+                    node: _,
+                    node_id: _
+                }
+                | NodeResponse::ConnectEventEnded {
+                    output: _,
+                    input: _
+                }
+                | NodeResponse::DisconnectEvent {
+                    output: _,
+                    input: _
+                }
+        ) || matches!(event, NodeResponse::User(response) if response.is_edit())
+    });
+
+    // Nodes are moved by dragging them, the move is recorded once released
+    let nodes_moved = ui.input().pointer.any_released() && state.history.nodes_moved(&state.graph);
+
+    if graph_edited || nodes_moved {
+        state.history.commit(&state.graph);
+    }
 
     if state.auto_compute {
         let must_refresh = responses.node_responses.iter().find(|&event| match event {
@@ -40,6 +102,15 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
                 Response::ScalarChanged => true,
                 Response::BooleanChanged => true,
                 Response::IntegerChanged => true,
+                Response::ChoiceChanged => true,
+                Response::ColorChanged => true,
+                Response::ExpressionChanged => true,
+                Response::FrameChanged => true,
+                Response::TextChanged => false,
+                Response::EditFinished => false,
+                Response::ProcessSequence(_) => false,
+                Response::AddVariable(_, _) => true,
+                Response::RemoveVariable(_) => true,
             },
         });
 
@@ -53,28 +124,30 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
         if let NodeResponse::SelectNode(node_id) = event {
             state.selected_node = SelectedNode::default(); // reset node
             state.selected_node.node_id = Some(*node_id);
-        }
-    });
 
-    // Check if we need to update the current selected node
-    responses.node_responses.iter().for_each(|event| {
-        let mut input_updated = false;
-
-        if let NodeResponse::User(Response::ImageFetched) = event {
-            input_updated = true;
-        }
-
-        if let NodeResponse::User(Response::ScalarChanged) = event {
-            input_updated = true;
+            // Holding shift adds or removes the node from the multiple selection
+            let selection = &mut state.graph.user_state.selection;
+            if ui.input().modifiers.shift {
+                if !selection.remove(node_id) {
+                    selection.insert(*node_id);
+                }
+            } else {
+                selection.clear();
+                selection.insert(*node_id);
+            }
         }
 
-        if input_updated {
-            let temp_selected = state.selected_node.node_id;
-            state.selected_node = SelectedNode::default(); // reset node
-            state.selected_node.node_id = temp_selected; // trigger update
+        if let NodeResponse::DeleteNodeFull { node_id, node } = event {
+            state.graph.user_state.selection.remove(node_id);
+            release_outputs(&mut state.graph, node);
         }
     });
 
+    // Clicking the background unselects everything
+    if state.graph.selected_node.is_none() {
+        state.graph.user_state.selection.clear();
+    }
+
     // Check if the current node was removed
     responses.node_responses.iter().for_each(|event| {
         if let NodeResponse::DeleteNodeUi(deleted_node) = event {
@@ -87,47 +160,270 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
     });
 }
 
-pub fn show_state(state: &mut state::AppState, _ui: &mut egui::Ui, ctx: &egui::Context) {
-    let output_label = |val: &ValueType| match val {
-        ValueType::Image { value } => Some(format!(
-            "Image of dimension {}x{}",
-            value.size[0], value.size[1]
-        )),
-        ValueType::ImageFetcher { value } => Some(format!(
-            "Image of dimension {}x{}",
-            value.image.size[0], value.image.size[1]
-        )),
-        ValueType::Slice { value } => Some(format!(
-            "Image slice of dimension {}x{}",
-            value.size[0], value.size[1]
-        )),
-        ValueType::_Color { value } => Some(format!(
-            "Color of value ({}, {}, {})",
-            value.r(),
-            value.g(),
-            value.b()
-        )),
-        ValueType::Scalar { value } => Some(format!("Scalar of value {}", value)),
-        ValueType::Integer { value } => Some(format!("Integer of value {}", value)),
-        ValueType::Boolean { value } => Some(format!("Boolean of value {}", value)),
+pub fn show_shortcuts(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context) {
+    // Text fields handle their own undo, copy and paste
+    if ctx.wants_keyboard_input() {
+        return;
+    }
+
+    let (undo, redo, duplicate, select_all, group, copy, pasted) = {
+        let input = ctx.input();
+        let command = input.modifiers.command;
+        let pressed = command && input.key_pressed(egui::Key::Z);
+
+        let copy = input.events.iter().any(|e| matches!(e, egui::Event::Copy));
+        let pasted = input.events.iter().find_map(|e| match e {
+            egui::Event::Paste(text) => Some(text.clone()),
+            _ => None,
+        });
+
+        (
+            pressed && !input.modifiers.shift,
+            pressed && input.modifiers.shift,
+            command && input.key_pressed(egui::Key::D),
+            command && input.key_pressed(egui::Key::A),
+            command && input.key_pressed(egui::Key::G),
+            copy,
+            pasted,
+        )
     };
 
-    let outputs_cache = state
-        .graph
-        .user_state
-        .outputs_cache
-        .iter()
-        .map(|(id, val)| format!("{:?} <=> {:?}", &id, output_label(val)))
-        .reduce(|a, b| format!("{}\n{}", a, b));
-
-    if let Some(debug) = outputs_cache {
-        ctx.debug_painter().text(
-            egui::pos2(30.0, 30.0),
-            egui::Align2::LEFT_TOP,
-            format!("GRAPH STATE\n{}", debug),
-            TextStyle::Monospace.resolve(&ctx.style()),
-            egui::Color32::RED,
+    if undo {
+        history::undo(state);
+    }
+
+    if redo {
+        history::redo(state);
+    }
+
+    if duplicate {
+        clipboard::duplicate(state);
+    }
+
+    if select_all {
+        clipboard::select_all(state);
+    }
+
+    if group {
+        group::open_dialog(state);
+    }
+
+    if copy {
+        if let Some(text) = clipboard::copy(state) {
+            ctx.output().copied_text = text;
+        }
+    }
+
+    if let Some(text) = pasted {
+        // Paste under the cursor, converted to the graph coordinates
+        let editor_rect = ui.max_rect();
+        let cursor = ctx
+            .input()
+            .pointer
+            .hover_pos()
+            .filter(|pos| editor_rect.contains(*pos))
+            .unwrap_or_else(|| editor_rect.center());
+        let position = cursor - state.graph.pan_zoom.pan - editor_rect.min.to_vec2();
+
+        if let Err(error) = clipboard::paste_text(state, &text, position) {
+            tracing::warn!("Failed to paste the nodes: {}", error);
+        }
+    }
+}
+
+/// Window listing the nodes of the graph with their inputs, outputs and the
+/// result of their last evaluation
+pub fn show_state(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context) {
+    let editor_rect = ui.max_rect();
+    let mut open = true;
+    let mut target = None;
+
+    egui::Window::new("🐞 Graph state")
+        .open(&mut open)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            let user_state = &state.graph.user_state;
+            let cache = &user_state.outputs_cache;
+
+            ui.label(format!(
+                "Cache: {} values, {:.1} / {:.0} MB, {} evicted",
+                cache.iter().count(),
+                cache.memory() as f64 / MEGABYTE,
+                cache.budget as f64 / MEGABYTE,
+                cache.evictions
+            ));
+            ui.label(format!("Evaluations: {}", user_state.evaluation));
+
+            ui.separator();
+
+            for node_id in state.graph.node_order.iter().copied() {
+                if let Some(node_id) = show_node_state(ui, &state.graph, node_id) {
+                    target = Some(node_id);
+                }
+            }
+        });
+
+    state.d_state = open;
+
+    if let Some(node_id) = target {
+        focus_node(state, node_id, editor_rect);
+    }
+}
+
+// Details of a node, returns the node to navigate to when a link is clicked
+fn show_node_state(ui: &mut egui::Ui, editor: &EditorState, node_id: NodeId) -> Option<NodeId> {
+    let graph = &editor.graph;
+    let user_state = &editor.user_state;
+    let node = graph.nodes.get(node_id)?;
+    let mut target = None;
+
+    let error = user_state.node_errors.get(&node_id);
+    let title = match error {
+        Some(_) => egui::RichText::new(format!("⚠ {}", node.label)).color(egui::Color32::RED),
+        None => egui::RichText::new(&node.label),
+    };
+
+    egui::CollapsingHeader::new(title)
+        .id_source(node_id)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("🎯 Select").clicked() {
+                    target = Some(node_id);
+                }
+
+                match user_state.profiler.get(node_id) {
+                    Some(profile) => ui.label(format!(
+                        "Evaluated in {}",
+                        node_profiler::format_duration(profile.time)
+                    )),
+                    None => ui.label("Not evaluated"),
+                };
+            });
+
+            if let Some(error) = error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            egui::Grid::new(("node state", node_id))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (name, input_id) in node.inputs.iter() {
+                        ui.label(format!("⮊ {}", name));
+
+                        match graph.connection(*input_id) {
+                            Some(output_id) => {
+                                let source = graph[output_id].node;
+                                let output_name = graph[source]
+                                    .outputs
+                                    .iter()
+                                    .find(|(_, id)| *id == output_id)
+                                    .map_or("?", |(name, _)| name.as_str());
+
+                                let link = format!("{}.{}", graph[source].label, output_name);
+                                if ui.link(link).clicked() {
+                                    target = Some(source);
+                                }
+                            }
+                            None => {
+                                ui.label(value_label(&graph[*input_id].value));
+                            }
+                        }
+                        ui.end_row();
+                    }
+
+                    for (name, output_id) in node.outputs.iter() {
+                        ui.label(format!("⮈ {}", name));
+
+                        match user_state.outputs_cache.get(output_id) {
+                            Some(value) => ui.label(value_label(value)),
+                            None if user_state.outputs_thumbnails.contains_key(output_id) => {
+                                ui.weak("Evicted from the cache")
+                            }
+                            None => ui.weak("Not computed"),
+                        };
+                        ui.end_row();
+                    }
+                });
+        });
+
+    target
+}
+
+// Short description of a value, with the size of the images
+fn value_label(value: &ValueType) -> String {
+    match value {
+        ValueType::Image { value } => format!("Image {}x{}", value.size[0], value.size[1]),
+        ValueType::FloatImage { value, .. } => {
+            format!("Float image {}x{}", value.size[0], value.size[1])
+        }
+        ValueType::ImageFetcher { value } => {
+            format!("Image {}x{}", value.image.size[0], value.image.size[1])
+        }
+        ValueType::ImageSequence { value } => match value.current_file() {
+            Some(file) => format!("Frame {} of {}", value.frame + 1, file.display()),
+            None => "No file".to_string(),
+        },
+        ValueType::Animation { value } if value.frames.is_empty() => "No file".to_string(),
+        ValueType::Animation { value } => {
+            format!("Frame {} of {}", value.frame + 1, value.path)
+        }
+        ValueType::Slice { value } => format!("Slice {}x{}", value.size[0], value.size[1]),
+        ValueType::Color { value } => {
+            format!("Color ({}, {}, {})", value.r(), value.g(), value.b())
+        }
+        ValueType::Scalar { value, .. } => format!("Scalar {}", value),
+        ValueType::Integer { value, .. } => format!("Integer {}", value),
+        ValueType::Boolean { value } => format!("Boolean {}", value),
+        ValueType::Choice { value } => format!("Choice {}", value.label()),
+        ValueType::Expression { value } => format!("Expression {}", value.source),
+        ValueType::Text { value } => format!("Text {}", value),
+    }
+}
+
+// Select a node and move the view so that it is in the middle of the editor
+fn focus_node(state: &mut state::AppState, node_id: NodeId, editor_rect: egui::Rect) {
+    state.graph.selected_node = Some(node_id);
+    state.graph.node_order.retain(|id| *id != node_id);
+    state.graph.node_order.push(node_id);
+
+    state.graph.user_state.selection.clear();
+    state.graph.user_state.selection.insert(node_id);
+
+    state.selected_node = SelectedNode::default(); // reset node
+    state.selected_node.node_id = Some(node_id);
+
+    if let Some(position) = state.graph.node_positions.get(node_id) {
+        state.graph.pan_zoom.pan = editor_rect.size() / 2.0 - position.to_vec2();
+    }
+}
+
+/// Display the time each node took during the last evaluation above its title bar
+pub fn show_timings(state: &state::AppState, ui: &mut egui::Ui, editor_rect: egui::Rect) {
+    let profiler = &state.graph.user_state.profiler;
+    let total = profiler.total();
+    let font = TextStyle::Small.resolve(ui.style());
+    let painter = ui.painter_at(editor_rect);
+
+    for (node_id, position) in state.graph.node_positions.iter() {
+        let profile = match profiler.get(node_id) {
+            Some(profile) if profile.evaluations > 0 => profile,
+            _ => continue,
+        };
+
+        // Same coordinates as the nodes drawn by the editor
+        let corner = *position + state.graph.pan_zoom.pan + editor_rect.min.to_vec2();
+
+        let text = node_profiler::format_duration(profile.time);
+        let galley = painter.layout_no_wrap(text, font.clone(), egui::Color32::BLACK);
+        let badge = egui::Rect::from_min_size(
+            corner - egui::vec2(0.0, galley.size().y + 6.0),
+            galley.size() + egui::vec2(8.0, 4.0),
         );
+
+        let color = node_profiler::time_color(profile.time, total);
+        painter.rect_filled(badge, 3.0, color);
+        painter.galley(badge.min + egui::vec2(4.0, 2.0), galley);
     }
 }
 
@@ -0,0 +1,114 @@
+use egui_node_graph::{InputParamKind, WidgetValueTrait};
+
+use crate::app::components::graph::node::*;
+use crate::app::components::graph::registry;
+use crate::app::state::{self, SelectedNode};
+
+pub fn show(state: &mut state::AppState, ui: &mut egui::Ui) {
+    let node_id = state
+        .selected_node
+        .node_id
+        .filter(|id| state.graph.graph.nodes.contains_key(*id));
+
+    match node_id {
+        Some(node_id) => {
+            egui::ScrollArea::vertical().show(ui, |ui| show_node(state, ui, node_id));
+        }
+        None => {
+            ui.allocate_ui_with_layout(
+                ui.available_size(),
+                egui::Layout::centered_and_justified(egui::Direction::TopDown),
+                |ui| ui.label("Select a node to edit its parameters"),
+            );
+        }
+    }
+}
+
+fn show_node(state: &mut state::AppState, ui: &mut egui::Ui, node_id: NodeId) {
+    let graph = &mut state.graph.graph;
+    let node = &graph[node_id];
+
+    match registry::describe(&node.user_data.template) {
+        Some(description) => {
+            ui.heading(format!("{} {}", description.icon, node.label));
+            ui.label(description.description);
+        }
+        None => {
+            ui.heading(&node.label);
+        }
+    }
+
+    ui.separator();
+
+    let inputs = node.inputs.clone();
+    let mut changed = false;
+    let mut edited = false;
+
+    for (name, input_id) in inputs {
+        let connected = graph.connection(input_id).is_some();
+        let input = &mut graph[input_id];
+
+        // Inputs fed by another node have no value to edit
+        if connected || input.kind() == InputParamKind::ConnectionOnly {
+            let status = if connected {
+                "connected"
+            } else {
+                "not connected"
+            };
+            ui.weak(format!("⮊ {}: {}", name, status));
+            continue;
+        }
+
+        ui.horizontal(|ui| {
+            let responses = input.value.value_widget(&name, ui);
+            changed |= responses
+                .iter()
+                .any(|response| *response != Response::EditFinished);
+            edited |= responses.iter().any(|response| response.is_edit());
+
+            if reset_button(ui, &mut input.value) {
+                changed = true;
+                edited = true;
+            }
+        });
+    }
+
+    if edited {
+        state.history.commit(&state.graph);
+    }
+
+    if changed {
+        if state.auto_compute {
+            evaluate_graph(&mut state.graph);
+        }
+
+        // Refresh the preview of the node
+        state.selected_node = SelectedNode::default();
+        state.selected_node.node_id = Some(node_id);
+    }
+}
+
+// Numeric parameters can be reset to the value they were created with
+fn reset_button(ui: &mut egui::Ui, value: &mut ValueType) -> bool {
+    let (modified, default) = match value {
+        ValueType::Scalar { value, info } => (*value != info.default as f32, info.default),
+        ValueType::Integer { value, info } => (*value != info.default as i32, info.default),
+        _ => return false,
+    };
+
+    let button = egui::Button::new("⟲").small();
+    let clicked = ui
+        .add_enabled(modified, button)
+        .on_hover_text(format!("Reset to {}", default))
+        .clicked();
+
+    if clicked {
+        match value {
+            ValueType::Scalar { value, info } => *value = info.default as f32,
+            ValueType::Integer { value, info } => *value = info.default as i32,
+            _ => {}
+        }
+    }
+
+    clicked
+}
@@ -1,4 +1,5 @@
 pub mod central_pannel;
+pub mod inspector_pannel;
 pub mod output_pannel;
 pub mod side_pannel;
 pub mod top_bar;
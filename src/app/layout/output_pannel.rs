@@ -1,14 +1,26 @@
 use egui::ColorImage;
 use egui_extras::RetainedImage;
-use std::collections::HashMap;
 
-use crate::app::components::display;
+use crate::app::components::display::image_compare::{self, CompareOptions, Side};
+use crate::app::components::display::image_viewer::{self, ImageView};
+use crate::app::components::display::{self, image_histogram::HistogramOptions};
 use crate::app::components::graph::node::{self, *};
+use crate::app::math::blend::difference_image;
 use crate::app::math::image::slice_to_image;
-use crate::app::state;
+use crate::app::math::statistics::{Channel, Statistics};
+use crate::app::state::{self, Compare, ComparedImages, SelectedNode};
+
+const VIEWER_ID: &str = "output viewer";
+const WINDOW_VIEWER_ID: &str = "detached output viewer";
 
 pub fn show(state: &mut state::AppState, ui: &mut egui::Ui) {
-    egui::ScrollArea::vertical().show(ui, |ui| {
+    // The mouse wheel zooms the viewers instead of scrolling the panel
+    let scrolling =
+        !image_viewer::hovered(ui.ctx(), VIEWER_ID) && !image_compare::hovered(ui.ctx());
+
+    let scroll_area = egui::ScrollArea::vertical().enable_scrolling(scrolling);
+
+    scroll_area.show(ui, |ui| {
         if let Some(selected_id) = state.selected_node.node_id {
             let selected_node = state
                 .graph
@@ -19,16 +31,45 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui) {
 
             // Check if the node exist in the graph
             if let Some((_, node)) = selected_node {
-                let outputs = node.output_ids();
+                // The node outputs that correspond to an image
+                let outputs: Vec<(String, OutputId)> = node
+                    .outputs
+                    .iter()
+                    .filter(|(_, id)| state.graph.user_state.outputs_thumbnails.contains_key(id))
+                    .cloned()
+                    .collect();
 
-                // Try to find a node output that correspond to an image
-                let find_image = outputs
-                    .into_iter()
-                    .find(|id| state.graph.user_state.outputs_images.contains_key(id));
+                // Preview the output chosen for this node, or the first one
+                let chosen = state
+                    .previewed_outputs
+                    .get(&selected_id)
+                    .filter(|&id| outputs.iter().any(|(_, other)| other == id))
+                    .or_else(|| outputs.first().map(|(_, id)| id))
+                    .copied();
 
-                // If the image was found, we update the value
-                if let Some(output_id) = find_image {
-                    show_selected(state, ui, output_id);
+                if let Some(mut output_id) = chosen {
+                    if outputs.len() > 1 {
+                        show_output_tabs(ui, &outputs, &mut output_id, &mut state.o_overview);
+                        state.previewed_outputs.insert(selected_id, output_id);
+                    }
+
+                    if outputs.len() > 1 && state.o_overview {
+                        let thumbnails = &state.graph.user_state.outputs_thumbnails;
+                        if let Some(output_id) = show_output_overview(ui, &outputs, thumbnails) {
+                            state.previewed_outputs.insert(selected_id, output_id);
+                            state.o_overview = false;
+                        }
+                    } else {
+                        show_selected(state, ui, output_id);
+                    }
+                }
+
+                // Crop nodes can be edited directly on their input image
+                if state.o_pannel == state::OutputPanel::Image {
+                    if let Some((source_id, region)) = crop_region(&state.graph.graph, selected_id)
+                    {
+                        show_crop_region(state, ui, selected_id, source_id, region);
+                    }
                 }
             }
         } else {
@@ -45,34 +86,109 @@ fn show_not_selected(ui: &mut egui::Ui) {
     );
 }
 
+// One tab per output of the node, and one for the overview of all of them
+fn show_output_tabs(
+    ui: &mut egui::Ui,
+    outputs: &[(String, OutputId)],
+    output_id: &mut OutputId,
+    overview: &mut bool,
+) {
+    ui.horizontal_wrapped(|ui| {
+        for (name, id) in outputs {
+            if ui
+                .selectable_label(!*overview && output_id == id, name)
+                .clicked()
+            {
+                *output_id = *id;
+                *overview = false;
+            }
+        }
+
+        ui.separator();
+        ui.selectable_value(overview, true, "▦ All");
+    });
+
+    ui.separator();
+}
+
+// Thumbnails of every output, returns the one clicked to preview it alone
+fn show_output_overview(
+    ui: &mut egui::Ui,
+    outputs: &[(String, OutputId)],
+    thumbnails: &OutputsThumbnails,
+) -> Option<OutputId> {
+    let spacing = ui.spacing().item_spacing.x;
+    let columns = if outputs.len() > 4 { 3.0 } else { 2.0 };
+    let tile = (ui.available_width() - spacing * (columns - 1.0)) / columns;
+
+    let mut clicked = None;
+
+    ui.horizontal_wrapped(|ui| {
+        for (name, id) in outputs {
+            if let Some(thumbnail) = thumbnails.get(id) {
+                ui.vertical(|ui| {
+                    ui.set_width(tile);
+
+                    let size = thumbnail.size_vec2();
+                    let size = size * (tile / size.x).min(tile / size.y);
+                    let button = egui::ImageButton::new(thumbnail.texture_id(ui.ctx()), size);
+
+                    if ui
+                        .add(button)
+                        .on_hover_text("Preview this output")
+                        .clicked()
+                    {
+                        clicked = Some(*id);
+                    }
+                    ui.label(name);
+                });
+            }
+        }
+    });
+
+    clicked
+}
+
 fn show_selected(state: &mut state::AppState, ui: &mut egui::Ui, output_id: OutputId) {
+    // Another output of the node was chosen, or the graph was evaluated
+    // again, its preview is computed again
+    let evaluation = state.graph.user_state.evaluation;
+    if state.selected_node.output_id != Some(output_id)
+        || state.selected_node.evaluation != evaluation
+    {
+        state.selected_node.output_id = Some(output_id);
+        state.selected_node.evaluation = evaluation;
+        state.selected_node.color_image = None;
+        state.selected_node.retained_image = None;
+        state.selected_node.source_image = None;
+    }
+
     // Extract the color image from the results
     if state.selected_node.color_image.is_none() {
-        state.selected_node.color_image = match state.graph.user_state.outputs_cache.get(&output_id)
-        {
-            Some(node::ValueType::Image { value }) => Some(value.clone()),
+        let value = output_value(&mut state.graph, output_id);
+
+        state.selected_node.color_image = match &value {
+            Some(node::ValueType::Image { value }) => Some(value.as_ref().clone()),
+            Some(node::ValueType::FloatImage { image, .. }) => Some(image.as_ref().clone()),
             Some(node::ValueType::Slice { value }) => Some(slice_to_image(value)),
             _ => None,
         };
+        state.selected_node.slice = matches!(value, Some(node::ValueType::Slice { .. }));
+        state.selected_node.float = matches!(value, Some(node::ValueType::FloatImage { .. }));
+        state.selected_node.format = state
+            .graph
+            .graph
+            .outputs
+            .get(output_id)
+            .and_then(|output| node::source_format(&state.graph.graph, output.node));
 
-        // If the color image was just initialized
+        // If the color image was just initialized, we compute its statistics
+        // and histograms
         if let Some(color_image) = &state.selected_node.color_image {
-            // We compute and store the image spectrum
-            for px in &color_image.pixels {
-                for i in 0..3 {
-                    let intensity = px[i];
-
-                    if intensity == 0 {
-                        continue; // dont take into account the intensity 0
-                    }
-
-                    if let Some(&count) = &mut state.selected_node.spectrum[i].get(&intensity) {
-                        state.selected_node.spectrum[i].insert(intensity, count + 1);
-                    } else {
-                        state.selected_node.spectrum[i].insert(intensity, 1);
-                    }
-                }
-            }
+            state.selected_node.statistics = Channel::COLORS
+                .iter()
+                .map(|&channel| (channel, Statistics::of_image(color_image, channel)))
+                .collect();
         }
     }
 
@@ -88,7 +204,11 @@ fn show_selected(state: &mut state::AppState, ui: &mut egui::Ui, output_id: Outp
 
     if let Some(retained_image) = &state.selected_node.retained_image {
         if let Some(color_image) = &state.selected_node.color_image {
-            show_image_infos(ui, color_image, retained_image);
+            show_image_infos(ui, color_image, retained_image, &state.selected_node);
+
+            egui::CollapsingHeader::new("📊 Statistics").show(ui, |ui| {
+                show_image_statistics(ui, &state.selected_node.statistics);
+            });
 
             ui.separator();
 
@@ -100,31 +220,293 @@ fn show_selected(state: &mut state::AppState, ui: &mut egui::Ui, output_id: Outp
                 );
                 ui.selectable_value(
                     &mut state.o_pannel,
-                    state::OutputPanel::Spectrum,
-                    "Spectrum graph",
+                    state::OutputPanel::Histogram,
+                    "Histogram",
                 );
+                ui.selectable_value(&mut state.o_pannel, state::OutputPanel::Compare, "Compare");
             });
 
             ui.separator();
 
             match state.o_pannel {
-                state::OutputPanel::Image => show_image_display(ui, retained_image),
-                state::OutputPanel::Spectrum => {
-                    show_image_spectrum(ui, &state.selected_node.spectrum)
+                state::OutputPanel::Image if state.d_viewer => {
+                    ui.horizontal(|ui| {
+                        ui.label("The viewer is detached");
+                        if ui.button("📌 Attach").clicked() {
+                            state.d_viewer = false;
+                        }
+                    });
                 }
+                state::OutputPanel::Image => {
+                    if ui.button("🗗 Detach").clicked() {
+                        state.d_viewer = true;
+                    }
+
+                    let size = retained_image.size_vec2();
+                    let height = (ui.available_width() * size.y / size.x).clamp(160.0, 480.0);
+
+                    show_image_viewer(
+                        ui,
+                        VIEWER_ID,
+                        &mut state.viewer,
+                        retained_image,
+                        color_image,
+                        state.selected_node.slice,
+                        height,
+                    );
+                }
+                state::OutputPanel::Histogram => show_image_histogram(
+                    ui,
+                    &state.selected_node.statistics,
+                    &mut state.o_histogram,
+                ),
+                state::OutputPanel::Compare => show_compare(
+                    ui,
+                    &mut state.graph,
+                    &mut state.compare,
+                    &mut state.viewer,
+                    &mut state.o_compare,
+                    output_id,
+                ),
             }
         }
     }
 }
 
-fn show_image_display(ui: &mut egui::Ui, image: &RetainedImage) {
-    display::image_frame::show(ui, image);
+fn show_crop_region(
+    state: &mut state::AppState,
+    ui: &mut egui::Ui,
+    node_id: NodeId,
+    source_id: OutputId,
+    mut region: [usize; 4],
+) {
+    // The source is inspected in full resolution, to pick the region pixels
+    if state.selected_node.source_image.is_none() {
+        state.selected_node.source_image = output_value(&mut state.graph, source_id)
+            .and_then(|value| value.try_to_image().ok())
+            .map(|image| {
+                RetainedImage::from_color_image("crop source image", image.as_ref().clone())
+            });
+    }
+
+    if let Some(image) = &state.selected_node.source_image {
+        ui.separator();
+        ui.label("Crop region");
+
+        let released = display::image_crop::show(ui, image, &mut region);
+
+        set_crop_region(&mut state.graph.graph, node_id, region);
+
+        if released {
+            state.history.commit(&state.graph);
+        }
+
+        if released && state.auto_compute {
+            evaluate_graph(&mut state.graph);
+
+            state.selected_node = SelectedNode::default(); // reset node
+            state.selected_node.node_id = Some(node_id); // trigger update
+        }
+    }
+}
+
+fn show_compare(
+    ui: &mut egui::Ui,
+    graph: &mut EditorState,
+    compare: &mut Compare,
+    view: &mut ImageView,
+    options: &mut CompareOptions,
+    output_id: OutputId,
+) {
+    ui.horizontal(|ui| {
+        if ui.button("📌 Pin as A").clicked() {
+            compare.a = Some(output_id);
+        }
+        if ui.button("📌 Pin as B").clicked() {
+            compare.b = Some(output_id);
+        }
+        if ui.button("⇄ Swap").clicked() {
+            std::mem::swap(&mut compare.a, &mut compare.b);
+        }
+        if ui.button("✖ Clear").clicked() {
+            *compare = Compare::default();
+        }
+    });
+
+    ui.label(format!("A: {}", pin_label(&graph.graph, compare.a)));
+    ui.label(format!("B: {}", pin_label(&graph.graph, compare.b)));
+    ui.separator();
+
+    let (a, b) = match (compare.a, compare.b) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            ui.weak("Pin the outputs of two nodes to compare them");
+            return;
+        }
+    };
+
+    update_compared_images(graph, compare, a, b, options.gain);
+
+    match &compare.images {
+        Some(images) => {
+            let side_a = Side {
+                image: &images.retained_a,
+                pixels: &images.a,
+            };
+            let side_b = Side {
+                image: &images.retained_b,
+                pixels: &images.b,
+            };
+            let difference = Side {
+                image: &images.retained_difference,
+                pixels: &images.difference,
+            };
+
+            let size = images.retained_a.size_vec2();
+            let height = (ui.available_width() * size.y / size.x).clamp(160.0, 480.0);
+
+            let hovered =
+                image_compare::show(ui, view, options, side_a, side_b, difference, height);
+
+            // The same pixel of both images
+            let inside = |pixels: &egui::ColorImage| {
+                hovered.filter(|&[x, y]| x < pixels.size[0] && y < pixels.size[1])
+            };
+
+            ui.horizontal(|ui| {
+                ui.strong("A");
+                image_viewer::show_readout(ui, &images.a, inside(&images.a), false);
+            });
+            ui.horizontal(|ui| {
+                ui.strong("B");
+                image_viewer::show_readout(ui, &images.b, inside(&images.b), false);
+            });
+        }
+        None => {
+            ui.weak("The pinned outputs have no image, evaluate the graph");
+        }
+    }
+}
+
+// Name of the node and of the port of a pinned output
+fn pin_label(graph: &ProcessGraph, output_id: Option<OutputId>) -> String {
+    let output = output_id.and_then(|id| graph.outputs.get(id).map(|output| (id, output)));
+
+    match output {
+        Some((id, output)) => match graph.nodes.get(output.node) {
+            Some(node) => {
+                let port = node.outputs.iter().find(|(_, other)| *other == id);
+                let port = port.map_or("", |(name, _)| name.as_str());
+                format!("{} ⮈ {}", node.label, port)
+            }
+            None => "removed".to_string(),
+        },
+        None => "not pinned".to_string(),
+    }
+}
+
+// Compute the pixels of the pinned outputs and their difference again when
+// the pins, the graph or the gain changed
+fn update_compared_images(
+    graph: &mut EditorState,
+    compare: &mut Compare,
+    a: OutputId,
+    b: OutputId,
+    gain: f32,
+) {
+    let source = (a, b, graph.user_state.evaluation, gain);
+
+    if compare.images.as_ref().map(|images| images.source) == Some(source) {
+        return;
+    }
+
+    let mut pixels = |id| output_value(graph, id).and_then(|value| value.try_to_image().ok());
+
+    compare.images = match (pixels(a), pixels(b)) {
+        (Some(a), Some(b)) => {
+            let difference = difference_image(&a, &b, gain);
+
+            Some(ComparedImages {
+                source,
+                retained_a: RetainedImage::from_color_image("compared output A", (*a).clone()),
+                retained_b: RetainedImage::from_color_image("compared output B", (*b).clone()),
+                retained_difference: RetainedImage::from_color_image(
+                    "difference of the compared outputs",
+                    difference.clone(),
+                ),
+                a,
+                b,
+                difference,
+            })
+        }
+        _ => None,
+    };
+}
+
+/// Display the preview of the selected node in its own window
+pub fn show_window(state: &mut state::AppState, ctx: &egui::Context) {
+    let selected = &state.selected_node;
+    let view = &mut state.viewer;
+
+    egui::Window::new("🔍 Viewer")
+        .open(&mut state.d_viewer)
+        .default_size([480.0, 480.0])
+        .resizable(true)
+        .show(ctx, |ui| {
+            match (&selected.retained_image, &selected.color_image) {
+                (Some(retained_image), Some(color_image)) => {
+                    // Leave room for the toolbar and the readout
+                    let rows = 2.0 * (ui.spacing().interact_size.y + ui.spacing().item_spacing.y);
+                    let height = ui.available_height() - rows;
+
+                    show_image_viewer(
+                        ui,
+                        WINDOW_VIEWER_ID,
+                        view,
+                        retained_image,
+                        color_image,
+                        selected.slice,
+                        height,
+                    );
+                }
+                _ => show_not_selected(ui),
+            }
+        });
+}
+
+fn show_image_viewer(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    view: &mut ImageView,
+    image: &RetainedImage,
+    pixels: &ColorImage,
+    slice: bool,
+    height: f32,
+) {
+    let viewport = egui::vec2(ui.available_width(), height);
+    image_viewer::show_toolbar(ui, view, image.size_vec2(), viewport);
+
+    let hovered = image_viewer::show(ui, id_source, view, image, pixels, height);
+    image_viewer::show_readout(ui, pixels, hovered, slice);
+}
+
+fn show_image_histogram(
+    ui: &mut egui::Ui,
+    statistics: &[(Channel, Statistics)],
+    options: &mut HistogramOptions,
+) {
+    display::image_histogram::show(ui, statistics, options);
 }
 
-fn show_image_spectrum(ui: &mut egui::Ui, spectrum: &[HashMap<u8, usize>]) {
-    display::image_spectrum::show(ui, spectrum);
+fn show_image_statistics(ui: &mut egui::Ui, statistics: &[(Channel, Statistics)]) {
+    display::image_statistics::show(ui, statistics);
 }
 
-fn show_image_infos(ui: &mut egui::Ui, image: &ColorImage, retained: &RetainedImage) {
-    display::image_infos::show(ui, image, retained);
+fn show_image_infos(
+    ui: &mut egui::Ui,
+    image: &ColorImage,
+    retained: &RetainedImage,
+    selected: &SelectedNode,
+) {
+    display::image_infos::show(ui, image, retained, selected.format, selected.float);
 }
@@ -1,4 +1,6 @@
 use crate::app::components::graph::node::*;
+use crate::app::components::graph::registry;
+use crate::app::components::graph::utils::fuzzy_score;
 use crate::app::state;
 
 pub fn show(state: &mut state::AppState, ui: &mut egui::Ui) {
@@ -6,56 +8,71 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui) {
 
     egui::ScrollArea::vertical().show(ui, |ui| {
         ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
-            full_collapsing("🖼 Input", ui, |ui| {
-                ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
-                    if ui.button("🔃 Fetcher").clicked() {
-                        create_node(state, NodeTemplate::ImageFetcher, egui::pos2(0.0, 0.0));
-                    }
-                });
-            });
+            ui.add(egui::TextEdit::singleline(&mut state.node_search).hint_text("🔍 Search"));
 
-            full_collapsing("↔ Convert", ui, |ui| {
-                if ui.button("▓ Gray scales").clicked() {
-                    create_node(state, NodeTemplate::GrayScales, egui::pos2(0.0, 0.0));
+            // Searching lists the matching nodes instead of the categories
+            if !state.node_search.is_empty() {
+                for node in registry::search(&state.node_search) {
+                    node_button(state, ui, node);
                 }
-                if ui.button("✖ Image to RGB Slice").clicked() {
-                    create_node(state, NodeTemplate::ImageToSlice, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("➗ RGB Slice to Image").clicked() {
-                    create_node(state, NodeTemplate::SliceToImage, egui::pos2(0.0, 0.0));
+                for group in state.library.clone() {
+                    if fuzzy_score(&state.node_search, &group.name).is_some()
+                        && ui.button(format!("📦 {}", group.name)).clicked()
+                    {
+                        create_node(state, NodeTemplate::Group(group), egui::pos2(0.0, 0.0));
+                    }
                 }
-            });
+                return;
+            }
 
-            full_collapsing("＃ Process", ui, |ui| {
-                if ui.button("👓 Gaussian blur").clicked() {
-                    create_node(state, NodeTemplate::GaussianBlur, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("〰 Fourier space").clicked() {
-                    create_node(state, NodeTemplate::FourierSpace, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("🌕 Brighten image").clicked() {
-                    create_node(state, NodeTemplate::BrightenImage, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("🌗 Contrast image").clicked() {
-                    create_node(state, NodeTemplate::ContrastImage, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("🔅 Invert image").clicked() {
-                    create_node(state, NodeTemplate::InvertImage, egui::pos2(0.0, 0.0));
-                }
-                if ui.button("🌈 Hue rotate").clicked() {
-                    create_node(state, NodeTemplate::HueRotate, egui::pos2(0.0, 0.0));
+            for category in registry::Category::ALL {
+                full_collapsing(category.label(), ui, |ui| {
+                    for node in registry::NODES.iter().filter(|n| n.category == category) {
+                        node_button(state, ui, node);
+                    }
+                });
+            }
+
+            full_collapsing("📦 Library", ui, |ui| {
+                if state.library.is_empty() {
+                    ui.weak("Group nodes (Ctrl+G) and save them to reuse them here");
                 }
-                if ui.button("↪ Flip image").clicked() {
-                    create_node(state, NodeTemplate::FlipImage, egui::pos2(0.0, 0.0));
+
+                let mut removed = None;
+                for (index, group) in state.library.clone().iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("🗑")
+                            .on_hover_text("Remove from the library")
+                            .clicked()
+                        {
+                            removed = Some(index);
+                        }
+                        if ui.button(format!("📦 {}", group.name)).clicked() {
+                            let template = NodeTemplate::Group(group.clone());
+                            create_node(state, template, egui::pos2(0.0, 0.0));
+                        }
+                    });
                 }
-                if ui.button("⟳ Rotate image").clicked() {
-                    create_node(state, NodeTemplate::RotateImage, egui::pos2(0.0, 0.0));
+
+                if let Some(index) = removed {
+                    state.library.remove(index);
                 }
             });
         });
     });
 }
 
+fn node_button(state: &mut state::AppState, ui: &mut egui::Ui, node: &registry::NodeDescription) {
+    let button = ui
+        .button(format!("{} {}", node.icon, node.name))
+        .on_hover_text(node.description);
+
+    if button.clicked() {
+        create_node(state, node.template.clone(), egui::pos2(0.0, 0.0));
+    }
+}
+
 fn full_collapsing(label: &str, ui: &mut egui::Ui, add_content: impl FnOnce(&mut egui::Ui)) {
     ui.collapsing(label, |ui| {
         ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
@@ -1,6 +1,14 @@
+use crate::app::components::display::node_profiler;
+use crate::app::components::graph::batch;
+use crate::app::components::graph::clipboard;
+use crate::app::components::graph::group;
 use crate::app::components::graph::node::*;
+use crate::app::components::graph::registry;
+use crate::app::history;
 use crate::app::state;
 
+const MEGABYTE: usize = 1024 * 1024;
+
 pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context) {
     egui::menu::bar(ui, |ui| {
         egui::widgets::global_dark_light_mode_switch(ui);
@@ -14,6 +22,60 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
             if ui.button("❌ Quit").clicked() {}
         });
 
+        ui.menu_button("✏ Edit", |ui| {
+            let undo = egui::Button::new("↩ Undo (Ctrl+Z)");
+            if ui.add_enabled(state.history.can_undo(), undo).clicked() {
+                history::undo(state);
+                ui.close_menu();
+            }
+
+            let redo = egui::Button::new("↪ Redo (Ctrl+Shift+Z)");
+            if ui.add_enabled(state.history.can_redo(), redo).clicked() {
+                history::redo(state);
+                ui.close_menu();
+            }
+
+            ui.separator();
+
+            let has_selection = !clipboard::selected_nodes(state).is_empty();
+
+            let copy = egui::Button::new("📋 Copy (Ctrl+C)");
+            if ui.add_enabled(has_selection, copy).clicked() {
+                if let Some(text) = clipboard::copy(state) {
+                    ctx.output().copied_text = text;
+                }
+                ui.close_menu();
+            }
+
+            let paste = egui::Button::new("📄 Paste (Ctrl+V)");
+            if ui.add_enabled(!state.clipboard.is_empty(), paste).clicked() {
+                let subgraph = state.clipboard.clone();
+                let position = subgraph.position() + egui::vec2(30.0, 30.0);
+
+                clipboard::paste(state, &subgraph, position);
+                ui.close_menu();
+            }
+
+            let duplicate = egui::Button::new("🗐 Duplicate (Ctrl+D)");
+            if ui.add_enabled(has_selection, duplicate).clicked() {
+                clipboard::duplicate(state);
+                ui.close_menu();
+            }
+
+            if ui.button("☑ Select all (Ctrl+A)").clicked() {
+                clipboard::select_all(state);
+                ui.close_menu();
+            }
+
+            ui.separator();
+
+            let group = egui::Button::new("📦 Group (Ctrl+G)");
+            if ui.add_enabled(has_selection, group).clicked() {
+                group::open_dialog(state);
+                ui.close_menu();
+            }
+        });
+
         if ui.button("▶ Play").clicked() {
             evaluate_graph(&mut state.graph);
         }
@@ -21,11 +83,16 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
         ui.checkbox(&mut state.auto_compute, "Auto");
 
         ui.menu_button("📓 Help", |ui| {
+            if ui.button("📖 Nodes").clicked() {
+                state.d_nodes = !state.d_nodes;
+            }
             if ui.button("ℹ About").clicked() {
                 state.d_about = !state.d_about;
             }
         });
 
+        ui.checkbox(&mut state.d_inspector, "Inspector");
+        ui.checkbox(&mut state.d_profiler, "Profiler");
         ui.checkbox(&mut state.d_state, "Debug");
     });
 
@@ -33,10 +100,84 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
         .open(&mut state.d_settings)
         .vscroll(true)
         .show(ctx, |ui| {
+            // Images computed by the nodes past this budget are evicted and
+            // computed again when they are needed
+            let cache = &mut state.graph.user_state.outputs_cache;
+            let mut budget = cache.budget / MEGABYTE;
+
+            ui.horizontal(|ui| {
+                ui.label("Cache budget");
+                ui.add(
+                    egui::DragValue::new(&mut budget)
+                        .clamp_range(16..=65536)
+                        .suffix(" MB"),
+                );
+            });
+            cache.budget = budget * MEGABYTE;
+
+            ui.separator();
+
             ctx.settings_ui(ui);
             ui.allocate_space(ui.available_size());
         });
 
+    if let Some(mut dialog) = state.group_dialog.take() {
+        let mut open = true;
+        let mut create = false;
+
+        egui::Window::new("📦 Group nodes")
+            .open(&mut open)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut dialog.name);
+                });
+
+                ui.separator();
+                ui.label("Parameters shown in the group node:");
+                for parameter in dialog.parameters.iter_mut() {
+                    let checkbox = egui::Checkbox::new(&mut parameter.promoted, &parameter.label);
+                    ui.add_enabled(!parameter.required, checkbox)
+                        .on_disabled_hover_text("The images are fetched by the group node");
+                }
+
+                ui.separator();
+                ui.checkbox(&mut dialog.save, "Save to the library");
+
+                create = ui.button("📦 Group").clicked();
+            });
+
+        if create {
+            group::group_nodes(state, &dialog);
+        } else if open {
+            state.group_dialog = Some(dialog);
+        }
+    }
+
+    if state.batch.is_some() {
+        show_batch(state, ctx);
+    }
+
+    egui::Window::new("⏱ Profiler")
+        .open(&mut state.d_profiler)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            node_profiler::show(
+                ui,
+                &state.graph.graph,
+                &state.graph.user_state.profiler,
+                &mut state.o_profiler,
+            );
+        });
+
+    egui::Window::new("📖 Nodes")
+        .open(&mut state.d_nodes)
+        .vscroll(true)
+        .show(ctx, |ui| {
+            registry::show_documentation(ui);
+        });
+
     egui::Window::new("About")
         .open(&mut state.d_about)
         .vscroll(true)
@@ -45,3 +186,40 @@ pub fn show(state: &mut state::AppState, ui: &mut egui::Ui, ctx: &egui::Context)
             ui.allocate_space(ui.available_size());
         });
 }
+
+// Progress of the processing of a sequence, with the files that failed
+fn show_batch(state: &mut state::AppState, ctx: &egui::Context) {
+    let mut open = true;
+    let mut cancel = false;
+
+    if let Some(batch) = &state.batch {
+        egui::Window::new("🎞 Process all")
+            .open(&mut open)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let text = format!("{} / {} files", batch.frame, batch.count);
+                ui.add(egui::ProgressBar::new(batch.progress()).text(text));
+                ui.label(format!("{} images written", batch.written));
+
+                for error in batch.errors.iter() {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                if !batch.done {
+                    cancel = ui.button("⏹ Cancel").clicked();
+                }
+            });
+    }
+
+    if cancel {
+        batch::cancel(state);
+    }
+
+    // Closing the window cancels the batch, it is removed once done
+    if !open {
+        batch::cancel(state);
+        if state.batch.as_ref().map_or(false, |batch| batch.done) {
+            state.batch = None;
+        }
+    }
+}
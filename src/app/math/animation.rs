@@ -0,0 +1,435 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+use std::sync::Arc;
+
+use egui::epaint::{Color32, ColorImage};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::AnimationDecoder;
+
+use crate::app::math::image::{decode_image, format_name};
+
+/// Seconds a frame is displayed when the file does not tell, as browsers do
+pub const DEFAULT_DELAY: f32 = 0.1;
+
+/// Speed of the quantization of the exported GIF, from 1 (best) to 30 (fastest)
+const GIF_SPEED: i32 = 10;
+
+#[derive(Clone)]
+pub struct Frame {
+    pub image: Arc<ColorImage>,
+    /// Seconds before the next frame is displayed
+    pub delay: f32,
+}
+
+/// Frames decoded from a file, along with its format
+pub struct Decoded {
+    pub frames: Vec<Frame>,
+    pub format: &'static str,
+}
+
+pub fn load_animation_file(path: &Path) -> Result<Decoded, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+    load_animation_bytes(&bytes, &path.to_string_lossy())
+}
+
+/// Decode the frames of an animated GIF or PNG, or of a Y4M video. The other
+/// images are decoded as a single frame, `name` gives the format of the images
+/// that cannot be guessed from their content.
+pub fn load_animation_bytes(bytes: &[u8], name: &str) -> Result<Decoded, String> {
+    let error = |err: image::ImageError| format!("Failed to decode the animation: {}", err);
+
+    if bytes.starts_with(y4m::SIGNATURE) {
+        let frames = y4m::decode(bytes)?;
+        return Ok(Decoded {
+            frames,
+            format: "Y4M",
+        });
+    }
+
+    let format = image::guess_format(bytes);
+    let frames = match format {
+        Ok(image::ImageFormat::Gif) => GifDecoder::new(Cursor::new(bytes))
+            .map_err(error)?
+            .into_frames()
+            .collect_frames()
+            .map_err(error)?,
+        Ok(image::ImageFormat::Png) => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).map_err(error)?;
+
+            if !decoder.is_apng() {
+                return still_frame(bytes, name);
+            }
+
+            decoder
+                .apng()
+                .into_frames()
+                .collect_frames()
+                .map_err(error)?
+        }
+        _ => return still_frame(bytes, name),
+    };
+
+    if frames.is_empty() {
+        return Err("The animation has no frame".to_string());
+    }
+
+    Ok(Decoded {
+        frames: frames.into_iter().map(to_frame).collect(),
+        format: match format {
+            Ok(image::ImageFormat::Png) => "APNG",
+            Ok(format) => format_name(format),
+            Err(_) => "Unknown",
+        },
+    })
+}
+
+fn still_frame(bytes: &[u8], name: &str) -> Result<Decoded, String> {
+    let decoded = decode_image(bytes, name)?;
+
+    Ok(Decoded {
+        frames: vec![Frame {
+            image: decoded.image,
+            delay: DEFAULT_DELAY,
+        }],
+        format: decoded.format,
+    })
+}
+
+fn to_frame(frame: image::Frame) -> Frame {
+    let (numerator, denominator) = frame.delay().numer_denom_ms();
+    let delay = numerator as f32 / denominator.max(1) as f32 / 1000.0;
+
+    let buffer = frame.into_buffer();
+    let size = [buffer.width() as usize, buffer.height() as usize];
+
+    Frame {
+        image: Arc::new(ColorImage::from_rgba_unmultiplied(size, buffer.as_raw())),
+        // Browsers also display the frames without delay for a tenth of second
+        delay: if delay > 0.0 { delay } else { DEFAULT_DELAY },
+    }
+}
+
+/// Writes the frames of an animated GIF as they are processed
+pub struct GifWriter {
+    encoder: GifEncoder<BufWriter<File>>,
+}
+
+impl GifWriter {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self { encoder })
+    }
+
+    /// Adds a frame displayed for `delay` seconds
+    pub fn add(&mut self, image: &ColorImage, delay: f32) -> Result<(), String> {
+        let pixels = image
+            .pixels
+            .iter()
+            .flat_map(|color| color.to_srgba_unmultiplied())
+            .collect();
+        let buffer = image::RgbaImage::from_raw(image.size[0] as u32, image.size[1] as u32, pixels)
+            .ok_or("The size of the image does not match its pixels")?;
+
+        let milliseconds = (delay * 1000.0).round() as u32;
+        let delay = image::Delay::from_numer_denom_ms(milliseconds, 1);
+
+        self.encoder
+            .encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))
+            .map_err(|err| format!("Failed to write the frame: {}", err))
+    }
+}
+
+// Raw YUV video, as written by `ffmpeg -pix_fmt yuv420p out.y4m`. The frames
+// are stored one after the other, each made of a luma and two chroma planes.
+mod y4m {
+    use super::*;
+
+    pub const SIGNATURE: &[u8] = b"YUV4MPEG2 ";
+
+    /// Width and height of the largest video read
+    const MAX_SIDE: usize = 16384;
+
+    /// Memory the decoded frames can take, the whole video is kept in RGBA
+    const MAX_DECODED_SIZE: usize = 1024 * 1024 * 1024;
+
+    // Size of the chroma planes relative to the luma plane
+    #[derive(Clone, Copy)]
+    enum Subsampling {
+        S420,
+        S422,
+        S444,
+        Mono,
+    }
+
+    struct Header {
+        width: usize,
+        height: usize,
+        delay: f32,
+        subsampling: Subsampling,
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Vec<Frame>, String> {
+        let (header, mut rest) = split_line(bytes)?;
+        let header = parse_header(header)?;
+
+        let (chroma_width, chroma_height) = match header.subsampling {
+            Subsampling::S420 => ((header.width + 1) / 2, (header.height + 1) / 2),
+            Subsampling::S422 => ((header.width + 1) / 2, header.height),
+            Subsampling::S444 => (header.width, header.height),
+            Subsampling::Mono => (0, 0),
+        };
+        let too_large = || "The video is too large".to_string();
+        let luma_size = header
+            .width
+            .checked_mul(header.height)
+            .ok_or_else(too_large)?;
+        let frame_size = chroma_width
+            .checked_mul(chroma_height)
+            .and_then(|chroma_size| chroma_size.checked_mul(2))
+            .and_then(|chroma_size| chroma_size.checked_add(luma_size))
+            .ok_or_else(too_large)?;
+        let decoded_size = luma_size
+            .checked_mul(std::mem::size_of::<Color32>())
+            .ok_or_else(too_large)?;
+
+        let mut frames = vec![];
+
+        while !rest.is_empty() {
+            let (frame_header, data) = split_line(rest)?;
+            if !frame_header.starts_with(b"FRAME") {
+                return Err(format!("The frame {} is missing", frames.len() + 1));
+            }
+            if data.len() < frame_size {
+                return Err(format!("The frame {} is truncated", frames.len() + 1));
+            }
+            if (frames.len() + 1) * decoded_size > MAX_DECODED_SIZE {
+                return Err(format!(
+                    "The video takes more than {} MB once decoded",
+                    MAX_DECODED_SIZE / 1024 / 1024
+                ));
+            }
+
+            let (luma, chroma) = data[..frame_size].split_at(luma_size);
+            let (u, v) = chroma.split_at(chroma_width * chroma_height);
+
+            let mut image = ColorImage::new([header.width, header.height], Color32::BLACK);
+            for y in 0..header.height {
+                for x in 0..header.width {
+                    let (cx, cy) = match header.subsampling {
+                        Subsampling::S420 => (x / 2, y / 2),
+                        Subsampling::S422 => (x / 2, y),
+                        _ => (x, y),
+                    };
+
+                    let luma = luma[y * header.width + x];
+                    image.pixels[y * header.width + x] = match header.subsampling {
+                        Subsampling::Mono => yuv_to_rgb(luma, 128, 128),
+                        _ => {
+                            let index = cy * chroma_width + cx;
+                            yuv_to_rgb(luma, u[index], v[index])
+                        }
+                    };
+                }
+            }
+
+            frames.push(Frame {
+                image: Arc::new(image),
+                delay: header.delay,
+            });
+            rest = &data[frame_size..];
+        }
+
+        if frames.is_empty() {
+            return Err("The video has no frame".to_string());
+        }
+
+        Ok(frames)
+    }
+
+    fn split_line(bytes: &[u8]) -> Result<(&[u8], &[u8]), String> {
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or("The video is truncated")?;
+
+        Ok((&bytes[..end], &bytes[end + 1..]))
+    }
+
+    fn parse_header(line: &[u8]) -> Result<Header, String> {
+        let line = String::from_utf8_lossy(line);
+
+        let mut header = Header {
+            width: 0,
+            height: 0,
+            delay: DEFAULT_DELAY,
+            subsampling: Subsampling::S420,
+        };
+
+        for parameter in line.split(' ').skip(1) {
+            let tag = parameter.get(..1).unwrap_or("");
+            let value = parameter.get(1..).unwrap_or("");
+
+            match tag {
+                "W" => header.width = value.parse().map_err(|_| "Invalid width")?,
+                "H" => header.height = value.parse().map_err(|_| "Invalid height")?,
+                "F" => {
+                    let mut rate = value.split(':').map(|part| part.parse::<f32>());
+                    if let (Some(Ok(numerator)), Some(Ok(denominator))) = (rate.next(), rate.next())
+                    {
+                        if numerator > 0.0 {
+                            header.delay = denominator / numerator;
+                        }
+                    }
+                }
+                "C" => {
+                    header.subsampling = match value {
+                        // The high bit depths are written `420p10`, `420p12`...
+                        _ if value.starts_with("420") && !value.contains("p1") => Subsampling::S420,
+                        "422" => Subsampling::S422,
+                        "444" => Subsampling::S444,
+                        "mono" => Subsampling::Mono,
+                        _ => return Err(format!("The colorspace {} is not supported", value)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if header.width == 0 || header.height == 0 {
+            return Err("The video has no size".to_string());
+        }
+        if header.width > MAX_SIDE || header.height > MAX_SIDE {
+            return Err(format!("The video is larger than {} pixels", MAX_SIDE));
+        }
+
+        Ok(header)
+    }
+
+    // Studio range BT.601, the usual encoding of standard definition videos
+    fn yuv_to_rgb(y: u8, u: u8, v: u8) -> Color32 {
+        let y = 1.164 * (y as f32 - 16.0);
+        let u = u as f32 - 128.0;
+        let v = v as f32 - 128.0;
+
+        let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+
+        Color32::from_rgb(
+            channel(y + 1.596 * v),
+            channel(y - 0.391 * u - 0.813 * v),
+            channel(y + 2.018 * u),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A video of the given frames, each made of its planes one after the other
+    fn y4m(header: &str, frames: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = format!("YUV4MPEG2 {}\n", header).into_bytes();
+        for frame in frames {
+            bytes.extend_from_slice(b"FRAME\n");
+            bytes.extend_from_slice(frame);
+        }
+        bytes
+    }
+
+    fn frame_pixels(decoded: &Decoded, frame: usize) -> Vec<Color32> {
+        decoded.frames[frame].image.pixels.clone()
+    }
+
+    #[test]
+    fn y4m_reads_the_header() {
+        let bytes = y4m("W2 H1 F25:1 C444", &[&[16, 235, 128, 128, 128, 128]]);
+        let decoded = load_animation_bytes(&bytes, "video.y4m").unwrap();
+
+        assert_eq!(decoded.format, "Y4M");
+        assert_eq!(decoded.frames[0].image.size, [2, 1]);
+        assert!((decoded.frames[0].delay - 0.04).abs() < 1e-6);
+    }
+
+    #[test]
+    fn y4m_rejects_invalid_headers() {
+        let frame: &[u8] = &[0; 6];
+
+        assert!(load_animation_bytes(&y4m("H1 C444", &[frame]), "").is_err());
+        assert!(load_animation_bytes(&y4m("W2 H1 C420p10", &[frame]), "").is_err());
+        assert!(load_animation_bytes(&y4m("W100000 H100000", &[frame]), "").is_err());
+        assert!(load_animation_bytes(&y4m("W2 H1 C444", &[]), "").is_err());
+        assert!(load_animation_bytes(&y4m("W2 H1 C444", &[&[0; 5]]), "").is_err());
+    }
+
+    #[test]
+    fn y4m_converts_studio_range_to_rgb() {
+        let bytes = y4m("W3 H1 C444", &[&[16, 235, 81, 128, 128, 90, 128, 128, 240]]);
+        let decoded = load_animation_bytes(&bytes, "").unwrap();
+        let pixels = frame_pixels(&decoded, 0);
+
+        assert_eq!(pixels[0], Color32::BLACK);
+        assert_eq!(pixels[1], Color32::WHITE);
+
+        // Pure red in BT.601
+        let [r, g, b, _] = pixels[2].to_array();
+        assert!(r > 250 && g < 5 && b < 5, "{:?}", pixels[2]);
+    }
+
+    #[test]
+    fn y4m_shares_the_chroma_between_pixels() {
+        // A 2x2 frame with a single chroma sample, tinting every pixel
+        let bytes = y4m("W2 H2 C420jpeg", &[&[128, 128, 128, 128, 200, 128]]);
+        let pixels = frame_pixels(&load_animation_bytes(&bytes, "").unwrap(), 0);
+        assert!(pixels.iter().all(|pixel| *pixel == pixels[0]));
+        assert!(pixels[0].b() > pixels[0].r());
+
+        // Two chroma samples per row in 4:2:2, the columns differ
+        let bytes = y4m("W4 H1 C422", &[&[128, 128, 128, 128, 200, 60, 128, 128]]);
+        let pixels = frame_pixels(&load_animation_bytes(&bytes, "").unwrap(), 0);
+        assert_eq!(pixels[0], pixels[1]);
+        assert_eq!(pixels[2], pixels[3]);
+        assert_ne!(pixels[0], pixels[2]);
+
+        // Gray videos have no chroma planes
+        let bytes = y4m("W2 H1 Cmono", &[&[128, 128], &[235, 235]]);
+        let decoded = load_animation_bytes(&bytes, "").unwrap();
+        assert_eq!(decoded.frames.len(), 2);
+        let [r, g, b, _] = frame_pixels(&decoded, 0)[0].to_array();
+        assert!(r == g && g == b);
+        assert_eq!(frame_pixels(&decoded, 1)[0], Color32::WHITE);
+    }
+
+    #[test]
+    fn gif_writer_round_trip() {
+        let path = std::env::temp_dir().join(format!("carbaseus-{}.gif", std::process::id()));
+
+        let red = ColorImage::new([3, 2], Color32::RED);
+        let blue = ColorImage::new([3, 2], Color32::BLUE);
+
+        let mut writer = GifWriter::create(&path).unwrap();
+        writer.add(&red, 0.2).unwrap();
+        writer.add(&blue, 0.5).unwrap();
+        drop(writer);
+
+        let decoded = load_animation_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(decoded.format, "GIF");
+        assert_eq!(decoded.frames.len(), 2);
+        assert_eq!(decoded.frames[0].image.size, [3, 2]);
+        assert!((decoded.frames[0].delay - 0.2).abs() < 1e-3);
+        assert!((decoded.frames[1].delay - 0.5).abs() < 1e-3);
+        assert_eq!(decoded.frames[0].image.pixels[0], Color32::RED);
+        assert_eq!(decoded.frames[1].image.pixels[0], Color32::BLUE);
+    }
+}
@@ -0,0 +1,290 @@
+use egui::epaint::{Color32, ColorImage};
+
+use super::image::ImageSlice;
+use super::transform::{border_index, resize_image, BorderMode, Interpolation};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    Subtract,
+    Difference,
+    Darken,
+    Lighten,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Normal
+    }
+}
+
+/// How an image of a different size is placed over the base image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    /// Resize the image to the base image size
+    Stretch,
+    /// Keep the image size and place it in the top left corner
+    TopLeft,
+    /// Keep the image size and place it at the center
+    Center,
+    /// Keep the image size and repeat it over the whole base image
+    Tile,
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Alignment::Stretch
+    }
+}
+
+// Blend the layer over the base image. The result keeps the size of the base
+// image, and the optional mask scales the opacity of the layer per pixel
+pub fn blend_images(
+    base: &ColorImage,
+    layer: &ColorImage,
+    mask: Option<&ImageSlice>,
+    opacity: f32,
+    mode: BlendMode,
+    alignment: Alignment,
+) -> ColorImage {
+    let opacity = opacity.clamp(0.0, 1.0);
+
+    let layer = align_image(layer, base.size, alignment);
+    let mask = mask.map(|mask| align_image(&slice_to_gray(mask), base.size, alignment));
+
+    let mut output = ColorImage::new(base.size, Color32::BLACK);
+
+    for (i, pixel) in output.pixels.iter_mut().enumerate() {
+        let [br, bg, bb, ba] = base.pixels[i].to_srgba_unmultiplied();
+        let [lr, lg, lb, la] = layer.pixels[i].to_srgba_unmultiplied();
+
+        let coverage = match &mask {
+            Some(mask) => mask.pixels[i].r() as f32 / 255.0,
+            None => 1.0,
+        };
+
+        let alpha = opacity * coverage * la as f32 / 255.0;
+
+        let channel = |b: u8, l: u8| {
+            let b = b as f32 / 255.0;
+            let l = l as f32 / 255.0;
+
+            let blended = blend_channel(b, l, mode);
+
+            ((b + (blended - b) * alpha) * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+
+        *pixel =
+            Color32::from_rgba_unmultiplied(channel(br, lr), channel(bg, lg), channel(bb, lb), ba);
+    }
+
+    output
+}
+
+// Blend two normalized channel values
+fn blend_channel(base: f32, layer: f32, mode: BlendMode) -> f32 {
+    match mode {
+        BlendMode::Normal => layer,
+        BlendMode::Multiply => base * layer,
+        BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - layer),
+        BlendMode::Overlay => {
+            if base < 0.5 {
+                2.0 * base * layer
+            } else {
+                1.0 - 2.0 * (1.0 - base) * (1.0 - layer)
+            }
+        }
+        BlendMode::Add => (base + layer).min(1.0),
+        BlendMode::Subtract => (base - layer).max(0.0),
+        BlendMode::Difference => (base - layer).abs(),
+        BlendMode::Darken => base.min(layer),
+        BlendMode::Lighten => base.max(layer),
+    }
+}
+
+// Bring the image to the given size, uncovered pixels are transparent
+pub fn align_image(image: &ColorImage, size: [usize; 2], alignment: Alignment) -> ColorImage {
+    if image.size == size {
+        return image.clone();
+    }
+
+    let [width, height] = image.size;
+
+    let (offset, border) = match alignment {
+        Alignment::Stretch => {
+            return resize_image(image, size[0], size[1], Interpolation::Bilinear);
+        }
+        Alignment::TopLeft => ([0, 0], BorderMode::Constant),
+        Alignment::Center => (
+            [
+                (size[0] as isize - width as isize) / 2,
+                (size[1] as isize - height as isize) / 2,
+            ],
+            BorderMode::Constant,
+        ),
+        Alignment::Tile => ([0, 0], BorderMode::Wrap),
+    };
+
+    let mut output = ColorImage::new(size, Color32::TRANSPARENT);
+
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            let src_x = border_index(x as isize - offset[0], width, border);
+            let src_y = border_index(y as isize - offset[1], height, border);
+
+            if let (Some(src_x), Some(src_y)) = (src_x, src_y) {
+                output.pixels[y * size[0] + x] = image.pixels[src_y * width + src_x];
+            }
+        }
+    }
+
+    output
+}
+
+// Convert a slice to a gray image, whatever its color
+fn slice_to_gray(slice: &ImageSlice) -> ColorImage {
+    ColorImage {
+        size: slice.size,
+        pixels: slice
+            .pixels
+            .iter()
+            .map(|&px| Color32::from_gray(px))
+            .collect(),
+    }
+}
+
+// Absolute difference of the channels of two images, multiplied by the gain so
+// that small differences can be seen. The images are compared from their top
+// left corner, over the size of the first one
+pub fn difference_image(a: &ColorImage, b: &ColorImage, gain: f32) -> ColorImage {
+    let b = align_image(b, a.size, Alignment::TopLeft);
+
+    let mut output = ColorImage::new(a.size, Color32::BLACK);
+
+    for (i, pixel) in output.pixels.iter_mut().enumerate() {
+        let [ar, ag, ab, _] = a.pixels[i].to_array();
+        let [br, bg, bb, _] = b.pixels[i].to_array();
+
+        let channel = |a: u8, b: u8| {
+            ((a as f32 - b as f32).abs() * gain)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+
+        *pixel = Color32::from_rgb(channel(ar, br), channel(ag, bg), channel(ab, bb));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::math::image::SliceColor;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn uniform(color: Color32) -> ColorImage {
+        ColorImage::new([2, 2], color)
+    }
+
+    #[test]
+    fn blend_modes_follow_their_formulas() {
+        let (base, layer) = (0.25, 0.5);
+
+        assert_close(blend_channel(base, layer, BlendMode::Normal), 0.5);
+        assert_close(blend_channel(base, layer, BlendMode::Multiply), 0.125);
+        assert_close(blend_channel(base, layer, BlendMode::Screen), 0.625);
+        assert_close(blend_channel(base, layer, BlendMode::Overlay), 0.25);
+        assert_close(blend_channel(0.75, layer, BlendMode::Overlay), 0.75);
+        assert_close(blend_channel(base, layer, BlendMode::Add), 0.75);
+        assert_close(blend_channel(base, layer, BlendMode::Subtract), 0.0);
+        assert_close(blend_channel(layer, base, BlendMode::Subtract), 0.25);
+        assert_close(blend_channel(base, layer, BlendMode::Difference), 0.25);
+        assert_close(blend_channel(base, layer, BlendMode::Darken), 0.25);
+        assert_close(blend_channel(base, layer, BlendMode::Lighten), 0.5);
+    }
+
+    #[test]
+    fn add_and_subtract_stay_in_range() {
+        assert_close(blend_channel(0.75, 0.5, BlendMode::Add), 1.0);
+        assert_close(blend_channel(0.25, 0.75, BlendMode::Subtract), 0.0);
+    }
+
+    #[test]
+    fn opacity_mixes_the_layer_with_the_base() {
+        let base = uniform(Color32::from_rgb(0, 100, 200));
+        let layer = uniform(Color32::from_rgb(200, 100, 0));
+
+        let half = blend_images(
+            &base,
+            &layer,
+            None,
+            0.5,
+            BlendMode::Normal,
+            Alignment::Stretch,
+        );
+        let none = blend_images(
+            &base,
+            &layer,
+            None,
+            0.0,
+            BlendMode::Normal,
+            Alignment::Stretch,
+        );
+
+        assert_eq!(half.pixels[0], Color32::from_rgb(100, 100, 100));
+        assert_eq!(none.pixels, base.pixels);
+    }
+
+    #[test]
+    fn mask_scales_the_opacity_per_pixel() {
+        let base = uniform(Color32::BLACK);
+        let layer = uniform(Color32::WHITE);
+        let mask = ImageSlice {
+            color: SliceColor::Gray,
+            size: [2, 2],
+            pixels: vec![0, 255, 0, 255],
+        };
+
+        let output = blend_images(
+            &base,
+            &layer,
+            Some(&mask),
+            1.0,
+            BlendMode::Normal,
+            Alignment::Stretch,
+        );
+
+        assert_eq!(output.pixels[0], Color32::BLACK);
+        assert_eq!(output.pixels[1], Color32::WHITE);
+    }
+
+    #[test]
+    fn smaller_layers_are_placed_by_the_alignment() {
+        let layer = ColorImage::new([1, 1], Color32::RED);
+
+        let top_left = align_image(&layer, [3, 3], Alignment::TopLeft);
+        let center = align_image(&layer, [3, 3], Alignment::Center);
+        let tile = align_image(&layer, [3, 3], Alignment::Tile);
+
+        assert_eq!(top_left.pixels[0], Color32::RED);
+        assert_eq!(top_left.pixels[4], Color32::TRANSPARENT);
+        assert_eq!(center.pixels[0], Color32::TRANSPARENT);
+        assert_eq!(center.pixels[4], Color32::RED);
+        assert!(tile.pixels.iter().all(|&pixel| pixel == Color32::RED));
+    }
+}
@@ -0,0 +1,500 @@
+use egui::epaint::{Color32, ColorImage};
+
+use super::image::ImageSlice;
+
+/// Edge preserving noise reduction filters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Denoise {
+    /// Replace each pixel by the median of its neighbourhood
+    Median { radius: usize },
+    /// Average the neighbourhood, weighted by the spatial distance and the
+    /// intensity difference to the center pixel
+    Bilateral {
+        sigma_spatial: f32,
+        sigma_range: f32,
+    },
+    /// Average the pixels of the search window whose surrounding patch looks
+    /// like the patch of the center pixel
+    NonLocalMeans {
+        strength: f32,
+        patch_radius: usize,
+        search_radius: usize,
+    },
+}
+
+pub fn denoise_image(image: &ColorImage, filter: Denoise) -> ColorImage {
+    let channels: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|px| [px.r(), px.g(), px.b()])
+        .collect();
+
+    let filtered = denoise(&channels, image.size, 3, filter);
+
+    // The alpha channel is kept untouched
+    ColorImage {
+        size: image.size,
+        pixels: image
+            .pixels
+            .iter()
+            .zip(filtered.chunks_exact(3))
+            .map(|(px, rgb)| Color32::from_rgba_premultiplied(rgb[0], rgb[1], rgb[2], px.a()))
+            .collect(),
+    }
+}
+
+pub fn denoise_slice(slice: &ImageSlice, filter: Denoise) -> ImageSlice {
+    ImageSlice {
+        color: slice.color.clone(),
+        size: slice.size,
+        pixels: denoise(&slice.pixels, slice.size, 1, filter),
+    }
+}
+
+// Filter pixels stored with interleaved channels
+fn denoise(pixels: &[u8], size: [usize; 2], channels: usize, filter: Denoise) -> Vec<u8> {
+    if size[0] == 0 || size[1] == 0 {
+        return pixels.to_vec();
+    }
+
+    match filter {
+        Denoise::Median { radius } => {
+            let mut output = vec![0; pixels.len()];
+
+            // The median is computed on each channel independently
+            for channel in 0..channels {
+                let plane: Vec<u8> = pixels
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .copied()
+                    .collect();
+                let filtered = median_plane(&plane, size, radius);
+
+                for (px, value) in output
+                    .iter_mut()
+                    .skip(channel)
+                    .step_by(channels)
+                    .zip(filtered)
+                {
+                    *px = value;
+                }
+            }
+
+            output
+        }
+        Denoise::Bilateral {
+            sigma_spatial,
+            sigma_range,
+        } => bilateral(pixels, size, channels, sigma_spatial, sigma_range),
+        Denoise::NonLocalMeans {
+            strength,
+            patch_radius,
+            search_radius,
+        } => non_local_means(
+            pixels,
+            size,
+            channels,
+            strength,
+            patch_radius,
+            search_radius,
+        ),
+    }
+}
+
+// Index of the neighbour of a pixel, replicating the pixels of the edges
+fn clamp_index(index: isize, size: usize) -> usize {
+    index.clamp(0, size as isize - 1) as usize
+}
+
+// Constant time median filter (Perreault & Hébert): a histogram is kept for
+// each column and updated once per row, the kernel histogram is then slid
+// along the row by adding and removing whole column histograms, so the cost
+// per pixel does not depend on the radius
+fn median_plane(plane: &[u8], [width, height]: [usize; 2], radius: usize) -> Vec<u8> {
+    if radius == 0 {
+        return plane.to_vec();
+    }
+
+    let r = radius as isize;
+    let half = ((2 * radius + 1) * (2 * radius + 1) / 2) as u32;
+    let pixel = |x: usize, y: isize| plane[clamp_index(y, height) * width + x] as usize;
+
+    let mut columns = vec![[0u32; 256]; width];
+    for (x, column) in columns.iter_mut().enumerate() {
+        for y in -r..=r {
+            column[pixel(x, y)] += 1;
+        }
+    }
+
+    let mut output = vec![0; plane.len()];
+
+    for y in 0..height as isize {
+        if y > 0 {
+            for (x, column) in columns.iter_mut().enumerate() {
+                column[pixel(x, y - r - 1)] -= 1;
+                column[pixel(x, y + r)] += 1;
+            }
+        }
+
+        let mut kernel = [0u32; 256];
+        for x in -r..=r {
+            let column = &columns[clamp_index(x, width)];
+            kernel.iter_mut().zip(column).for_each(|(k, c)| *k += c);
+        }
+
+        for x in 0..width as isize {
+            if x > 0 {
+                let removed = &columns[clamp_index(x - r - 1, width)];
+                let added = &columns[clamp_index(x + r, width)];
+
+                for (k, (a, r)) in kernel.iter_mut().zip(added.iter().zip(removed)) {
+                    *k = *k + a - r;
+                }
+            }
+
+            let mut count = 0;
+            let median = kernel
+                .iter()
+                .position(|&bin| {
+                    count += bin;
+                    count > half
+                })
+                .unwrap_or(255);
+
+            output[y as usize * width + x as usize] = median as u8;
+        }
+    }
+
+    output
+}
+
+fn bilateral(
+    pixels: &[u8],
+    [width, height]: [usize; 2],
+    channels: usize,
+    sigma_spatial: f32,
+    sigma_range: f32,
+) -> Vec<u8> {
+    if sigma_spatial <= 0.0 || sigma_range <= 0.0 {
+        return pixels.to_vec();
+    }
+
+    let radius = (2.0 * sigma_spatial).ceil() as isize;
+
+    // Spatial weights of the window, row by row
+    let spatial: Vec<f32> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx * dx + dy * dy) as f32))
+        .map(|d2| (-d2 / (2.0 * sigma_spatial * sigma_spatial)).exp())
+        .collect();
+
+    // Range weights for every mean squared difference between two pixels
+    let range: Vec<f32> = (0..=255 * 255)
+        .map(|d2| (-(d2 as f32) / (2.0 * sigma_range * sigma_range)).exp())
+        .collect();
+
+    let mut output = vec![0; pixels.len()];
+    let mut sum = vec![0.0; channels];
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let center = &pixels[(y as usize * width + x as usize) * channels..][..channels];
+
+            sum.iter_mut().for_each(|s| *s = 0.0);
+            let mut total = 0.0;
+
+            let window =
+                (-radius..=radius).flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)));
+
+            for ((dx, dy), spatial) in window.zip(&spatial) {
+                let index = clamp_index(y + dy, height) * width + clamp_index(x + dx, width);
+                let neighbour = &pixels[index * channels..][..channels];
+
+                let d2: usize = center
+                    .iter()
+                    .zip(neighbour)
+                    .map(|(&a, &b)| (a as isize - b as isize).pow(2) as usize)
+                    .sum();
+
+                let weight = spatial * range[d2 / channels];
+
+                sum.iter_mut()
+                    .zip(neighbour)
+                    .for_each(|(s, &v)| *s += weight * v as f32);
+                total += weight;
+            }
+
+            let index = (y as usize * width + x as usize) * channels;
+            for (px, s) in output[index..index + channels].iter_mut().zip(&sum) {
+                *px = (s / total).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    output
+}
+
+// Non-local means, computed one search offset at a time: the squared
+// differences between the image and its shifted copy are summed in an
+// integral image, which gives the distance between any two patches in
+// constant time
+fn non_local_means(
+    pixels: &[u8],
+    [width, height]: [usize; 2],
+    channels: usize,
+    strength: f32,
+    patch_radius: usize,
+    search_radius: usize,
+) -> Vec<u8> {
+    if strength <= 0.0 || search_radius == 0 {
+        return pixels.to_vec();
+    }
+
+    let s = search_radius as isize;
+    let p = patch_radius as isize;
+    let h2 = strength * strength;
+
+    let mut sums = vec![0.0f32; pixels.len()];
+    let mut totals = vec![0.0f32; width * height];
+
+    // Integral image of the squared differences, with a leading zero row and column
+    let stride = width + 1;
+    let mut integral = vec![0u64; stride * (height + 1)];
+
+    for dy in -s..=s {
+        for dx in -s..=s {
+            let shifted = |x: usize, y: usize| {
+                clamp_index(y as isize + dy, height) * width + clamp_index(x as isize + dx, width)
+            };
+
+            for y in 0..height {
+                let mut row = 0;
+                for x in 0..width {
+                    let a = &pixels[(y * width + x) * channels..][..channels];
+                    let b = &pixels[shifted(x, y) * channels..][..channels];
+
+                    row += a
+                        .iter()
+                        .zip(b)
+                        .map(|(&a, &b)| (a as i64 - b as i64).pow(2) as u64)
+                        .sum::<u64>();
+
+                    integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row;
+                }
+            }
+
+            for y in 0..height {
+                let y0 = clamp_index(y as isize - p, height);
+                let y1 = clamp_index(y as isize + p, height) + 1;
+
+                for x in 0..width {
+                    let x0 = clamp_index(x as isize - p, width);
+                    let x1 = clamp_index(x as isize + p, width) + 1;
+
+                    let distance = integral[y1 * stride + x1] + integral[y0 * stride + x0]
+                        - integral[y0 * stride + x1]
+                        - integral[y1 * stride + x0];
+                    let area = ((y1 - y0) * (x1 - x0) * channels) as f32;
+
+                    let weight = (-(distance as f32 / area) / h2).exp();
+
+                    let neighbour = &pixels[shifted(x, y) * channels..][..channels];
+                    let index = y * width + x;
+
+                    sums[index * channels..][..channels]
+                        .iter_mut()
+                        .zip(neighbour)
+                        .for_each(|(s, &v)| *s += weight * v as f32);
+                    totals[index] += weight;
+                }
+            }
+        }
+    }
+
+    sums.iter()
+        .enumerate()
+        .map(|(i, s)| (s / totals[i / channels]).round().clamp(0.0, 255.0) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic noise, so that the tests do not depend on a random crate
+    fn noise(count: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    // Left half dark and right half bright
+    fn edge([width, height]: [usize; 2]) -> Vec<u8> {
+        (0..width * height)
+            .map(|i| if i % width < width / 2 { 20 } else { 230 })
+            .collect()
+    }
+
+    fn slice(size: [usize; 2], pixels: Vec<u8>) -> ImageSlice {
+        ImageSlice {
+            size,
+            pixels,
+            ..Default::default()
+        }
+    }
+
+    fn left_half(pixels: &[u8], [width, _]: [usize; 2]) -> Vec<u8> {
+        pixels
+            .chunks(width)
+            .flat_map(|row| row[..width / 2].to_vec())
+            .collect()
+    }
+
+    fn variance(pixels: &[u8]) -> f32 {
+        let mean = pixels.iter().map(|&v| v as f32).sum::<f32>() / pixels.len() as f32;
+        pixels
+            .iter()
+            .map(|&v| (v as f32 - mean).powi(2))
+            .sum::<f32>()
+            / pixels.len() as f32
+    }
+
+    // Sort every neighbourhood, to check the histogram based median against
+    fn naive_median(plane: &[u8], [width, height]: [usize; 2], radius: usize) -> Vec<u8> {
+        let r = radius as isize;
+
+        (0..height as isize)
+            .flat_map(|y| (0..width as isize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut window: Vec<u8> = (-r..=r)
+                    .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| {
+                        plane[clamp_index(y + dy, height) * width + clamp_index(x + dx, width)]
+                    })
+                    .collect();
+                window.sort_unstable();
+                window[window.len() / 2]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn median_matches_the_sorted_neighbourhood() {
+        let size = [7, 5];
+        let plane = noise(35, 1);
+
+        for radius in 1..=3 {
+            assert_eq!(
+                median_plane(&plane, size, radius),
+                naive_median(&plane, size, radius)
+            );
+        }
+    }
+
+    #[test]
+    fn median_removes_isolated_pixels() {
+        let mut pixels = vec![100; 25];
+        pixels[12] = 255;
+
+        let output = denoise_slice(&slice([5, 5], pixels), Denoise::Median { radius: 1 });
+
+        assert!(output.pixels.iter().all(|&v| v == 100));
+    }
+
+    #[test]
+    fn bilateral_smooths_the_noise_but_keeps_the_edges() {
+        let size = [8, 8];
+        let pixels: Vec<u8> = edge(size)
+            .iter()
+            .zip(noise(64, 2))
+            .map(|(&v, n)| v + n / 16)
+            .collect();
+
+        let output = denoise_slice(
+            &slice(size, pixels.clone()),
+            Denoise::Bilateral {
+                sigma_spatial: 2.0,
+                sigma_range: 30.0,
+            },
+        );
+
+        assert!(variance(&left_half(&output.pixels, size)) < variance(&left_half(&pixels, size)));
+        for row in output.pixels.chunks(8) {
+            assert!(row[3] < 50 && row[4] > 200);
+        }
+    }
+
+    #[test]
+    fn non_local_means_smooths_the_noise_but_keeps_the_edges() {
+        let size = [10, 10];
+        let pixels: Vec<u8> = edge(size)
+            .iter()
+            .zip(noise(100, 3))
+            .map(|(&v, n)| v + n / 16)
+            .collect();
+
+        let output = denoise_slice(
+            &slice(size, pixels.clone()),
+            Denoise::NonLocalMeans {
+                strength: 10.0,
+                patch_radius: 1,
+                search_radius: 3,
+            },
+        );
+
+        assert!(variance(&left_half(&output.pixels, size)) < variance(&left_half(&pixels, size)));
+        for row in output.pixels.chunks(10) {
+            assert!(row[4] < 50 && row[5] > 200);
+        }
+    }
+
+    #[test]
+    fn uniform_images_are_unchanged() {
+        let image = ColorImage::new([6, 4], Color32::from_rgba_premultiplied(10, 80, 150, 200));
+        let filters = [
+            Denoise::Median { radius: 2 },
+            Denoise::Bilateral {
+                sigma_spatial: 1.5,
+                sigma_range: 20.0,
+            },
+            Denoise::NonLocalMeans {
+                strength: 10.0,
+                patch_radius: 1,
+                search_radius: 2,
+            },
+        ];
+
+        for filter in filters {
+            assert_eq!(denoise_image(&image, filter).pixels, image.pixels);
+        }
+    }
+
+    #[test]
+    fn empty_parameters_keep_the_pixels() {
+        let size = [4, 4];
+        let pixels = noise(16, 4);
+        let filters = [
+            Denoise::Median { radius: 0 },
+            Denoise::Bilateral {
+                sigma_spatial: 0.0,
+                sigma_range: 20.0,
+            },
+            Denoise::NonLocalMeans {
+                strength: 10.0,
+                patch_radius: 1,
+                search_radius: 0,
+            },
+        ];
+
+        for filter in filters {
+            assert_eq!(
+                denoise_slice(&slice(size, pixels.clone()), filter).pixels,
+                pixels
+            );
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use std::fmt;
+use std::sync::Arc;
+
+use egui::epaint::{Color32, ColorImage};
+
+use super::image::{ImageSlice, SliceColor};
+
+/// Variables that are always available, after the user defined ones
+pub const PIXEL_VARIABLES: [&str; 4] = ["x", "y", "w", "h"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f32),
+    Variable(usize),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Min,
+    Max,
+    Abs,
+    Pow,
+    Sqrt,
+    Clamp,
+    Floor,
+    Ceil,
+    Round,
+    Sin,
+    Cos,
+    Exp,
+    Log,
+    If,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "abs" => Some(Function::Abs),
+            "pow" => Some(Function::Pow),
+            "sqrt" => Some(Function::Sqrt),
+            "clamp" => Some(Function::Clamp),
+            "floor" => Some(Function::Floor),
+            "ceil" => Some(Function::Ceil),
+            "round" => Some(Function::Round),
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "exp" => Some(Function::Exp),
+            "log" => Some(Function::Log),
+            "if" => Some(Function::If),
+            _ => None,
+        }
+    }
+
+    /// Minimum and maximum number of arguments
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Function::Min | Function::Max => (2, usize::MAX),
+            Function::Pow => (2, 2),
+            Function::Clamp | Function::If => (3, 3),
+            _ => (1, 1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Character offset of the error in the source
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Identifier(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "'{}'", value),
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+// Longest symbols first so that "<=" is not read as "<" followed by "="
+const SYMBOLS: [&str; 21] = [
+    "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "^", "<", ">", "!", "(", ")", ",",
+    "?", ":", "=",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse::<f32>().map_err(|_| ParseError {
+                position: start,
+                message: format!("Invalid number '{}'", text),
+            })?;
+
+            tokens.push((Token::Number(value), start));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), start));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| ParseError {
+                    position: i,
+                    message: format!("Unexpected character '{}'", c),
+                })?;
+
+            tokens.push((Token::Symbol(symbol), i));
+            i += symbol.len();
+        }
+    }
+
+    tokens.push((Token::End, chars.len()));
+
+    Ok(tokens)
+}
+
+/// Parse the source of an expression. Identifiers are resolved against the
+/// given variables followed by the pixel variables, in that order.
+pub fn parse(source: &str, variables: &[&str]) -> Result<Expr, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        index: 0,
+        variables,
+    };
+
+    let expr = parser.expression()?;
+
+    match parser.peek() {
+        Token::End => Ok(expr),
+        token => Err(parser.error(format!("Unexpected {}", token))),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    variables: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if token != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            position: self.tokens[self.index].1,
+            message,
+        }
+    }
+
+    fn accept(&mut self, symbol: &'static str) -> bool {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ParseError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{}' but found {}", symbol, self.peek())))
+        }
+    }
+
+    // expression := logical ( "?" expression ":" expression )?
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.binary(0)?;
+
+        if self.accept("?") {
+            let then = self.expression()?;
+            self.expect(":")?;
+            let otherwise = self.expression()?;
+
+            Ok(Expr::Conditional(
+                Box::new(condition),
+                Box::new(then),
+                Box::new(otherwise),
+            ))
+        } else {
+            Ok(condition)
+        }
+    }
+
+    // Binary operators by increasing precedence, all left associative
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("<=", BinaryOp::LessEqual),
+                (">=", BinaryOp::GreaterEqual),
+                ("==", BinaryOp::Equal),
+                ("!=", BinaryOp::NotEqual),
+                ("<", BinaryOp::Less),
+                (">", BinaryOp::Greater),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+
+        'operators: loop {
+            for (symbol, op) in LEVELS[level] {
+                if self.accept(symbol) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+
+            return Ok(lhs);
+        }
+    }
+
+    // unary := ( "-" | "+" | "!" ) unary | power
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.accept("-") {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else if self.accept("+") {
+            self.unary()
+        } else if self.accept("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    // power := primary ( "^" unary )?, which makes the power right associative
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.primary()?;
+
+        if self.accept("^") {
+            let exponent = self.unary()?;
+            Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // primary := number | variable | function "(" arguments ")" | "(" expression ")"
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let position = self.tokens[self.index].1;
+
+        match self.next() {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Symbol("(") => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Identifier(name) => {
+                if let Some(function) = Function::from_name(&name) {
+                    self.expect("(")?;
+                    let arguments = self.arguments()?;
+
+                    let (min, max) = function.arity();
+                    if arguments.len() < min || arguments.len() > max {
+                        return Err(ParseError {
+                            position,
+                            message: format!(
+                                "'{}' does not take {} argument(s)",
+                                name,
+                                arguments.len()
+                            ),
+                        });
+                    }
+
+                    Ok(Expr::Call(function, arguments))
+                } else if name == "pi" {
+                    Ok(Expr::Number(std::f32::consts::PI))
+                } else if let Some(index) = self
+                    .variables
+                    .iter()
+                    .chain(PIXEL_VARIABLES.iter())
+                    .position(|variable| *variable == name)
+                {
+                    Ok(Expr::Variable(index))
+                } else {
+                    Err(ParseError {
+                        position,
+                        message: format!("Unknown variable '{}'", name),
+                    })
+                }
+            }
+            token => Err(ParseError {
+                position,
+                message: format!("Unexpected {}", token),
+            }),
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut arguments = vec![];
+
+        if self.accept(")") {
+            return Ok(arguments);
+        }
+
+        loop {
+            arguments.push(self.expression()?);
+
+            if self.accept(")") {
+                return Ok(arguments);
+            }
+
+            self.expect(",")?;
+        }
+    }
+}
+
+fn truth(value: bool) -> f32 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression, the values are indexed like the variables
+    pub fn eval(&self, values: &[f32]) -> f32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(index) => values[*index],
+            Expr::Negate(expr) => -expr.eval(values),
+            Expr::Not(expr) => truth(expr.eval(values) == 0.0),
+            Expr::Binary(op, lhs, rhs) => {
+                let a = lhs.eval(values);
+                let b = rhs.eval(values);
+
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Rem => a % b,
+                    BinaryOp::Pow => a.powf(b),
+                    BinaryOp::Less => truth(a < b),
+                    BinaryOp::LessEqual => truth(a <= b),
+                    BinaryOp::Greater => truth(a > b),
+                    BinaryOp::GreaterEqual => truth(a >= b),
+                    BinaryOp::Equal => truth(a == b),
+                    BinaryOp::NotEqual => truth(a != b),
+                    BinaryOp::And => truth(a != 0.0 && b != 0.0),
+                    BinaryOp::Or => truth(a != 0.0 || b != 0.0),
+                }
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if condition.eval(values) != 0.0 {
+                    then.eval(values)
+                } else {
+                    otherwise.eval(values)
+                }
+            }
+            Expr::Call(function, arguments) => {
+                let arg = |i: usize| arguments[i].eval(values);
+
+                match function {
+                    Function::Min => arguments
+                        .iter()
+                        .map(|expr| expr.eval(values))
+                        .fold(f32::INFINITY, f32::min),
+                    Function::Max => arguments
+                        .iter()
+                        .map(|expr| expr.eval(values))
+                        .fold(f32::NEG_INFINITY, f32::max),
+                    Function::Abs => arg(0).abs(),
+                    Function::Pow => arg(0).powf(arg(1)),
+                    Function::Sqrt => arg(0).sqrt(),
+                    Function::Clamp => arg(0).max(arg(1)).min(arg(2)),
+                    Function::Floor => arg(0).floor(),
+                    Function::Ceil => arg(0).ceil(),
+                    Function::Round => arg(0).round(),
+                    Function::Sin => arg(0).sin(),
+                    Function::Cos => arg(0).cos(),
+                    Function::Exp => arg(0).exp(),
+                    Function::Log => arg(0).ln(),
+                    Function::If => {
+                        if arg(0) != 0.0 {
+                            arg(1)
+                        } else {
+                            arg(2)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A value bound to an expression variable
+pub enum Operand {
+    Scalar(f32),
+    Slice(Arc<ImageSlice>),
+    Image(Arc<ColorImage>),
+}
+
+impl Operand {
+    /// Normalized value of the operand at a pixel, for the given channel of
+    /// images (or their luminance when no channel is given)
+    fn value(&self, x: usize, y: usize, channel: Option<usize>) -> f32 {
+        match self {
+            Operand::Scalar(value) => *value,
+            Operand::Slice(slice) => {
+                if x < slice.size[0] && y < slice.size[1] {
+                    slice.pixels[y * slice.size[0] + x] as f32 / 255.0
+                } else {
+                    0.0
+                }
+            }
+            Operand::Image(image) => {
+                if x < image.size[0] && y < image.size[1] {
+                    let px = image.pixels[y * image.size[0] + x];
+                    match channel {
+                        Some(channel) => px[channel] as f32 / 255.0,
+                        None => {
+                            (0.299 * px.r() as f32 + 0.587 * px.g() as f32 + 0.114 * px.b() as f32)
+                                / 255.0
+                        }
+                    }
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    fn size(&self) -> Option<[usize; 2]> {
+        match self {
+            Operand::Scalar(_) => None,
+            Operand::Slice(slice) => Some(slice.size),
+            Operand::Image(image) => Some(image.size),
+        }
+    }
+}
+
+/// Size of the first image or slice operand
+pub fn operands_size(operands: &[Operand]) -> Option<[usize; 2]> {
+    operands.iter().find_map(Operand::size)
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Evaluate the expression once per pixel, with images seen through their luminance
+pub fn evaluate_slice(expr: &Expr, operands: &[Operand], size: [usize; 2]) -> ImageSlice {
+    let mut output = ImageSlice::new(SliceColor::Gray, size);
+    let mut values = vec![0.0; operands.len() + PIXEL_VARIABLES.len()];
+
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            fill_values(&mut values, operands, [x, y], size, None);
+            output.pixels[y * size[0] + x] = to_byte(expr.eval(&values));
+        }
+    }
+
+    output
+}
+
+// Evaluate the expression once per pixel and channel, slices and scalars
+// contribute the same value to every channel
+pub fn evaluate_image(expr: &Expr, operands: &[Operand], size: [usize; 2]) -> ColorImage {
+    let mut output = ColorImage::new(size, Color32::BLACK);
+    let mut values = vec![0.0; operands.len() + PIXEL_VARIABLES.len()];
+
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            let mut channels = [0; 3];
+
+            for (channel, value) in channels.iter_mut().enumerate() {
+                fill_values(&mut values, operands, [x, y], size, Some(channel));
+                *value = to_byte(expr.eval(&values));
+            }
+
+            output.pixels[y * size[0] + x] =
+                Color32::from_rgb(channels[0], channels[1], channels[2]);
+        }
+    }
+
+    output
+}
+
+fn fill_values(
+    values: &mut [f32],
+    operands: &[Operand],
+    [x, y]: [usize; 2],
+    size: [usize; 2],
+    channel: Option<usize>,
+) {
+    for (value, operand) in values.iter_mut().zip(operands) {
+        *value = operand.value(x, y, channel);
+    }
+
+    let pixel = [x as f32, y as f32, size[0] as f32, size[1] as f32];
+    values[operands.len()..].copy_from_slice(&pixel);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> f32 {
+        parse(source, &[]).unwrap().eval(&[])
+    }
+
+    #[test]
+    fn operators_follow_their_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("-2 ^ 2"), -4.0);
+        assert_eq!(eval("1 + 1 == 2 && 3 < 2 || 1"), 1.0);
+        assert_eq!(eval("!0 ? 4 : 5"), 4.0);
+        assert_eq!(eval("7 % 4"), 3.0);
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert_eq!(eval("min(3, 1, 2) + max(4, 5)"), 6.0);
+        assert_eq!(eval("clamp(1.5, 0, 1)"), 1.0);
+        assert_eq!(eval("if(0, 1, 2)"), 2.0);
+        assert_eq!(eval("round(pi)"), 3.0);
+    }
+
+    #[test]
+    fn variables_come_before_the_pixel_variables() {
+        let expr = parse("a * 2 + w", &["a"]).unwrap();
+
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                BinaryOp::Add,
+                Box::new(Expr::Binary(
+                    BinaryOp::Mul,
+                    Box::new(Expr::Variable(0)),
+                    Box::new(Expr::Number(2.0)),
+                )),
+                Box::new(Expr::Variable(3)),
+            )
+        );
+        assert_eq!(expr.eval(&[1.5, 0.0, 0.0, 10.0, 20.0]), 13.0);
+    }
+
+    #[test]
+    fn errors_give_their_position() {
+        let error = parse("1 + b", &["a"]).unwrap_err();
+        assert_eq!(error.position, 4);
+        assert_eq!(error.message, "Unknown variable 'b'");
+
+        let error = parse("pow(2)", &[]).unwrap_err();
+        assert_eq!(error.position, 0);
+
+        let error = parse("(1 + 2", &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "column 7: Expected ')' but found end of expression"
+        );
+
+        assert!(parse("1 2", &[]).is_err());
+        assert!(parse("", &[]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use egui::epaint::{Color32, ColorImage};
+use image::{ColorType, DynamicImage};
+
+/// An image with floating point channels, for the high bit depth and HDR
+/// images that do not fit in 8 bits without losing their precision
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatImage {
+    /// width, height.
+    pub size: [usize; 2],
+    /// Red, green, blue and alpha of the pixels, row by row. The colors may
+    /// exceed 1 in linear images.
+    pub pixels: Vec<[f32; 4]>,
+    /// The colors are proportional to the light, as in HDR and EXR files,
+    /// instead of being encoded in sRGB
+    pub linear: bool,
+}
+
+impl FloatImage {
+    /// Keeps the images decoded with more than 8 bits per channel
+    pub fn from_dynamic(image: &DynamicImage) -> Option<Self> {
+        let linear = match image.color() {
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => false,
+            ColorType::Rgb32F | ColorType::Rgba32F => true,
+            _ => return None,
+        };
+
+        let buffer = image.to_rgba32f();
+
+        Some(Self {
+            size: [buffer.width() as usize, buffer.height() as usize],
+            pixels: buffer.pixels().map(|pixel| pixel.0).collect(),
+            linear,
+        })
+    }
+
+    pub fn from_color_image(image: &ColorImage) -> Self {
+        let pixels = image
+            .pixels
+            .iter()
+            .map(|color| {
+                let [r, g, b, a] = color.to_srgba_unmultiplied();
+                [r, g, b, a].map(|channel| channel as f32 / 255.0)
+            })
+            .collect();
+
+        Self {
+            size: image.size,
+            pixels,
+            linear: false,
+        }
+    }
+
+    /// The image as displayed, the colors are multiplied by `2^stops` and
+    /// those out of range are clipped
+    pub fn tone_map(&self, stops: f32) -> ColorImage {
+        let gain = 2f32.powf(stops);
+        let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|[r, g, b, a]| {
+                let [r, g, b] = [r, g, b].map(|channel| {
+                    if self.linear {
+                        linear_to_srgb(channel * gain)
+                    } else {
+                        channel * gain
+                    }
+                });
+
+                Color32::from_rgba_unmultiplied(byte(r), byte(g), byte(b), byte(*a))
+            })
+            .collect();
+
+        ColorImage {
+            size: self.size,
+            pixels,
+        }
+    }
+
+    pub fn to_color_image(&self) -> ColorImage {
+        self.tone_map(0.0)
+    }
+
+    /// The colors proportional to the light, as written in HDR and EXR files
+    pub fn to_linear(&self) -> Self {
+        self.convert(true, srgb_to_linear)
+    }
+
+    /// The colors encoded in sRGB, as written in 16 bits TIFF and PNG files
+    pub fn to_srgb(&self) -> Self {
+        self.convert(false, linear_to_srgb)
+    }
+
+    fn convert(&self, linear: bool, transfer: fn(f32) -> f32) -> Self {
+        if self.linear == linear {
+            return self.clone();
+        }
+
+        Self {
+            size: self.size,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|[r, g, b, a]| [transfer(*r), transfer(*g), transfer(*b), *a])
+                .collect(),
+            linear,
+        }
+    }
+
+    /// Size of the pixels in memory
+    pub fn bytes(&self) -> usize {
+        self.pixels.len() * std::mem::size_of::<[f32; 4]>()
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: Vec<[f32; 4]>, linear: bool) -> FloatImage {
+        FloatImage {
+            size: [pixels.len(), 1],
+            pixels,
+            linear,
+        }
+    }
+
+    #[test]
+    fn transfer_functions_are_inverse() {
+        for value in [0.0, 0.002, 0.04, 0.2, 0.5, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+
+        // Middle gray
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn conversions_keep_the_alpha_and_round_trip() {
+        let srgb = image(vec![[0.5, 0.25, 1.0, 0.5]], false);
+        let linear = srgb.to_linear();
+
+        assert!(linear.linear);
+        assert_eq!(linear.pixels[0][3], 0.5);
+        assert_eq!(linear.to_linear(), linear);
+
+        let back = linear.to_srgb();
+        assert!(!back.linear);
+        for (channel, expected) in back.pixels[0].iter().zip(srgb.pixels[0]) {
+            assert!((channel - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn tone_map_applies_the_exposure_and_clips() {
+        let hdr = image(vec![[4.0, 0.25, 0.0, 1.0], [-1.0, 0.5, 2.0, 1.0]], true);
+
+        let image = hdr.tone_map(0.0);
+        assert_eq!(image.pixels[0].r(), 255);
+        assert_eq!(image.pixels[1].r(), 0);
+        assert_eq!(image.pixels[1].b(), 255);
+
+        // One stop down brings the 0.5 of the linear image to 0.25
+        let darker = hdr.tone_map(-1.0);
+        assert_eq!(darker.pixels[1].g(), image.pixels[0].g());
+    }
+
+    #[test]
+    fn only_high_bit_depths_are_kept() {
+        let bytes = image::RgbImage::new(2, 2);
+        assert!(FloatImage::from_dynamic(&DynamicImage::ImageRgb8(bytes)).is_none());
+
+        let words = image::ImageBuffer::from_pixel(2, 1, image::Rgb([65535u16, 0, 32768]));
+        let float = FloatImage::from_dynamic(&DynamicImage::ImageRgb16(words)).unwrap();
+        assert_eq!(float.size, [2, 1]);
+        assert!(!float.linear);
+        assert_eq!(float.pixels[0][0], 1.0);
+        assert_eq!(float.pixels[0][3], 1.0);
+        assert!((float.pixels[0][2] - 0.5).abs() < 1e-4);
+
+        let hdr = image::Rgb32FImage::from_pixel(1, 1, image::Rgb([2.0, 0.0, 0.0]));
+        assert!(
+            FloatImage::from_dynamic(&DynamicImage::ImageRgb32F(hdr))
+                .unwrap()
+                .linear
+        );
+    }
+
+    #[test]
+    fn bytes_counts_four_floats_per_pixel() {
+        assert_eq!(image(vec![[0.0; 4]; 3], false).bytes(), 48);
+    }
+}
@@ -1,5 +1,10 @@
+use std::sync::Arc;
+
 use egui::epaint::{Color32, ColorImage};
-use image::imageops;
+use image::codecs::hdr::HdrEncoder;
+use image::{imageops, DynamicImage, ImageFormat};
+
+use crate::app::math::float_image::FloatImage;
 
 #[derive(Clone, PartialEq)]
 pub enum SliceColor {
@@ -86,7 +91,7 @@ pub fn egui_to_image(image: ColorImage) -> image::RgbImage {
     let mut image_buffer = image::RgbImage::new(image.size[0] as u32, image.size[1] as u32);
 
     for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
-        let index = (x as usize) * image.size[0] + (y as usize);
+        let index = (y as usize) * image.size[0] + (x as usize);
         let tmp_pixels = image.pixels[index];
         *pixel = image::Rgb([tmp_pixels.r(), tmp_pixels.g(), tmp_pixels.b()]);
     }
@@ -100,7 +105,7 @@ pub fn image_to_egui(image: image::RgbImage) -> ColorImage {
         Color32::BLACK,
     );
     for (x, y, &pixel) in image.enumerate_pixels() {
-        let index = (x as usize) * image_buffer.size[0] + (y as usize);
+        let index = (y as usize) * image_buffer.size[0] + (x as usize);
         image_buffer.pixels[index] = Color32::from_rgb(pixel[0], pixel[1], pixel[2]);
     }
 
@@ -111,7 +116,7 @@ pub fn _slice_to_luma(image: ImageSlice) -> image::GrayImage {
     let mut image_buffer = image::GrayImage::new(image.size[0] as u32, image.size[1] as u32);
 
     for (x, y, pixel) in image_buffer.enumerate_pixels_mut() {
-        let index = (x as usize) * image.size[0] + (y as usize);
+        let index = (y as usize) * image.size[0] + (x as usize);
         let tmp_pixels = image.pixels[index];
         *pixel = image::Luma([tmp_pixels]);
     }
@@ -125,7 +130,7 @@ pub fn luma_to_slice(image: image::GrayImage) -> ImageSlice {
         [image.width() as usize, image.height() as usize],
     );
     for (x, y, &pixel) in image.enumerate_pixels() {
-        let index = (x as usize) * image_buffer.size[0] + (y as usize);
+        let index = (y as usize) * image_buffer.size[0] + (x as usize);
         image_buffer.pixels[index] = pixel[0];
     }
 
@@ -187,19 +192,183 @@ pub fn _gray_to_image(buffer: Vec<f32>, row_size: usize, col_size: usize) -> Col
 
     image
 }
-// from emgui_extras 1.18.0 / images.rs
-pub fn load_image_bytes(image_bytes: &[u8]) -> Option<ColorImage> {
-    let result = image::load_from_memory(image_bytes).map_err(|err| err.to_string());
+/// An image decoded from a file, along with its format. The images with more
+/// than 8 bits per channel are also kept in floating point.
+#[derive(Clone)]
+pub struct DecodedImage {
+    pub image: Arc<ColorImage>,
+    pub float: Option<Arc<FloatImage>>,
+    pub format: &'static str,
+}
 
-    match result {
-        Ok(image) => {
+/// Extensions of the image files that can be read
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "tif", "tiff", "bmp", "gif", "tga", "ico", "qoi", "hdr", "exr",
+];
+
+/// Extensions of the image files that can be written, WebP is only decoded
+pub const WRITABLE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "tif", "tiff", "bmp", "gif", "tga", "ico", "qoi", "hdr", "exr",
+];
+
+/// Whether an image can be written at this path, according to its extension
+pub fn is_writable(path: &std::path::Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| {
+            WRITABLE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+        })
+}
+
+/// Decodes an image, its format is guessed from its content or else from the
+/// extension of `name`, a path or an URL, since some formats such as TGA have
+/// no signature.
+pub fn decode_image(bytes: &[u8], name: &str) -> Result<DecodedImage, String> {
+    let format = guess_format(bytes, name)
+        .ok_or_else(|| "The format of the image is not supported".to_string())?;
+    let image = image::load_from_memory_with_format(bytes, format)
+        .map_err(|err| format!("Failed to decode the image: {}", err))?;
+
+    let float = FloatImage::from_dynamic(&image);
+    let image = match &float {
+        Some(float) => float.to_color_image(),
+        None => {
             let size = [image.width() as _, image.height() as _];
-            let image_buffer = image.to_rgba8();
-            let pixels = image_buffer.as_flat_samples();
+            ColorImage::from_rgba_unmultiplied(size, image.to_rgba8().as_raw())
+        }
+    };
+
+    Ok(DecodedImage {
+        image: Arc::new(image),
+        float: float.map(Arc::new),
+        format: format_name(format),
+    })
+}
+
+pub fn load_image_file(path: &std::path::Path) -> Result<DecodedImage, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+    decode_image(&bytes, &path.to_string_lossy())
+}
+
+pub fn guess_format(bytes: &[u8], name: &str) -> Option<ImageFormat> {
+    if let Ok(format) = image::guess_format(bytes) {
+        return Some(format);
+    }
+
+    // The query and the fragment of an URL are not part of the extension, but
+    // the files may have these characters in their name
+    let path = if name.contains("://") {
+        name.split(['?', '#']).next().unwrap_or(name)
+    } else {
+        name
+    };
+    ImageFormat::from_path(path).ok()
+}
+
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "PNG",
+        ImageFormat::Jpeg => "JPEG",
+        ImageFormat::Gif => "GIF",
+        ImageFormat::WebP => "WebP",
+        ImageFormat::Pnm => "PNM",
+        ImageFormat::Tiff => "TIFF",
+        ImageFormat::Tga => "TGA",
+        ImageFormat::Dds => "DDS",
+        ImageFormat::Bmp => "BMP",
+        ImageFormat::Ico => "ICO",
+        ImageFormat::Hdr => "Radiance HDR",
+        ImageFormat::OpenExr => "OpenEXR",
+        ImageFormat::Farbfeld => "Farbfeld",
+        ImageFormat::Avif => "AVIF",
+        ImageFormat::Qoi => "QOI",
+        _ => "Unknown",
+    }
+}
+
+// The format is given by the extension of the path, the formats without
+// transparency are written without the alpha channel
+pub fn save_image(image: &ColorImage, path: &std::path::Path) -> Result<(), String> {
+    let error = |err: image::ImageError| format!("Failed to write {}: {}", path.display(), err);
+
+    if !is_writable(path) {
+        return Err(format!("Cannot write images in {}", path.display()));
+    }
+
+    let format = ImageFormat::from_path(path).map_err(error)?;
+
+    // Only floating point images can be written in these formats
+    if let ImageFormat::Hdr | ImageFormat::OpenExr = format {
+        return save_float_image(&FloatImage::from_color_image(image), path);
+    }
+
+    let pixels = image
+        .pixels
+        .iter()
+        .flat_map(|color| color.to_srgba_unmultiplied())
+        .collect();
+    let buffer = image::RgbaImage::from_raw(image.size[0] as u32, image.size[1] as u32, pixels)
+        .ok_or("The size of the image does not match its pixels")?;
+    let image = DynamicImage::ImageRgba8(buffer);
+
+    match format {
+        ImageFormat::Jpeg | ImageFormat::Pnm => DynamicImage::ImageRgb8(image.to_rgb8())
+            .save_with_format(path, format)
+            .map_err(error),
+        _ => image.save_with_format(path, format).map_err(error),
+    }
+}
+
+/// Writes the colors in floating point in HDR and EXR files, and in 16 bits
+/// in TIFF and PNG files. The other formats are written in 8 bits.
+pub fn save_float_image(image: &FloatImage, path: &std::path::Path) -> Result<(), String> {
+    let error = |err: image::ImageError| format!("Failed to write {}: {}", path.display(), err);
+
+    let format = ImageFormat::from_path(path).map_err(error)?;
+    let [width, height] = image.size;
+
+    match format {
+        ImageFormat::OpenExr => {
+            let pixels = image.to_linear().pixels.into_iter().flatten().collect();
+            let buffer = image::Rgba32FImage::from_raw(width as u32, height as u32, pixels)
+                .ok_or("The size of the image does not match its pixels")?;
 
-            Some(ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
+            DynamicImage::ImageRgba32F(buffer)
+                .save_with_format(path, format)
+                .map_err(error)
         }
-        Err(_) => None,
+        ImageFormat::Hdr => {
+            let pixels: Vec<image::Rgb<f32>> = image
+                .to_linear()
+                .pixels
+                .into_iter()
+                .map(|[r, g, b, _]| image::Rgb([r, g, b]))
+                .collect();
+
+            let file = std::fs::File::create(path)
+                .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+            HdrEncoder::new(std::io::BufWriter::new(file))
+                .encode(&pixels, width, height)
+                .map_err(error)
+        }
+        ImageFormat::Tiff | ImageFormat::Png => {
+            let pixels = image
+                .to_srgb()
+                .pixels
+                .into_iter()
+                .flatten()
+                .map(|channel| (channel.clamp(0.0, 1.0) * 65535.0).round() as u16)
+                .collect();
+            let buffer = image::ImageBuffer::from_raw(width as u32, height as u32, pixels)
+                .ok_or("The size of the image does not match its pixels")?;
+
+            DynamicImage::ImageRgba16(buffer)
+                .save_with_format(path, format)
+                .map_err(error)
+        }
+        _ => save_image(&image.to_color_image(), path),
     }
 }
 
@@ -258,3 +427,58 @@ pub fn rotate_image(image: &ColorImage, sigma: i32) -> ColorImage {
 
     image_to_egui(output_image)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        let mut bytes = std::io::Cursor::new(vec![]);
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut bytes, format)
+            .unwrap();
+
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn format_is_guessed_from_the_content() {
+        let decoded = decode_image(&encode(ImageFormat::Png), "image.jpg").unwrap();
+
+        assert_eq!(decoded.format, "PNG");
+        assert_eq!(decoded.image.size, [2, 2]);
+        assert!(decoded.float.is_none());
+    }
+
+    #[test]
+    fn format_without_signature_is_given_by_the_extension() {
+        let bytes = encode(ImageFormat::Tga);
+
+        let decoded = decode_image(&bytes, "https://example.com/image.TGA?size=2#top").unwrap();
+        assert_eq!(decoded.format, "TGA");
+        assert_eq!(decoded.image.pixels[0], Color32::RED);
+
+        assert!(decode_image(&bytes, "https://example.com/image").is_err());
+    }
+
+    #[test]
+    fn file_names_keep_their_query_and_fragment_characters() {
+        let bytes = encode(ImageFormat::Tga);
+
+        assert_eq!(
+            guess_format(&bytes, "shots/shot#1.tga"),
+            Some(ImageFormat::Tga)
+        );
+        assert_eq!(guess_format(&bytes, "what?.tga"), Some(ImageFormat::Tga));
+        assert_eq!(guess_format(&bytes, "shot.tga#1"), None);
+    }
+
+    #[test]
+    fn webp_is_only_read() {
+        assert!(IMAGE_EXTENSIONS.contains(&"webp"));
+        assert!(!is_writable(std::path::Path::new("out/frame.webp")));
+        assert!(is_writable(std::path::Path::new("out/frame.PNG")));
+        assert!(!is_writable(std::path::Path::new("out/frame")));
+    }
+}
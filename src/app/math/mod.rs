@@ -1,2 +1,10 @@
+pub mod animation;
+pub mod blend;
+pub mod denoise;
+pub mod expression;
 pub mod fft;
+pub mod float_image;
 pub mod image;
+pub mod scalar;
+pub mod statistics;
+pub mod transform;
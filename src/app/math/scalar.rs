@@ -0,0 +1,85 @@
+use egui::epaint::{Color32, ColorImage};
+
+/// Binary operations between two scalars
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarOperation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Modulo,
+    Minimum,
+    Maximum,
+}
+
+impl Default for ScalarOperation {
+    fn default() -> Self {
+        ScalarOperation::Add
+    }
+}
+
+/// Comparisons between two scalars
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Comparison::Greater
+    }
+}
+
+pub fn apply_operation(a: f32, b: f32, operation: ScalarOperation) -> f32 {
+    match operation {
+        ScalarOperation::Add => a + b,
+        ScalarOperation::Subtract => a - b,
+        ScalarOperation::Multiply => a * b,
+        ScalarOperation::Divide => a / b,
+        ScalarOperation::Power => a.powf(b),
+        ScalarOperation::Modulo => a.rem_euclid(b),
+        ScalarOperation::Minimum => a.min(b),
+        ScalarOperation::Maximum => a.max(b),
+    }
+}
+
+pub fn compare(a: f32, b: f32, comparison: Comparison) -> bool {
+    match comparison {
+        Comparison::Less => a < b,
+        Comparison::LessOrEqual => a <= b,
+        Comparison::Equal => a == b,
+        Comparison::NotEqual => a != b,
+        Comparison::GreaterOrEqual => a >= b,
+        Comparison::Greater => a > b,
+    }
+}
+
+// Average color of the image. The channels are premultiplied by the alpha,
+// so transparent pixels weigh less in the mean of the color
+pub fn mean_color(image: &ColorImage) -> Color32 {
+    let mut sum = [0.0f64; 4];
+
+    for px in &image.pixels {
+        sum[0] += px.r() as f64;
+        sum[1] += px.g() as f64;
+        sum[2] += px.b() as f64;
+        sum[3] += px.a() as f64;
+    }
+
+    let count = image.pixels.len().max(1) as f64;
+    let [r, g, b, a] = sum.map(|channel| (channel / count).round() as u8);
+
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+// Relative luminance of a color, between 0 and 255, with the same weights as
+// the gray scales conversion
+pub fn luminance(color: Color32) -> f32 {
+    0.2126 * color.r() as f32 + 0.7152 * color.g() as f32 + 0.0722 * color.b() as f32
+}
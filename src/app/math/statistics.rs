@@ -0,0 +1,189 @@
+use egui::epaint::{Color32, ColorImage};
+
+use super::image::ImageSlice;
+use super::scalar::luminance;
+
+/// Number of pixels of each intensity
+pub type Histogram = [usize; 256];
+
+/// Channel of an image the statistics are computed on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luminance,
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Channel::Luminance
+    }
+}
+
+impl Channel {
+    pub const COLORS: [Channel; 4] = [
+        Channel::Red,
+        Channel::Green,
+        Channel::Blue,
+        Channel::Luminance,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Red => "Red",
+            Channel::Green => "Green",
+            Channel::Blue => "Blue",
+            Channel::Alpha => "Alpha",
+            Channel::Luminance => "Luminance",
+        }
+    }
+
+    pub fn value(&self, px: Color32) -> u8 {
+        match self {
+            Channel::Red => px.r(),
+            Channel::Green => px.g(),
+            Channel::Blue => px.b(),
+            Channel::Alpha => px.a(),
+            Channel::Luminance => luminance(px).round() as u8,
+        }
+    }
+}
+
+pub fn histogram(values: impl Iterator<Item = u8>) -> Histogram {
+    let mut histogram = [0; 256];
+
+    for value in values {
+        histogram[value as usize] += 1;
+    }
+
+    histogram
+}
+
+/// Statistics of the intensities of a channel, computed from its histogram
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub histogram: Histogram,
+    pub count: usize,
+    pub mean: f32,
+    pub std_dev: f32,
+    pub min: u8,
+    pub max: u8,
+    /// Shannon entropy, in bits
+    pub entropy: f32,
+}
+
+impl Statistics {
+    pub fn new(histogram: Histogram) -> Self {
+        let count: usize = histogram.iter().sum();
+
+        let mut statistics = Self {
+            histogram,
+            count,
+            mean: 0.0,
+            std_dev: 0.0,
+            min: 0,
+            max: 0,
+            entropy: 0.0,
+        };
+
+        if count == 0 {
+            return statistics;
+        }
+
+        let total = count as f64;
+        let bins = || histogram.iter().enumerate().filter(|(_, &n)| n > 0);
+
+        let mean = bins().map(|(v, &n)| v as f64 * n as f64).sum::<f64>() / total;
+        let variance = bins()
+            .map(|(v, &n)| (v as f64 - mean).powi(2) * n as f64)
+            .sum::<f64>()
+            / total;
+        let entropy = bins()
+            .map(|(_, &n)| {
+                let p = n as f64 / total;
+                -p * p.log2()
+            })
+            .sum::<f64>();
+
+        statistics.mean = mean as f32;
+        statistics.std_dev = variance.sqrt() as f32;
+        statistics.min = bins().next().map_or(0, |(v, _)| v as u8);
+        statistics.max = bins().next_back().map_or(0, |(v, _)| v as u8);
+        statistics.entropy = entropy as f32;
+
+        statistics
+    }
+
+    pub fn of_image(image: &ColorImage, channel: Channel) -> Self {
+        Self::new(histogram(image.pixels.iter().map(|&px| channel.value(px))))
+    }
+
+    pub fn of_slice(slice: &ImageSlice) -> Self {
+        Self::new(histogram(slice.pixels.iter().copied()))
+    }
+
+    /// Smallest intensity such that `percent` of the pixels are not above it
+    pub fn percentile(&self, percent: f32) -> u8 {
+        let rank = (percent.clamp(0.0, 100.0) as f64 / 100.0 * self.count as f64).ceil() as usize;
+
+        let mut cumulated = 0;
+        for (value, &n) in self.histogram.iter().enumerate() {
+            cumulated += n;
+            if n > 0 && cumulated >= rank {
+                return value as u8;
+            }
+        }
+
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_of_two_intensities() {
+        let statistics = Statistics::new(histogram([10, 20, 20, 10].into_iter()));
+
+        assert_eq!(statistics.count, 4);
+        assert_eq!(statistics.mean, 15.0);
+        assert_eq!(statistics.std_dev, 5.0);
+        assert_eq!(statistics.min, 10);
+        assert_eq!(statistics.max, 20);
+        assert_eq!(statistics.entropy, 1.0);
+    }
+
+    #[test]
+    fn statistics_of_nothing() {
+        let statistics = Statistics::new([0; 256]);
+
+        assert_eq!(statistics.count, 0);
+        assert_eq!(statistics.mean, 0.0);
+        assert_eq!(statistics.entropy, 0.0);
+        assert_eq!(statistics.percentile(50.0), 0);
+    }
+
+    #[test]
+    fn percentile_is_the_smallest_intensity_reaching_the_rank() {
+        let statistics = Statistics::new(histogram([10, 20, 20, 10].into_iter()));
+
+        assert_eq!(statistics.percentile(0.0), 10);
+        assert_eq!(statistics.percentile(50.0), 10);
+        assert_eq!(statistics.percentile(51.0), 20);
+        assert_eq!(statistics.percentile(100.0), 20);
+        assert_eq!(statistics.percentile(150.0), 20);
+    }
+
+    #[test]
+    fn luminance_channel_of_gray_is_its_intensity() {
+        let image = ColorImage::new([2, 2], Color32::from_gray(128));
+
+        let statistics = Statistics::of_image(&image, Channel::Luminance);
+
+        assert_eq!((statistics.min, statistics.max), (128, 128));
+        assert_eq!(statistics.entropy, 0.0);
+    }
+}
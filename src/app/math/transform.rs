@@ -0,0 +1,580 @@
+use egui::epaint::{Color32, ColorImage};
+use image::imageops::{self, FilterType};
+
+use super::image::{egui_to_image, image_to_egui};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Bilinear
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderMode {
+    /// Pixels outside of the image are black
+    Constant,
+    /// Pixels outside of the image repeat the closest edge pixel
+    Replicate,
+    /// Pixels outside of the image mirror the image content
+    Reflect,
+    /// Pixels outside of the image wrap around to the opposite edge
+    Wrap,
+}
+
+impl Default for BorderMode {
+    fn default() -> Self {
+        BorderMode::Constant
+    }
+}
+
+// Resize an image, a null dimension keeps the aspect ratio of the source image
+pub fn resize_image(
+    image: &ColorImage,
+    width: usize,
+    height: usize,
+    interpolation: Interpolation,
+) -> ColorImage {
+    let [src_width, src_height] = image.size;
+
+    let (width, height) = match (width, height) {
+        (0, 0) => (src_width, src_height),
+        (0, h) => ((src_width * h / src_height.max(1)).max(1), h),
+        (w, 0) => (w, (src_height * w / src_width.max(1)).max(1)),
+        (w, h) => (w, h),
+    };
+
+    let filter = match interpolation {
+        Interpolation::Nearest => FilterType::Nearest,
+        Interpolation::Bilinear => FilterType::Triangle,
+        Interpolation::Bicubic => FilterType::CatmullRom,
+        Interpolation::Lanczos => FilterType::Lanczos3,
+    };
+
+    let temp_image = egui_to_image(image.clone());
+
+    let output_image = imageops::resize(&temp_image, width as u32, height as u32, filter);
+
+    image_to_egui(output_image)
+}
+
+// Downscale an image so that its largest side is at most `max_size`. Each
+// pixel of the result averages the block of pixels it covers, which is faster
+// than a resize and does not alias
+pub fn thumbnail_image(image: &ColorImage, max_size: usize) -> ColorImage {
+    let [width, height] = image.size;
+    let largest = width.max(height);
+
+    if largest <= max_size {
+        return image.clone();
+    }
+
+    let size = [
+        (width * max_size / largest).max(1),
+        (height * max_size / largest).max(1),
+    ];
+    let mut output = ColorImage::new(size, Color32::TRANSPARENT);
+
+    for y in 0..size[1] {
+        let y0 = y * height / size[1];
+        let y1 = ((y + 1) * height / size[1]).max(y0 + 1);
+
+        for x in 0..size[0] {
+            let x0 = x * width / size[0];
+            let x1 = ((x + 1) * width / size[0]).max(x0 + 1);
+
+            // The channels are premultiplied, so they can be averaged directly
+            let mut sum = [0u64; 4];
+            for row in image.pixels[y0 * width..y1 * width].chunks_exact(width) {
+                for px in &row[x0..x1] {
+                    for (channel, value) in sum.iter_mut().zip(px.to_array()) {
+                        *channel += value as u64;
+                    }
+                }
+            }
+
+            let count = ((x1 - x0) * (y1 - y0)) as u64;
+            let [r, g, b, a] = sum.map(|channel| ((channel + count / 2) / count) as u8);
+            output.pixels[y * size[0] + x] = Color32::from_rgba_premultiplied(r, g, b, a);
+        }
+    }
+
+    output
+}
+
+// Crop a region of the image, the region is clamped to the image bounds
+// and a null width or height extends the region up to the image edge
+pub fn crop_image(
+    image: &ColorImage,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> ColorImage {
+    let [src_width, src_height] = image.size;
+
+    let x = x.min(src_width.saturating_sub(1));
+    let y = y.min(src_height.saturating_sub(1));
+
+    let width = match width {
+        0 => src_width - x,
+        w => w.min(src_width - x),
+    };
+    let height = match height {
+        0 => src_height - y,
+        h => h.min(src_height - y),
+    };
+
+    let mut output = ColorImage::new([width, height], Color32::BLACK);
+
+    for row in 0..height {
+        let src_start = (y + row) * src_width + x;
+        let dst_start = row * width;
+
+        output.pixels[dst_start..dst_start + width]
+            .copy_from_slice(&image.pixels[src_start..src_start + width]);
+    }
+
+    output
+}
+
+// Add a border around the image, filled according to the border mode. The
+// constant border is filled with the given color
+pub fn pad_image(
+    image: &ColorImage,
+    padding: [usize; 4],
+    border: BorderMode,
+    fill: Color32,
+) -> ColorImage {
+    let [top, right, bottom, left] = padding;
+    let [src_width, src_height] = image.size;
+
+    let size = [src_width + left + right, src_height + top + bottom];
+    let mut output = ColorImage::new(size, fill);
+
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            let src_x = border_index(x as isize - left as isize, src_width, border);
+            let src_y = border_index(y as isize - top as isize, src_height, border);
+
+            if let (Some(src_x), Some(src_y)) = (src_x, src_y) {
+                output.pixels[y * size[0] + x] = image.pixels[src_y * src_width + src_x];
+            }
+        }
+    }
+
+    output
+}
+
+// Rotate the image clockwise around its center by an angle in degrees. When
+// expanding, the output grows to contain the whole rotated image, otherwise
+// it keeps the source size and the corners are cropped
+pub fn rotate_image_angle(
+    image: &ColorImage,
+    angle: f32,
+    expand: bool,
+    interpolation: Interpolation,
+) -> ColorImage {
+    let [src_width, src_height] = image.size;
+    let (sin, cos) = angle.to_radians().sin_cos();
+
+    let size = if expand {
+        let w = src_width as f32;
+        let h = src_height as f32;
+        [
+            (w * cos.abs() + h * sin.abs()).round().max(1.0) as usize,
+            (w * sin.abs() + h * cos.abs()).round().max(1.0) as usize,
+        ]
+    } else {
+        image.size
+    };
+
+    let src_center = (src_width as f32 / 2.0, src_height as f32 / 2.0);
+    let dst_center = (size[0] as f32 / 2.0, size[1] as f32 / 2.0);
+
+    warp(image, size, interpolation, |x, y| {
+        let dx = x - dst_center.0;
+        let dy = y - dst_center.1;
+
+        (
+            cos * dx + sin * dy + src_center.0,
+            -sin * dx + cos * dy + src_center.1,
+        )
+    })
+}
+
+// Apply the affine transform [a, b, c, d, e, f] that maps a source pixel (x, y)
+// to (a * x + b * y + c, d * x + e * y + f) in the output
+pub fn affine_warp(
+    image: &ColorImage,
+    matrix: [f32; 6],
+    interpolation: Interpolation,
+) -> anyhow::Result<ColorImage> {
+    let [a, b, c, d, e, f] = matrix;
+    let det = a * e - b * d;
+
+    if det.abs() < f32::EPSILON {
+        anyhow::bail!("The affine matrix is not invertible")
+    }
+
+    // Inverse of the 2x2 linear part, applied after removing the translation
+    let inverse = [e / det, -b / det, -d / det, a / det];
+
+    Ok(warp(image, image.size, interpolation, |x, y| {
+        let tx = x - c;
+        let ty = y - f;
+
+        (
+            inverse[0] * tx + inverse[1] * ty,
+            inverse[2] * tx + inverse[3] * ty,
+        )
+    }))
+}
+
+// Move the four corners of the image (top left, top right, bottom right and
+// bottom left) to the given positions, expressed relative to the image size
+pub fn perspective_warp(
+    image: &ColorImage,
+    corners: [[f32; 2]; 4],
+    interpolation: Interpolation,
+) -> anyhow::Result<ColorImage> {
+    let [width, height] = [image.size[0] as f32, image.size[1] as f32];
+
+    let source = [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height]];
+    let target = corners.map(|[x, y]| [x * width, y * height]);
+
+    // We need the mapping from the output back to the source image
+    let h = match homography(target, source) {
+        Some(h) => h,
+        None => anyhow::bail!("The corners do not define a valid perspective"),
+    };
+
+    Ok(warp(image, image.size, interpolation, |x, y| {
+        let w = h[6] * x + h[7] * y + h[8];
+
+        (
+            (h[0] * x + h[1] * y + h[2]) / w,
+            (h[3] * x + h[4] * y + h[5]) / w,
+        )
+    }))
+}
+
+// Build an image of the given size where each pixel is sampled from the source
+// image at the position returned by the inverse mapping
+fn warp(
+    image: &ColorImage,
+    size: [usize; 2],
+    interpolation: Interpolation,
+    inverse: impl Fn(f32, f32) -> (f32, f32),
+) -> ColorImage {
+    let mut output = ColorImage::new(size, Color32::BLACK);
+
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            // Work with pixel centers to avoid a half pixel shift
+            let (src_x, src_y) = inverse(x as f32 + 0.5, y as f32 + 0.5);
+
+            output.pixels[y * size[0] + x] = sample(
+                image,
+                src_x - 0.5,
+                src_y - 0.5,
+                interpolation,
+                BorderMode::Constant,
+            );
+        }
+    }
+
+    output
+}
+
+// Sample the image at a continuous position using a separable kernel
+pub fn sample(
+    image: &ColorImage,
+    x: f32,
+    y: f32,
+    interpolation: Interpolation,
+    border: BorderMode,
+) -> Color32 {
+    let [width, height] = image.size;
+
+    if !x.is_finite() || !y.is_finite() {
+        return Color32::BLACK;
+    }
+
+    if interpolation == Interpolation::Nearest {
+        let src_x = border_index(x.round() as isize, width, border);
+        let src_y = border_index(y.round() as isize, height, border);
+
+        return match (src_x, src_y) {
+            (Some(src_x), Some(src_y)) => image.pixels[src_y * width + src_x],
+            _ => Color32::BLACK,
+        };
+    }
+
+    let radius = match interpolation {
+        Interpolation::Bicubic => 2,
+        Interpolation::Lanczos => 3,
+        _ => 1,
+    };
+
+    let base_x = x.floor() as isize;
+    let base_y = y.floor() as isize;
+
+    let mut sum = [0.0_f32; 4];
+    let mut total = 0.0_f32;
+
+    for j in (1 - radius)..=radius {
+        let weight_y = kernel(y - (base_y + j) as f32, interpolation);
+        if weight_y == 0.0 {
+            continue;
+        }
+
+        for i in (1 - radius)..=radius {
+            let weight = weight_y * kernel(x - (base_x + i) as f32, interpolation);
+            if weight == 0.0 {
+                continue;
+            }
+
+            total += weight;
+
+            let src_x = border_index(base_x + i, width, border);
+            let src_y = border_index(base_y + j, height, border);
+
+            if let (Some(src_x), Some(src_y)) = (src_x, src_y) {
+                let px = image.pixels[src_y * width + src_x];
+                for (channel, value) in sum.iter_mut().enumerate() {
+                    *value += weight * px[channel] as f32;
+                }
+            } else {
+                // Pixels outside of the image are opaque black
+                sum[3] += weight * 255.0;
+            }
+        }
+    }
+
+    if total == 0.0 {
+        return Color32::BLACK;
+    }
+
+    let [r, g, b, a] = sum.map(|value| (value / total).round().clamp(0.0, 255.0) as u8);
+
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+fn kernel(distance: f32, interpolation: Interpolation) -> f32 {
+    let d = distance.abs();
+
+    match interpolation {
+        Interpolation::Nearest => {
+            if d < 0.5 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        Interpolation::Bilinear => (1.0 - d).max(0.0),
+        // Catmull-Rom spline
+        Interpolation::Bicubic => {
+            if d < 1.0 {
+                1.5 * d * d * d - 2.5 * d * d + 1.0
+            } else if d < 2.0 {
+                -0.5 * d * d * d + 2.5 * d * d - 4.0 * d + 2.0
+            } else {
+                0.0
+            }
+        }
+        // Lanczos with a window of 3
+        Interpolation::Lanczos => {
+            if d < f32::EPSILON {
+                1.0
+            } else if d < 3.0 {
+                let pi_d = std::f32::consts::PI * d;
+                3.0 * pi_d.sin() * (pi_d / 3.0).sin() / (pi_d * pi_d)
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+// Map a possibly out of bounds index inside the range 0..size
+pub fn border_index(index: isize, size: usize, border: BorderMode) -> Option<usize> {
+    let size = size as isize;
+
+    if size == 0 {
+        return None;
+    }
+
+    if (0..size).contains(&index) {
+        return Some(index as usize);
+    }
+
+    match border {
+        BorderMode::Constant => None,
+        BorderMode::Replicate => Some(index.clamp(0, size - 1) as usize),
+        BorderMode::Reflect => {
+            let period = 2 * size;
+            let folded = index.rem_euclid(period);
+
+            if folded < size {
+                Some(folded as usize)
+            } else {
+                Some((period - 1 - folded) as usize)
+            }
+        }
+        BorderMode::Wrap => Some(index.rem_euclid(size) as usize),
+    }
+}
+
+// Compute the 3x3 homography (row major) that maps each source point to its
+// target point, by solving the 8x8 linear system with a gaussian elimination
+fn homography(source: [[f32; 2]; 4], target: [[f32; 2]; 4]) -> Option<[f32; 9]> {
+    let mut system = [[0.0_f64; 9]; 8];
+
+    for (i, ([x, y], [u, v])) in source.iter().zip(target.iter()).enumerate() {
+        let (x, y, u, v) = (*x as f64, *y as f64, *u as f64, *v as f64);
+
+        system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+        system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+    }
+
+    for col in 0..8 {
+        // Partial pivoting for numerical stability
+        let pivot = (col..8).max_by(|&a, &b| {
+            system[a][col]
+                .abs()
+                .partial_cmp(&system[b][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+
+        if system[pivot][col].abs() < 1e-10 {
+            return None;
+        }
+
+        system.swap(col, pivot);
+        let pivot_row = system[col];
+
+        for (row, values) in system.iter_mut().enumerate() {
+            if row != col {
+                let factor = values[col] / pivot_row[col];
+                for (value, pivot) in values.iter_mut().zip(pivot_row).skip(col) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+    }
+
+    let mut h = [1.0_f32; 9];
+    for (i, row) in system.iter().enumerate() {
+        h[i] = (row[8] / row[i]) as f32;
+    }
+
+    Some(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Image where each pixel has a distinct red value
+    fn gradient(width: usize, height: usize) -> ColorImage {
+        let pixels = (0..width * height)
+            .map(|i| Color32::from_rgb(i as u8 * 10, 0, 0))
+            .collect();
+
+        ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
+
+    fn apply(h: [f32; 9], [x, y]: [f32; 2]) -> [f32; 2] {
+        let w = h[6] * x + h[7] * y + h[8];
+        [
+            (h[0] * x + h[1] * y + h[2]) / w,
+            (h[3] * x + h[4] * y + h[5]) / w,
+        ]
+    }
+
+    #[test]
+    fn homography_maps_the_corners() {
+        let source = [[0.0, 0.0], [4.0, 0.0], [4.0, 3.0], [0.0, 3.0]];
+        let target = [[1.0, 0.5], [3.0, 0.0], [4.0, 3.0], [0.0, 2.5]];
+
+        let h = homography(source, target).unwrap();
+
+        for (point, expected) in source.iter().zip(target.iter()) {
+            let [x, y] = apply(h, *point);
+            assert!((x - expected[0]).abs() < 1e-4, "{} != {}", x, expected[0]);
+            assert!((y - expected[1]).abs() < 1e-4, "{} != {}", y, expected[1]);
+        }
+    }
+
+    #[test]
+    fn homography_rejects_aligned_corners() {
+        let source = [[0.0, 0.0], [4.0, 0.0], [4.0, 3.0], [0.0, 3.0]];
+        let target = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [3.0, 0.0]];
+
+        assert!(homography(source, target).is_none());
+    }
+
+    #[test]
+    fn perspective_warp_keeps_the_image_with_its_own_corners() {
+        let image = gradient(4, 3);
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+        let warped = perspective_warp(&image, corners, Interpolation::Nearest).unwrap();
+
+        assert_eq!(warped.pixels, image.pixels);
+    }
+
+    #[test]
+    fn affine_warp_translates_the_pixels() {
+        let image = gradient(4, 3);
+
+        let warped = affine_warp(
+            &image,
+            [1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+            Interpolation::Nearest,
+        )
+        .unwrap();
+
+        for y in 0..3 {
+            assert_eq!(warped.pixels[y * 4], Color32::BLACK);
+            for x in 1..4 {
+                assert_eq!(warped.pixels[y * 4 + x], image.pixels[y * 4 + x - 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn affine_warp_rejects_singular_matrices() {
+        let image = gradient(4, 3);
+
+        let warped = affine_warp(
+            &image,
+            [1.0, 2.0, 0.0, 2.0, 4.0, 0.0],
+            Interpolation::Bilinear,
+        );
+
+        assert!(warped.is_err());
+    }
+
+    #[test]
+    fn border_index_modes() {
+        assert_eq!(border_index(-1, 4, BorderMode::Constant), None);
+        assert_eq!(border_index(-1, 4, BorderMode::Replicate), Some(0));
+        assert_eq!(border_index(5, 4, BorderMode::Replicate), Some(3));
+        assert_eq!(border_index(-1, 4, BorderMode::Reflect), Some(0));
+        assert_eq!(border_index(5, 4, BorderMode::Reflect), Some(2));
+        assert_eq!(border_index(-1, 4, BorderMode::Wrap), Some(3));
+        assert_eq!(border_index(0, 0, BorderMode::Wrap), None);
+    }
+}
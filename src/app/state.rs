@@ -1,10 +1,20 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use egui::ColorImage;
 use egui_extras::RetainedImage;
-use egui_node_graph::NodeId;
+use egui_node_graph::{NodeId, OutputId};
 
+use crate::app::components::display::image_compare::CompareOptions;
+use crate::app::components::display::image_histogram::HistogramOptions;
+use crate::app::components::display::image_viewer::ImageView;
+use crate::app::components::display::node_profiler::ProfilerOptions;
+use crate::app::components::graph::batch::Batch;
+use crate::app::components::graph::clipboard::SubGraph;
+use crate::app::components::graph::group::{GroupDefinition, GroupDialog};
 use crate::app::components::graph::node;
+use crate::app::history::History;
+use crate::app::math::statistics::{Channel, Statistics};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
@@ -15,16 +25,53 @@ pub struct AppState {
     #[serde(skip)] // opt-out serialization
     pub selected_node: SelectedNode,
 
+    #[serde(skip)] // opt-out serialization
+    pub history: History,
+
+    #[serde(skip)] // opt-out serialization
+    pub clipboard: SubGraph,
+
+    #[serde(skip)] // opt-out serialization
+    pub group_dialog: Option<GroupDialog>,
+
+    #[serde(skip)] // opt-out serialization
+    pub node_search: String,
+
+    /// Files of a sequence being processed
+    #[serde(skip)] // opt-out serialization
+    pub batch: Option<Batch>,
+
+    #[serde(skip)] // opt-out serialization
+    pub viewer: ImageView,
+
+    #[serde(skip)] // opt-out serialization
+    pub compare: Compare,
+
+    /// Output previewed for the nodes with several images
+    #[serde(skip)] // opt-out serialization
+    pub previewed_outputs: HashMap<NodeId, OutputId>,
+
     #[serde(skip)] // opt-out serialization
     pub first_loop: bool,
 
     pub auto_compute: bool,
 
+    /// Groups saved by the user, available in the side panel and the node finder
+    pub library: Vec<Arc<GroupDefinition>>,
+
     // Display
     pub d_settings: bool,
     pub d_about: bool,
+    pub d_nodes: bool,
+    pub d_inspector: bool,
+    pub d_viewer: bool,
+    pub d_profiler: bool,
     pub d_state: bool,
     pub o_pannel: OutputPanel,
+    pub o_histogram: HistogramOptions,
+    pub o_compare: CompareOptions,
+    pub o_overview: bool,
+    pub o_profiler: ProfilerOptions,
 }
 
 impl Default for AppState {
@@ -32,14 +79,31 @@ impl Default for AppState {
         Self {
             graph: node::EditorState::new(1.0, node::GraphState::default()),
             selected_node: SelectedNode::default(),
+            history: History::default(),
+            clipboard: SubGraph::default(),
+            group_dialog: None,
+            node_search: String::new(),
+            batch: None,
+            viewer: ImageView::default(),
+            compare: Compare::default(),
+            previewed_outputs: HashMap::new(),
             first_loop: true,
             auto_compute: true,
+            library: vec![],
 
             // Display
             d_settings: false,
             d_about: false,
+            d_nodes: false,
+            d_inspector: true,
+            d_viewer: false,
+            d_profiler: false,
             d_state: false,
             o_pannel: OutputPanel::default(),
+            o_histogram: HistogramOptions::default(),
+            o_compare: CompareOptions::default(),
+            o_overview: false,
+            o_profiler: ProfilerOptions::default(),
         }
     }
 }
@@ -47,7 +111,9 @@ impl Default for AppState {
 #[derive(serde::Deserialize, serde::Serialize, PartialEq, Eq, Debug)]
 pub enum OutputPanel {
     Image,
-    Spectrum,
+    #[serde(alias = "Spectrum")]
+    Histogram,
+    Compare,
 }
 
 impl Default for OutputPanel {
@@ -56,20 +122,40 @@ impl Default for OutputPanel {
     }
 }
 
+#[derive(Default)]
 pub struct SelectedNode {
     pub node_id: Option<NodeId>,
+    pub output_id: Option<OutputId>,
+    /// Evaluation of the graph the preview was computed for
+    pub evaluation: usize,
     pub color_image: Option<ColorImage>,
+    /// The previewed output is a slice, displayed as an image
+    pub slice: bool,
+    /// The previewed output is a floating point image, displayed in 8 bits
+    pub float: bool,
+    /// Format of the file the previewed image was decoded from
+    pub format: Option<&'static str>,
     pub retained_image: Option<RetainedImage>,
-    pub spectrum: Vec<HashMap<u8, usize>>,
+    pub statistics: Vec<(Channel, Statistics)>,
+    /// Image a crop node is edited on
+    pub source_image: Option<RetainedImage>,
 }
 
-impl Default for SelectedNode {
-    fn default() -> Self {
-        Self {
-            node_id: None,
-            color_image: None,
-            retained_image: None,
-            spectrum: vec![HashMap::default(); 3],
-        }
-    }
+/// Outputs pinned to be compared, with the images computed from them
+#[derive(Default)]
+pub struct Compare {
+    pub a: Option<OutputId>,
+    pub b: Option<OutputId>,
+    pub images: Option<ComparedImages>,
+}
+
+pub struct ComparedImages {
+    /// The pins, evaluation and gain the images were computed for
+    pub source: (OutputId, OutputId, usize, f32),
+    pub a: Arc<ColorImage>,
+    pub b: Arc<ColorImage>,
+    pub difference: ColorImage,
+    pub retained_a: RetainedImage,
+    pub retained_b: RetainedImage,
+    pub retained_difference: RetainedImage,
 }